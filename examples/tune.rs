@@ -0,0 +1,86 @@
+//! Picks matmul block sizes for this machine and runs a small blocked f64
+//! matmul with them
+//!
+//! Run with `cargo run --example tune`.
+
+use std::error::Error;
+use std::time::Instant;
+
+use matmul_utils::{detect, dtype_size, format_size, BlockSizes, MatrixShape, MR, NR};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut processor = detect()?;
+    // A VM or container may hide some caches; tile for safe sizes instead
+    processor.assume_defaults();
+
+    let level = processor.primary_level().ok_or("no cache level detected")?;
+    let bytes_per_elem = dtype_size("f64").ok_or("unknown dtype")?;
+    let blocks = level.recommended_blocks(bytes_per_elem);
+
+    println!("{}", processor.model_name.trim());
+    println!(
+        "L1 data {}, L2 {}, L3 {}",
+        format_size(level.l1_cache.effective_data_size()),
+        format_size(level.l2_cache),
+        format_size(level.l3_cache)
+    );
+    println!(
+        "f64 blocks: MC = {}, KC = {}, NC = {} (micro-kernel {}x{})",
+        blocks.mc, blocks.kc, blocks.nc, MR, NR
+    );
+
+    let shape = MatrixShape {
+        m: 384,
+        n: 384,
+        k: 384,
+    };
+    let blocks = blocks.fit_to(&shape);
+    let a: Vec<f64> = (0..shape.m * shape.k).map(|i| (i % 13) as f64).collect();
+    let b: Vec<f64> = (0..shape.k * shape.n).map(|i| (i % 7) as f64).collect();
+    let mut c = vec![0.0; shape.m * shape.n];
+
+    let start = Instant::now();
+    blocked_matmul(&shape, blocks, &a, &b, &mut c);
+    let seconds = start.elapsed().as_secs_f64();
+
+    // Spot-check one entry against the plain dot product
+    let (i, j) = (shape.m - 1, shape.n / 2);
+    let expected: f64 = (0..shape.k)
+        .map(|p| a[i * shape.k + p] * b[p * shape.n + j])
+        .sum();
+    assert_eq!(c[i * shape.n + j], expected);
+
+    let flops = 2.0 * (shape.m * shape.n * shape.k) as f64;
+    println!(
+        "{}x{}x{} matmul at those blocks: {:.2} GFLOPS",
+        shape.m,
+        shape.n,
+        shape.k,
+        flops / seconds / 1e9
+    );
+
+    Ok(())
+}
+
+// C += A * B over row-major matrices, one NC x KC panel of B and one MC x KC
+// block of A at a time, the loop order the block sizes are meant for
+fn blocked_matmul(shape: &MatrixShape, blocks: BlockSizes, a: &[f64], b: &[f64], c: &mut [f64]) {
+    for jc in (0..shape.n).step_by(blocks.nc) {
+        let nc = blocks.nc.min(shape.n - jc);
+        for pc in (0..shape.k).step_by(blocks.kc) {
+            let kc = blocks.kc.min(shape.k - pc);
+            for ic in (0..shape.m).step_by(blocks.mc) {
+                let mc = blocks.mc.min(shape.m - ic);
+
+                for i in ic..ic + mc {
+                    for p in pc..pc + kc {
+                        let a_ip = a[i * shape.k + p];
+                        for j in jc..jc + nc {
+                            c[i * shape.n + j] += a_ip * b[p * shape.n + j];
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,316 @@
+//! Microbenchmarks measuring what the OS doesn't report
+//!
+//! Only built with the `bench-detect` feature, since running them adds a
+//! noticeable delay to detection.
+
+use std::hint::black_box;
+use std::mem::size_of;
+use std::time::Instant;
+
+use crate::cache::LevelKey;
+use crate::tiling::{BlockSizes, MatrixShape, MR, NR};
+
+const CHASE_STEPS: usize = 1 << 21;
+const TRIAD_MIN_BYTES: usize = 32 << 20;
+const TRIAD_RUNS: usize = 5;
+
+/// Average latency of a dependent load over a working set of `bytes`
+///
+/// Chases a random single-cycle permutation with one slot per cache line, so
+/// neither the prefetcher nor out-of-order execution can hide the latency.
+pub fn pointer_chase_latency_ns(bytes: usize, line_size: usize) -> Option<f64> {
+    let stride = line_size.max(size_of::<usize>()) / size_of::<usize>();
+    let slots = bytes / (stride * size_of::<usize>());
+
+    if slots < 2 {
+        return None;
+    }
+
+    // Sattolo's algorithm yields a permutation made of a single cycle
+    let mut next: Vec<usize> = (0..slots).collect();
+    let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+    for i in (1..slots).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        next.swap(i, (state % i as u64) as usize);
+    }
+
+    let mut chain = vec![0usize; slots * stride];
+    for (slot, next_slot) in next.iter().enumerate() {
+        chain[slot * stride] = next_slot * stride;
+    }
+
+    // One lap to warm the caches, then the timed run
+    let mut index = 0;
+    for _ in 0..slots {
+        index = chain[index];
+    }
+
+    let start = Instant::now();
+    for _ in 0..CHASE_STEPS {
+        index = chain[index];
+    }
+    let elapsed = start.elapsed();
+    black_box(index);
+
+    Some(elapsed.as_nanos() as f64 / CHASE_STEPS as f64)
+}
+
+/// Sustained memory bandwidth in GB/s measured with the STREAM Triad kernel
+/// `a[i] = b[i] + s * c[i]` on one thread
+///
+/// Like STREAM, each of the three f64 arrays is at least four times `l3_bytes`
+/// (and never under 32 MB) so the caches can't serve it, the best of
+/// `TRIAD_RUNS` passes is kept and each pass counts 24 bytes per element:
+/// two reads and one write, ignoring write-allocate traffic.
+pub fn stream_triad_gbps(l3_bytes: usize) -> Option<f64> {
+    let elements = (4 * l3_bytes).max(TRIAD_MIN_BYTES) / size_of::<f64>();
+
+    let mut a = vec![0.0f64; elements];
+    let b = vec![1.0f64; elements];
+    let c = vec![2.0f64; elements];
+    let scalar = black_box(3.0);
+
+    // The first pass also faults the pages of `a` in
+    let mut best = f64::INFINITY;
+    for _ in 0..=TRIAD_RUNS {
+        let start = Instant::now();
+        for ((a, b), c) in a.iter_mut().zip(&b).zip(&c) {
+            *a = b + scalar * c;
+        }
+        black_box(&a);
+        best = best.min(start.elapsed().as_secs_f64());
+    }
+
+    if best <= 0.0 {
+        return None;
+    }
+
+    Some((3 * elements * size_of::<f64>()) as f64 / best / 1e9)
+}
+
+/// Throughput in GFLOPS of an f64 matmul of `shape` blocked with `blocks`,
+/// None for empty shapes or blocks
+pub fn blocked_matmul_gflops(shape: &MatrixShape, blocks: BlockSizes) -> Option<f64> {
+    let MatrixShape { m, n, k } = *shape;
+    if m == 0 || n == 0 || k == 0 || blocks.mc == 0 || blocks.kc == 0 || blocks.nc == 0 {
+        return None;
+    }
+
+    let a = vec![1.0; m * k];
+    let b = vec![1.0; k * n];
+    let mut c = vec![0.0; m * n];
+
+    let start = Instant::now();
+    blocked_matmul(shape, blocks, &a, &b, &mut c);
+    let elapsed = start.elapsed();
+    black_box(&c);
+
+    Some(2.0 * (m * n * k) as f64 / elapsed.as_secs_f64() / 1e9)
+}
+
+// C += A * B on row-major matrices, looping over blocks in BLIS order (NC
+// panels of B, KC slices of the shared dimension, MC blocks of A) down to an
+// MR x NR tile accumulated in registers
+fn blocked_matmul(shape: &MatrixShape, blocks: BlockSizes, a: &[f64], b: &[f64], c: &mut [f64]) {
+    let MatrixShape { m, n, k } = *shape;
+
+    for jc in (0..n).step_by(blocks.nc) {
+        let nc = blocks.nc.min(n - jc);
+
+        for pc in (0..k).step_by(blocks.kc) {
+            let kc = blocks.kc.min(k - pc);
+
+            for ic in (0..m).step_by(blocks.mc) {
+                let mc = blocks.mc.min(m - ic);
+
+                for jr in (jc..jc + nc).step_by(NR) {
+                    let nr = NR.min(jc + nc - jr);
+
+                    for ir in (ic..ic + mc).step_by(MR) {
+                        let mr = MR.min(ic + mc - ir);
+                        let mut tile = [[0.0; NR]; MR];
+
+                        for p in pc..pc + kc {
+                            for (i, row) in tile.iter_mut().enumerate().take(mr) {
+                                let a_ip = a[(ir + i) * k + p];
+                                for (j, acc) in row.iter_mut().enumerate().take(nr) {
+                                    *acc += a_ip * b[p * n + jr + j];
+                                }
+                            }
+                        }
+
+                        for (i, row) in tile.iter().enumerate().take(mr) {
+                            for (j, acc) in row.iter().enumerate().take(nr) {
+                                c[(ir + i) * n + jr + j] += acc;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Runs `bench` on the cores behind `key`, putting the thread back where it
+/// was afterwards; None when the thread couldn't be moved there
+///
+/// Linux pins the thread to `cpus`, the CPUs sharing the level's caches,
+/// and so does Windows for the CPUs of its first processor group. macOS has
+/// no affinity API, so the QoS class steers it instead: user interactive
+/// work runs on the performance cores and background work is kept on the
+/// efficiency cores.
+#[allow(unused_variables)]
+pub fn on_cores<T>(key: LevelKey, cpus: &[usize], bench: impl FnOnce() -> T) -> Option<T> {
+    #[cfg(target_os = "linux")]
+    return with_affinity(cpus, bench);
+
+    #[cfg(target_os = "macos")]
+    return with_qos_class(key, bench);
+
+    #[cfg(windows)]
+    return with_thread_affinity_mask(cpus, bench);
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+    None
+}
+
+// glibc's cpu_set_t, enough for 1024 CPUs
+#[cfg(target_os = "linux")]
+const CPU_SET_WORDS: usize = 16;
+
+#[cfg(target_os = "linux")]
+extern "C" {
+    fn sched_getaffinity(pid: i32, cpusetsize: usize, mask: *mut u64) -> i32;
+    fn sched_setaffinity(pid: i32, cpusetsize: usize, mask: *const u64) -> i32;
+}
+
+#[cfg(target_os = "linux")]
+use std::mem;
+
+#[cfg(target_os = "linux")]
+fn with_affinity<T>(cpus: &[usize], bench: impl FnOnce() -> T) -> Option<T> {
+    let mut mask = [0u64; CPU_SET_WORDS];
+    for &cpu in cpus.iter().filter(|&&cpu| cpu < CPU_SET_WORDS * 64) {
+        mask[cpu / 64] |= 1 << (cpu % 64);
+    }
+    if mask.iter().all(|&word| word == 0) {
+        return None;
+    }
+
+    let mut previous = [0u64; CPU_SET_WORDS];
+    // SAFETY: both masks are CPU_SET_WORDS words long and pid 0 is this thread
+    unsafe {
+        if sched_getaffinity(0, mem::size_of_val(&previous), previous.as_mut_ptr()) != 0
+            || sched_setaffinity(0, mem::size_of_val(&mask), mask.as_ptr()) != 0
+        {
+            return None;
+        }
+    }
+
+    let result = bench();
+    // SAFETY: as above
+    unsafe {
+        sched_setaffinity(0, mem::size_of_val(&previous), previous.as_ptr());
+    }
+
+    Some(result)
+}
+
+#[cfg(target_os = "macos")]
+const QOS_CLASS_USER_INTERACTIVE: u32 = 0x21;
+#[cfg(target_os = "macos")]
+const QOS_CLASS_BACKGROUND: u32 = 0x09;
+
+#[cfg(target_os = "macos")]
+extern "C" {
+    fn qos_class_self() -> u32;
+    fn pthread_set_qos_class_self_np(qos_class: u32, relative_priority: i32) -> i32;
+}
+
+#[cfg(target_os = "macos")]
+fn with_qos_class<T>(key: LevelKey, bench: impl FnOnce() -> T) -> Option<T> {
+    let qos_class = match key {
+        LevelKey::Efficiency(_) => QOS_CLASS_BACKGROUND,
+        _ => QOS_CLASS_USER_INTERACTIVE,
+    };
+
+    // SAFETY: both only change the calling thread's scheduling class
+    unsafe {
+        let previous = qos_class_self();
+        if pthread_set_qos_class_self_np(qos_class, 0) != 0 {
+            return None;
+        }
+
+        let result = bench();
+        pthread_set_qos_class_self_np(previous, 0);
+
+        Some(result)
+    }
+}
+
+#[cfg(windows)]
+#[link(name = "kernel32")]
+extern "system" {
+    fn GetCurrentThread() -> *mut std::ffi::c_void;
+    fn SetThreadAffinityMask(thread: *mut std::ffi::c_void, mask: usize) -> usize;
+}
+
+// A thread's mask covers the 64 CPUs of one processor group, CPUs past it
+// can't be named this way
+#[cfg(windows)]
+fn with_thread_affinity_mask<T>(cpus: &[usize], bench: impl FnOnce() -> T) -> Option<T> {
+    let mask = cpus
+        .iter()
+        .filter(|&&cpu| cpu < usize::BITS as usize)
+        .fold(0usize, |mask, &cpu| mask | 1 << cpu);
+    if mask == 0 {
+        return None;
+    }
+
+    // SAFETY: the pseudo handle of the current thread needs no closing, and
+    // only this thread's affinity changes
+    let previous = unsafe { SetThreadAffinityMask(GetCurrentThread(), mask) };
+    if previous == 0 {
+        return None;
+    }
+
+    let result = bench();
+    // SAFETY: as above
+    unsafe {
+        SetThreadAffinityMask(GetCurrentThread(), previous);
+    }
+
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocked_matmul_matches_the_naive_product() {
+        // Odd sizes so every loop ends on a partial block
+        let shape = MatrixShape { m: 13, n: 11, k: 9 };
+        let blocks = BlockSizes {
+            mc: 8,
+            kc: 4,
+            nc: 8,
+        };
+
+        let a: Vec<f64> = (0..shape.m * shape.k).map(|x| x as f64).collect();
+        let b: Vec<f64> = (0..shape.k * shape.n).map(|x| (x % 7) as f64).collect();
+        let mut c = vec![0.0; shape.m * shape.n];
+        blocked_matmul(&shape, blocks, &a, &b, &mut c);
+
+        for i in 0..shape.m {
+            for j in 0..shape.n {
+                let expected: f64 = (0..shape.k)
+                    .map(|p| a[i * shape.k + p] * b[p * shape.n + j])
+                    .sum();
+                assert_eq!(c[i * shape.n + j], expected);
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! What a WebAssembly build can tell JavaScript about the CPU
+//!
+//! Browsers expose `navigator.hardwareConcurrency` and nothing about caches,
+//! so every cache size here is one of the `assume_defaults` values, not a
+//! detection. The summary is plain JSON for a `#[wasm_bindgen]` wrapper (or
+//! any other glue) to hand to JS as a string.
+
+use alloc::string::ToString;
+use alloc::vec;
+
+use crate::cache::{LevelKey, ProcessorLevel};
+use crate::json::Json;
+
+/// Core count and f64 blocks for a WebAssembly matmul kernel, from the
+/// `navigator.hardwareConcurrency` JS passes in and assumed cache sizes
+pub fn browser_summary(hardware_concurrency: usize) -> Json {
+    let mut level = ProcessorLevel::new(LevelKey::Default);
+    level.assume_defaults();
+    let blocks = level.recommended_blocks(8);
+
+    Json::Object(vec![
+        (
+            "hardware_concurrency".to_string(),
+            Json::UInt(hardware_concurrency),
+        ),
+        ("caches_assumed".to_string(), Json::Bool(true)),
+        (
+            "l1_data_cache".to_string(),
+            Json::UInt(level.l1_cache.data_size),
+        ),
+        ("l2_cache".to_string(), Json::UInt(level.l2_cache)),
+        ("l3_cache".to_string(), Json::UInt(level.l3_cache)),
+        (
+            "line_size".to_string(),
+            Json::UInt(level.l1_cache.line_size),
+        ),
+        (
+            "blocks".to_string(),
+            Json::Object(vec![
+                ("mc".to_string(), Json::UInt(blocks.mc)),
+                ("kc".to_string(), Json::UInt(blocks.kc)),
+                ("nc".to_string(), Json::UInt(blocks.nc)),
+            ]),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_assumed_caches_for_js() {
+        let summary = browser_summary(8);
+
+        assert_eq!(summary.get("hardware_concurrency"), Some(&Json::UInt(8)));
+        assert_eq!(summary.get("caches_assumed"), Some(&Json::Bool(true)));
+        assert_eq!(summary.get("l2_cache"), Some(&Json::UInt(256 * 1024)));
+        assert_eq!(
+            summary.get("blocks").and_then(|blocks| blocks.get("kc")),
+            Some(&Json::UInt(256))
+        );
+    }
+}
@@ -0,0 +1,1104 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::json::Json;
+use crate::parse::format_cpu_list;
+use crate::size::format_size;
+
+/// How a cache size was obtained, for telling measured values from guesses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Sysfs,
+    Sysctl,
+    Cpuid,
+    Hwloc,
+    Wmic,
+    /// GetLogicalProcessorInformation on Windows on ARM
+    Win32,
+    Kstat,
+    /// A last resort like lscpu or the "cache size" of /proc/cpuinfo
+    Fallback,
+    /// Filled in by `assume_defaults`, not detected at all
+    Assumed,
+}
+
+impl Source {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Source::Sysfs => "sysfs",
+            Source::Sysctl => "sysctl",
+            Source::Cpuid => "cpuid",
+            Source::Hwloc => "hwloc",
+            Source::Wmic => "wmic",
+            Source::Win32 => "win32",
+            Source::Kstat => "kstat",
+            Source::Fallback => "fallback",
+            Source::Assumed => "assumed",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            Source::Sysfs,
+            Source::Sysctl,
+            Source::Cpuid,
+            Source::Hwloc,
+            Source::Wmic,
+            Source::Win32,
+            Source::Kstat,
+            Source::Fallback,
+            Source::Assumed,
+        ]
+        .into_iter()
+        .find(|source| source.as_str() == name)
+    }
+
+    fn to_json(source: Option<Self>) -> Json {
+        source.map_or(Json::Null, |source| Json::Str(source.as_str().to_string()))
+    }
+
+    fn from_json(json: Option<&Json>) -> Option<Self> {
+        Self::from_name(json?.as_str()?)
+    }
+}
+
+/// L1 cache information, split into instruction and data or unified
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CacheInfo {
+    pub instruction_size: usize,
+    pub data_size: usize,
+    pub unified_size: usize,
+    /// Line size of the data (or unified) L1 cache
+    pub line_size: usize,
+    /// Measured load latency, None unless benchmarked
+    pub latency_ns: Option<f64>,
+    /// Where the L1 sizes came from, None when none was detected
+    pub source: Option<Source>,
+}
+
+// How many times larger one split L1 may be than the other before the
+// formatter remarks on it; real designs stay within 2x (Apple's 192 KB
+// instruction and 128 KB data, Golden Cove's 32 KB and 48 KB)
+const L1_ASYMMETRY: usize = 4;
+
+impl CacheInfo {
+    /// The L1 that holds data for blocking: the unified cache on CPUs
+    /// without a split L1, the data cache otherwise
+    pub fn effective_data_size(&self) -> usize {
+        if self.unified_size > 0 {
+            self.unified_size
+        } else {
+            self.data_size
+        }
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::Object(vec![
+            (
+                "instruction_size".to_string(),
+                Json::UInt(self.instruction_size),
+            ),
+            ("data_size".to_string(), Json::UInt(self.data_size)),
+            ("unified_size".to_string(), Json::UInt(self.unified_size)),
+            ("line_size".to_string(), Json::UInt(self.line_size)),
+            (
+                "latency_ns".to_string(),
+                Json::from_option_f64(self.latency_ns),
+            ),
+            ("source".to_string(), Source::to_json(self.source)),
+        ])
+    }
+
+    /// Reads back what `to_json` wrote
+    pub fn from_json(json: &Json) -> Option<Self> {
+        let size = |key| json.get(key).and_then(Json::as_usize);
+
+        Some(Self {
+            instruction_size: size("instruction_size")?,
+            data_size: size("data_size")?,
+            unified_size: size("unified_size")?,
+            line_size: size("line_size")?,
+            latency_ns: json.get("latency_ns").and_then(Json::as_f64),
+            source: Source::from_json(json.get("source")),
+        })
+    }
+
+    pub fn format(&self) -> Vec<String> {
+        let mut result = Vec::new();
+
+        // Every size that was reported gets a line, even when a unified size
+        // and split sizes both came back; the latency was measured against
+        // the unified cache if there is one
+        if self.unified_size > 0 {
+            result.push(format!(
+                "L1 Cache (Unified): {}{}",
+                format_size(self.unified_size),
+                format_latency(self.latency_ns)
+            ));
+        }
+
+        if self.instruction_size > 0 {
+            result.push(format!(
+                "L1 Instruction Cache: {}",
+                format_size(self.instruction_size)
+            ));
+        }
+
+        if self.data_size > 0 {
+            let latency = if self.unified_size > 0 {
+                None
+            } else {
+                self.latency_ns
+            };
+
+            result.push(format!(
+                "L1 Data Cache: {}{}",
+                format_size(self.data_size),
+                format_latency(latency)
+            ));
+        }
+
+        if let Some(note) = self.asymmetry_note() {
+            result.push(note);
+        }
+
+        result
+    }
+
+    // A lone instruction size reads like a suspiciously small L1, and split
+    // sizes far apart usually mean one of them was misread
+    fn asymmetry_note(&self) -> Option<String> {
+        if self.unified_size > 0 || self.instruction_size == 0 {
+            return None;
+        }
+
+        if self.data_size == 0 {
+            return Some("Note: L1 Instruction-only detected; data cache size unknown".to_string());
+        }
+
+        let (smaller, larger) = if self.instruction_size < self.data_size {
+            (self.instruction_size, self.data_size)
+        } else {
+            (self.data_size, self.instruction_size)
+        };
+        (larger / smaller >= L1_ASYMMETRY).then(|| {
+            format!(
+                "Note: L1 instruction and data sizes differ {}x or more, one may be misreported",
+                L1_ASYMMETRY
+            )
+        })
+    }
+}
+
+/// Identifies a group of identical cores, ordered so performance cores come
+/// first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LevelKey {
+    Performance,
+    /// Apple's perflevel index, 1 and up
+    Efficiency(usize),
+    /// The only level on homogeneous CPUs
+    Default,
+    Group(usize),
+}
+
+impl LevelKey {
+    /// The key a level name was made from, the reverse of `Display`
+    pub fn from_name(name: &str) -> Option<Self> {
+        let index = |prefix: &str, suffix: &str| {
+            name.strip_prefix(prefix)?
+                .strip_suffix(suffix)?
+                .parse::<usize>()
+                .ok()
+        };
+
+        match name {
+            "Performance Cores" => Some(LevelKey::Performance),
+            "Default" => Some(LevelKey::Default),
+            _ => index("Efficiency Cores (Level ", ")")
+                .map(LevelKey::Efficiency)
+                .or_else(|| index("Core Group ", "").map(LevelKey::Group)),
+        }
+    }
+}
+
+impl fmt::Display for LevelKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LevelKey::Performance => write!(f, "Performance Cores"),
+            LevelKey::Efficiency(level) => write!(f, "Efficiency Cores (Level {})", level),
+            LevelKey::Default => write!(f, "Default"),
+            LevelKey::Group(group) => write!(f, "Core Group {}", group),
+        }
+    }
+}
+
+// Conservative sizes for caches that weren't detected, see `assume_defaults`
+const DEFAULT_L1_DATA: usize = 32 * 1024;
+const DEFAULT_L2: usize = 256 * 1024;
+const DEFAULT_L3: usize = 8 * 1024 * 1024;
+const DEFAULT_LINE_SIZE: usize = 64;
+
+/// One translation lookaside buffer
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TlbInfo {
+    pub level: usize,
+    /// "Data", "Instruction" or "Unified"
+    pub kind: String,
+    /// Page sizes it translates, "4K", "2M" and the like
+    pub page_sizes: Vec<String>,
+    pub entries: usize,
+    /// Equal to `entries` when fully associative, 0 when unknown
+    pub ways: usize,
+}
+
+impl TlbInfo {
+    pub fn to_json(&self) -> Json {
+        Json::Object(vec![
+            ("level".to_string(), Json::UInt(self.level)),
+            ("kind".to_string(), Json::Str(self.kind.clone())),
+            (
+                "page_sizes".to_string(),
+                Json::Array(
+                    self.page_sizes
+                        .iter()
+                        .map(|size| Json::Str(size.clone()))
+                        .collect(),
+                ),
+            ),
+            ("entries".to_string(), Json::UInt(self.entries)),
+            ("ways".to_string(), Json::UInt(self.ways)),
+        ])
+    }
+
+    /// Reads back what `to_json` wrote
+    pub fn from_json(json: &Json) -> Option<Self> {
+        let size = |key| json.get(key).and_then(Json::as_usize);
+
+        Some(Self {
+            level: size("level")?,
+            kind: json.get("kind")?.as_str()?.to_string(),
+            page_sizes: json
+                .get("page_sizes")?
+                .as_array()?
+                .iter()
+                .map(|size| Some(size.as_str()?.to_string()))
+                .collect::<Option<_>>()?,
+            entries: size("entries")?,
+            ways: size("ways")?,
+        })
+    }
+
+    /// "L1 Data TLB: 64 entries, 4-way, 4K pages"
+    pub fn format(&self) -> String {
+        let ways = match self.ways {
+            0 => String::new(),
+            ways if ways == self.entries => ", fully associative".to_string(),
+            ways => format!(", {}-way", ways),
+        };
+
+        format!(
+            "L{} {} TLB: {} entries{}, {} pages",
+            self.level,
+            self.kind,
+            self.entries,
+            ways,
+            self.page_sizes.join("/")
+        )
+    }
+}
+
+/// Cache hierarchy of one group of identical cores
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ProcessorLevel {
+    pub level_name: String,
+    pub l1_cache: CacheInfo,
+    pub l2_cache: usize,
+    pub l3_cache: usize,
+    pub l2_line_size: usize,
+    pub l3_line_size: usize,
+    /// Logical CPUs sharing each cache with the detected core
+    pub l2_shared_cpus: Vec<usize>,
+    pub l3_shared_cpus: Vec<usize>,
+    /// Physical cores (not SMT threads) sharing each cache, 0 when unknown
+    pub l2_shared_cores: usize,
+    pub l3_shared_cores: usize,
+    /// Logical CPUs of each cluster of cores sharing an L2, as on Apple
+    /// Silicon; empty when unknown
+    pub l2_clusters: Vec<Vec<usize>>,
+    pub l2_latency_ns: Option<f64>,
+    pub l3_latency_ns: Option<f64>,
+    /// L3 ways (and their bytes) the resctrl group we run in may allocate
+    /// into, None when cache allocation isn't in use
+    pub available_l3_ways: Option<usize>,
+    pub available_l3_bytes: Option<usize>,
+    pub l2_source: Option<Source>,
+    pub l3_source: Option<Source>,
+    /// Whether the caches are ECC or parity protected, None where the
+    /// platform doesn't say
+    pub ecc_protected: Option<bool>,
+    /// How the L1 data cache is indexed and tagged, "VIPT" or "PIPT", where
+    /// the architecture documents it; None otherwise
+    pub l1_indexing: Option<String>,
+    /// Ways of the L1 data (or unified), L2 and L3 caches, 0 when unknown
+    pub l1_ways: usize,
+    pub l2_ways: usize,
+    pub l3_ways: usize,
+    /// TLBs of the detected core, empty where they weren't described
+    pub tlbs: Vec<TlbInfo>,
+}
+
+impl ProcessorLevel {
+    pub fn new(key: LevelKey) -> Self {
+        Self {
+            level_name: key.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Fills undetected caches with conservative sizes (32 KB L1 data,
+    /// 256 KB L2, 8 MB L3, 64 B lines) so tiling always has something to
+    /// work with, returning the names of what was filled in
+    ///
+    /// An L2 of 8 MB or more is taken as the last level, as on Apple's
+    /// P-cores, and gets no L3.
+    pub fn assume_defaults(&mut self) -> Vec<&'static str> {
+        let mut assumed = Vec::new();
+
+        if self.l1_cache.effective_data_size() == 0 {
+            self.l1_cache.data_size = DEFAULT_L1_DATA;
+            self.l1_cache.source = Some(Source::Assumed);
+            assumed.push("L1 Data");
+        }
+        if self.l2_cache == 0 {
+            self.l2_cache = DEFAULT_L2;
+            self.l2_source = Some(Source::Assumed);
+            assumed.push("L2");
+        }
+        if self.l3_cache == 0 && self.l2_cache < DEFAULT_L3 {
+            self.l3_cache = DEFAULT_L3;
+            self.l3_source = Some(Source::Assumed);
+            assumed.push("L3");
+        }
+
+        let mut line_sizes = vec![&mut self.l1_cache.line_size, &mut self.l2_line_size];
+        if self.l3_cache > 0 {
+            line_sizes.push(&mut self.l3_line_size);
+        }
+        if line_sizes.iter().any(|line_size| **line_size == 0) {
+            for line_size in line_sizes.into_iter().filter(|line_size| **line_size == 0) {
+                *line_size = DEFAULT_LINE_SIZE;
+            }
+            assumed.push("line size");
+        }
+
+        assumed
+    }
+
+    /// Credits `source` with every detected cache that has none yet
+    pub fn set_sources(&mut self, source: Source) {
+        let l1_detected =
+            self.l1_cache.effective_data_size() > 0 || self.l1_cache.instruction_size > 0;
+
+        for (detected, slot) in [
+            (l1_detected, &mut self.l1_cache.source),
+            (self.l2_cache > 0, &mut self.l2_source),
+            (self.l3_cache > 0, &mut self.l3_source),
+        ] {
+            if detected && slot.is_none() {
+                *slot = Some(source);
+            }
+        }
+    }
+
+    /// One "L2: 1.00 MB (via sysfs)" line per detected cache
+    pub fn format_sources(&self) -> Vec<String> {
+        [
+            (
+                "L1 Data",
+                self.l1_cache.effective_data_size(),
+                self.l1_cache.source,
+            ),
+            ("L2", self.l2_cache, self.l2_source),
+            ("L3", self.l3_cache, self.l3_source),
+        ]
+        .into_iter()
+        .filter(|&(_, size, _)| size > 0)
+        .map(|(name, size, source)| {
+            format!(
+                "{}: {} (via {})",
+                name,
+                format_size(size),
+                source.map_or("unknown", |source| source.as_str())
+            )
+        })
+        .collect()
+    }
+
+    /// Every cache of the level added up, L1 instruction included
+    pub fn total_cache_bytes(&self) -> usize {
+        self.l1_cache.unified_size
+            + self.l1_cache.instruction_size
+            + self.l1_cache.data_size
+            + self.l2_cache
+            + self.l3_cache
+    }
+
+    /// Reads back what `to_json` wrote
+    pub fn from_json(json: &Json) -> Option<Self> {
+        let size = |key| json.get(key).and_then(Json::as_usize);
+        let cpus = |key| -> Option<Vec<usize>> {
+            json.get(key)?
+                .as_array()?
+                .iter()
+                .map(Json::as_usize)
+                .collect()
+        };
+
+        Some(Self {
+            level_name: json.get("name")?.as_str()?.to_string(),
+            l1_cache: CacheInfo::from_json(json.get("l1_cache")?)?,
+            l2_cache: size("l2_cache")?,
+            l3_cache: size("l3_cache")?,
+            l2_line_size: size("l2_line_size")?,
+            l3_line_size: size("l3_line_size")?,
+            l2_shared_cpus: cpus("l2_shared_cpus")?,
+            l3_shared_cpus: cpus("l3_shared_cpus")?,
+            l2_shared_cores: size("l2_shared_cores")?,
+            l3_shared_cores: size("l3_shared_cores")?,
+            l2_clusters: match json.get("l2_clusters") {
+                Some(clusters) => clusters
+                    .as_array()?
+                    .iter()
+                    .map(|cluster| cluster.as_array()?.iter().map(Json::as_usize).collect())
+                    .collect::<Option<_>>()?,
+                None => Vec::new(),
+            },
+            l2_latency_ns: json.get("l2_latency_ns").and_then(Json::as_f64),
+            l3_latency_ns: json.get("l3_latency_ns").and_then(Json::as_f64),
+            available_l3_ways: size("available_l3_ways"),
+            available_l3_bytes: size("available_l3_bytes"),
+            l2_source: Source::from_json(json.get("l2_source")),
+            l3_source: Source::from_json(json.get("l3_source")),
+            ecc_protected: json.get("ecc_protected").and_then(Json::as_bool),
+            l1_indexing: json
+                .get("l1_indexing")
+                .and_then(Json::as_str)
+                .map(str::to_string),
+            l1_ways: size("l1_ways").unwrap_or(0),
+            l2_ways: size("l2_ways").unwrap_or(0),
+            l3_ways: size("l3_ways").unwrap_or(0),
+            tlbs: match json.get("tlbs") {
+                Some(tlbs) => tlbs
+                    .as_array()?
+                    .iter()
+                    .map(TlbInfo::from_json)
+                    .collect::<Option<_>>()?,
+                None => Vec::new(),
+            },
+        })
+    }
+
+    /// Each cache's share of `total_cache_bytes`, "L2 is 6% of total cache"
+    pub fn format_stats(&self) -> Vec<String> {
+        let total = self.total_cache_bytes();
+        if total == 0 {
+            return Vec::new();
+        }
+
+        let l1 =
+            self.l1_cache.unified_size + self.l1_cache.instruction_size + self.l1_cache.data_size;
+        [("L1", l1), ("L2", self.l2_cache), ("L3", self.l3_cache)]
+            .into_iter()
+            .filter(|&(_, size)| size > 0)
+            .map(|(name, size)| {
+                // A big L3 leaves L1 well under a percent, which isn't zero
+                let percent = size as f64 / total as f64 * 100.0;
+                if percent < 1.0 {
+                    format!("{} is <1% of total cache", name)
+                } else {
+                    format!("{} is {:.0}% of total cache", name, percent)
+                }
+            })
+            .collect()
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::Object(vec![
+            ("name".to_string(), Json::Str(self.level_name.clone())),
+            ("l1_cache".to_string(), self.l1_cache.to_json()),
+            ("l2_cache".to_string(), Json::UInt(self.l2_cache)),
+            ("l2_line_size".to_string(), Json::UInt(self.l2_line_size)),
+            ("l3_cache".to_string(), Json::UInt(self.l3_cache)),
+            ("l3_line_size".to_string(), Json::UInt(self.l3_line_size)),
+            (
+                "l2_shared_cpus".to_string(),
+                cpus_to_json(&self.l2_shared_cpus),
+            ),
+            (
+                "l3_shared_cpus".to_string(),
+                cpus_to_json(&self.l3_shared_cpus),
+            ),
+            (
+                "l2_shared_cores".to_string(),
+                Json::UInt(self.l2_shared_cores),
+            ),
+            (
+                "l3_shared_cores".to_string(),
+                Json::UInt(self.l3_shared_cores),
+            ),
+            (
+                "l2_clusters".to_string(),
+                Json::Array(
+                    self.l2_clusters
+                        .iter()
+                        .map(|cpus| cpus_to_json(cpus))
+                        .collect(),
+                ),
+            ),
+            (
+                "l2_latency_ns".to_string(),
+                Json::from_option_f64(self.l2_latency_ns),
+            ),
+            (
+                "l3_latency_ns".to_string(),
+                Json::from_option_f64(self.l3_latency_ns),
+            ),
+            (
+                "available_l3_ways".to_string(),
+                self.available_l3_ways.map_or(Json::Null, Json::UInt),
+            ),
+            (
+                "available_l3_bytes".to_string(),
+                self.available_l3_bytes.map_or(Json::Null, Json::UInt),
+            ),
+            ("l2_source".to_string(), Source::to_json(self.l2_source)),
+            ("l3_source".to_string(), Source::to_json(self.l3_source)),
+            (
+                "ecc_protected".to_string(),
+                self.ecc_protected.map_or(Json::Null, Json::Bool),
+            ),
+            (
+                "l1_indexing".to_string(),
+                self.l1_indexing
+                    .as_deref()
+                    .map_or(Json::Null, |indexing| Json::Str(indexing.to_string())),
+            ),
+            ("l1_ways".to_string(), Json::UInt(self.l1_ways)),
+            ("l2_ways".to_string(), Json::UInt(self.l2_ways)),
+            ("l3_ways".to_string(), Json::UInt(self.l3_ways)),
+            (
+                "tlbs".to_string(),
+                Json::Array(self.tlbs.iter().map(TlbInfo::to_json).collect()),
+            ),
+        ])
+    }
+
+    pub fn format(&self) -> Vec<String> {
+        let mut result = Vec::new();
+
+        result.push(format!("\n{}", self.level_name));
+        result.push("-".repeat(self.level_name.len()));
+
+        // Add L1 cache info
+        result.extend(self.l1_cache.format());
+        if let Some(indexing) = &self.l1_indexing {
+            result.push(format!("L1 Data Indexing: {}", indexing));
+        }
+
+        // Add L2 and L3 cache info
+        let sharing = match self.l2_shared() {
+            Some(true) if !self.l2_clusters.is_empty() => {
+                format!(" (shared per cluster of {} cores)", self.l2_shared_cores)
+            }
+            Some(true) => format!(" (shared by {} cores)", self.l2_shared_cores),
+            Some(false) => " (per-core)".to_string(),
+            None => String::new(),
+        };
+        result.push(format!(
+            "L2 Cache: {}{}{}",
+            format_size(self.l2_cache),
+            sharing,
+            format_latency(self.l2_latency_ns)
+        ));
+
+        if !self.l2_clusters.is_empty() {
+            let clusters: Vec<String> = self
+                .l2_clusters
+                .iter()
+                .map(|cpus| format_cpu_list(cpus))
+                .collect();
+            result.push(format!("L2 Clusters: CPUs {}", clusters.join(", ")));
+        }
+
+        if self.l3_cache > 0 {
+            let per_core = match self.l3_per_core() {
+                Some(per_core) if self.l3_shared_cores > 1 => {
+                    format!(" ({}/core)", format_size(per_core))
+                }
+                _ => String::new(),
+            };
+
+            result.push(format!(
+                "L3 Cache: {}{}{}",
+                format_size(self.l3_cache),
+                per_core,
+                format_latency(self.l3_latency_ns)
+            ));
+
+            if let Some(available) = self.available_l3_bytes {
+                result.push(format!(
+                    "L3 available to this cgroup: {}",
+                    format_size(available)
+                ));
+            }
+        }
+
+        match self.ecc_protected {
+            Some(true) => result.push("Cache Protection: ECC/parity".to_string()),
+            Some(false) => result.push("Cache Protection: none".to_string()),
+            None => {}
+        }
+
+        let ways: Vec<String> = [
+            ("L1", self.l1_ways),
+            ("L2", self.l2_ways),
+            ("L3", self.l3_ways),
+        ]
+        .into_iter()
+        .filter(|&(_, ways)| ways > 0)
+        .map(|(name, ways)| format!("{} {}-way", name, ways))
+        .collect();
+        if !ways.is_empty() {
+            result.push(format!("Associativity: {}", ways.join(", ")));
+        }
+
+        result.extend(self.tlbs.iter().map(TlbInfo::format));
+
+        result
+    }
+
+    /// The hierarchy as a box-drawn tree, outermost cache first: the L3, each
+    /// L2 in it (one per `l2_clusters` entry, else the detected core's), and
+    /// the per-core L1s under every L2
+    pub fn format_tree(&self) -> Vec<String> {
+        let cpus = |cpus: &[usize]| {
+            if cpus.is_empty() {
+                String::new()
+            } else {
+                format!(" (CPUs {})", format_cpu_list(cpus))
+            }
+        };
+
+        let mut result = vec![self.level_name.clone()];
+        let mut indent = String::new();
+        if self.l3_cache > 0 {
+            result.push(format!(
+                "└── L3 {}{}",
+                format_size(self.l3_cache),
+                cpus(&self.l3_shared_cpus)
+            ));
+            indent.push_str("    ");
+        }
+
+        let l1 = if self.l1_cache.unified_size > 0 {
+            format!("L1 {}", format_size(self.l1_cache.unified_size))
+        } else {
+            format!(
+                "L1i {}, L1d {}",
+                format_size(self.l1_cache.instruction_size),
+                format_size(self.l1_cache.data_size)
+            )
+        };
+
+        let detected_core = [self.l2_shared_cpus.clone()];
+        let l2_groups = if self.l2_clusters.is_empty() {
+            &detected_core[..]
+        } else {
+            &self.l2_clusters[..]
+        };
+        for (i, group) in l2_groups.iter().enumerate() {
+            let (branch, stem) = if i + 1 == l2_groups.len() {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+
+            result.push(format!(
+                "{}{}L2 {}{}",
+                indent,
+                branch,
+                format_size(self.l2_cache),
+                cpus(group)
+            ));
+            result.push(format!("{}{}└── each core: {}", indent, stem, l1));
+        }
+
+        result
+    }
+
+    /// Whether the L2 is shared between cores (an Apple cluster) rather than
+    /// private to one, None when the topology is unknown
+    pub fn l2_shared(&self) -> Option<bool> {
+        if self.l2_cache == 0 || self.l2_shared_cores == 0 {
+            return None;
+        }
+
+        Some(self.l2_shared_cores > 1)
+    }
+
+    /// Share of the L3 each core can count on when all of them are busy
+    pub fn l3_per_core(&self) -> Option<usize> {
+        if self.l3_cache == 0 || self.l3_shared_cores == 0 {
+            return None;
+        }
+
+        Some(self.l3_cache / self.l3_shared_cores)
+    }
+
+    /// Some ARM parts use different coherency line sizes per level, which
+    /// breaks code assuming a single cache line constant
+    pub fn line_size_warning(&self) -> Option<String> {
+        let line_sizes: Vec<(&str, usize)> = [
+            ("L1", self.l1_cache.line_size),
+            ("L2", self.l2_line_size),
+            ("L3", self.l3_line_size),
+        ]
+        .into_iter()
+        .filter(|(_, line_size)| *line_size > 0)
+        .collect();
+
+        if line_sizes.windows(2).all(|pair| pair[0].1 == pair[1].1) {
+            return None;
+        }
+
+        let details: Vec<String> = line_sizes
+            .iter()
+            .map(|(name, line_size)| format!("{} line size {}B", name, line_size))
+            .collect();
+
+        Some(format!("Warning: {}", details.join(", ")))
+    }
+
+    /// L1 <= L2 <= L3 holds on every real CPU, so an inverted pair points at
+    /// a misread unit; None when the detected sizes are in order
+    pub fn hierarchy_warning(&self) -> Option<String> {
+        let details: Vec<String> = self
+            .inverted_pairs()
+            .iter()
+            .map(|((inner, inner_size), (outer, outer_size))| {
+                format!(
+                    "{} ({}) is larger than {} ({})",
+                    inner,
+                    format_size(*inner_size),
+                    outer,
+                    format_size(*outer_size)
+                )
+            })
+            .collect();
+
+        if details.is_empty() {
+            return None;
+        }
+
+        Some(format!("Warning: {}", details.join(", ")))
+    }
+
+    /// Zeroes the outer cache of every inverted pair, the one a unit misread
+    /// usually shrinks
+    pub fn drop_inverted_caches(&mut self) {
+        for (_, (outer, _)) in self.inverted_pairs() {
+            match outer {
+                "L2" => self.l2_cache = 0,
+                _ => self.l3_cache = 0,
+            }
+        }
+    }
+
+    // Adjacent detected levels where the inner one is the larger
+    fn inverted_pairs(&self) -> Vec<(SizedLevel, SizedLevel)> {
+        let sizes: Vec<SizedLevel> = [
+            ("L1", self.l1_cache.effective_data_size()),
+            ("L2", self.l2_cache),
+            ("L3", self.l3_cache),
+        ]
+        .into_iter()
+        .filter(|(_, size)| *size > 0)
+        .collect();
+
+        sizes
+            .windows(2)
+            .filter(|pair| pair[0].1 > pair[1].1)
+            .map(|pair| (pair[0], pair[1]))
+            .collect()
+    }
+}
+
+// A cache's name ("L2") and size in bytes
+type SizedLevel = (&'static str, usize);
+
+fn format_latency(latency_ns: Option<f64>) -> String {
+    match latency_ns {
+        Some(latency_ns) => format!(" (~{:.1} ns)", latency_ns),
+        None => String::new(),
+    }
+}
+
+fn cpus_to_json(cpus: &[usize]) -> Json {
+    Json::Array(cpus.iter().map(|cpu| Json::UInt(*cpu)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draws_each_l2_cluster_under_the_l3() {
+        let mut level = ProcessorLevel::new(LevelKey::Performance);
+        level.l1_cache.instruction_size = 192 * 1024;
+        level.l1_cache.data_size = 128 * 1024;
+        level.l2_cache = 16 * 1024 * 1024;
+        level.l3_cache = 24 * 1024 * 1024;
+        level.l3_shared_cpus = (0..8).collect();
+        level.l2_clusters = vec![vec![0, 1, 2, 3], vec![4, 5, 6, 7]];
+
+        assert_eq!(
+            level.format_tree(),
+            vec![
+                "Performance Cores",
+                "└── L3 24.00 MB (CPUs 0-7)",
+                "    ├── L2 16.00 MB (CPUs 0-3)",
+                "    │   └── each core: L1i 192.00 KB, L1d 128.00 KB",
+                "    └── L2 16.00 MB (CPUs 4-7)",
+                "        └── each core: L1i 192.00 KB, L1d 128.00 KB",
+            ]
+        );
+    }
+
+    #[test]
+    fn assumes_defaults_only_for_missing_caches() {
+        let mut level = ProcessorLevel::new(LevelKey::Default);
+        level.l1_cache.data_size = 48 * 1024;
+        level.l1_cache.line_size = 64;
+        level.l2_line_size = 64;
+
+        assert_eq!(level.assume_defaults(), vec!["L2", "L3", "line size"]);
+        assert_eq!(level.l1_cache.data_size, 48 * 1024);
+        assert_eq!(level.l2_cache, 256 * 1024);
+        assert_eq!(level.l3_cache, 8 * 1024 * 1024);
+        assert_eq!(level.l3_line_size, 64);
+        assert!(level.block_sizes(8).is_some());
+
+        assert!(level.assume_defaults().is_empty());
+
+        // An M1 P-level: 12 MB of L2 and nothing after it
+        let mut level = ProcessorLevel::new(LevelKey::Performance);
+        level.l1_cache.data_size = 128 * 1024;
+        level.l1_cache.line_size = 128;
+        level.l2_cache = 12 * 1024 * 1024;
+        level.l2_line_size = 128;
+
+        assert!(level.assume_defaults().is_empty());
+        assert_eq!(level.l3_cache, 0);
+        assert_eq!(level.l3_line_size, 0);
+        assert!(level.hierarchy_warning().is_none());
+    }
+
+    #[test]
+    fn reports_each_cache_share_of_the_total() {
+        let mut level = ProcessorLevel::new(LevelKey::Default);
+        assert!(level.format_stats().is_empty());
+
+        level.l1_cache.data_size = 32 * 1024;
+        level.l1_cache.instruction_size = 32 * 1024;
+        level.l2_cache = 448 * 1024;
+        level.l3_cache = 512 * 1024;
+
+        assert_eq!(level.total_cache_bytes(), 1024 * 1024);
+        assert_eq!(level.format_stats()[0], "L1 is 6% of total cache");
+
+        level.l3_cache = 105 * 1024 * 1024;
+        assert_eq!(level.format_stats()[0], "L1 is <1% of total cache");
+        level.l3_cache = 512 * 1024;
+        assert_eq!(
+            level.format_stats(),
+            vec![
+                "L1 is 6% of total cache",
+                "L2 is 44% of total cache",
+                "L3 is 50% of total cache"
+            ]
+        );
+    }
+
+    #[test]
+    fn levels_survive_a_json_round_trip() {
+        let mut level = ProcessorLevel::new(LevelKey::Efficiency(2));
+        level.l1_cache.data_size = 64 * 1024;
+        level.l1_cache.line_size = 64;
+        level.l1_cache.latency_ns = Some(1.25);
+        level.l2_cache = 4 * 1024 * 1024;
+        level.l2_shared_cpus = vec![4, 5, 6, 7];
+        level.l2_shared_cores = 4;
+        level.available_l3_ways = Some(4);
+        level.l2_ways = 16;
+        level.tlbs.push(TlbInfo {
+            level: 1,
+            kind: "Data".to_string(),
+            page_sizes: vec!["2M".to_string(), "4M".to_string()],
+            entries: 32,
+            ways: 32,
+        });
+
+        let json = Json::parse(&level.to_json().render()).unwrap();
+        assert_eq!(ProcessorLevel::from_json(&json), Some(level.clone()));
+        assert_eq!(
+            LevelKey::from_name(&level.level_name),
+            Some(LevelKey::Efficiency(2))
+        );
+
+        for key in [LevelKey::Performance, LevelKey::Default, LevelKey::Group(3)] {
+            assert_eq!(LevelKey::from_name(&key.to_string()), Some(key));
+        }
+        assert_eq!(LevelKey::from_name("Core Group x"), None);
+    }
+
+    #[test]
+    fn formats_associativity_and_tlbs() {
+        let mut level = ProcessorLevel::new(LevelKey::Default);
+        level.l1_ways = 12;
+        level.l2_ways = 10;
+        level.tlbs = vec![
+            TlbInfo {
+                level: 1,
+                kind: "Data".to_string(),
+                page_sizes: vec!["4K".to_string()],
+                entries: 64,
+                ways: 4,
+            },
+            TlbInfo {
+                level: 1,
+                kind: "Instruction".to_string(),
+                page_sizes: vec!["2M".to_string(), "4M".to_string()],
+                entries: 8,
+                ways: 8,
+            },
+        ];
+
+        let lines = level.format();
+        assert!(lines.contains(&"Associativity: L1 12-way, L2 10-way".to_string()));
+        assert!(lines.contains(&"L1 Data TLB: 64 entries, 4-way, 4K pages".to_string()));
+        assert!(lines.contains(
+            &"L1 Instruction TLB: 8 entries, fully associative, 2M/4M pages".to_string()
+        ));
+    }
+
+    #[test]
+    fn credits_each_cache_to_its_source() {
+        let mut level = ProcessorLevel::new(LevelKey::Default);
+        level.l1_cache.data_size = 48 * 1024;
+        level.l2_cache = 2 * 1024 * 1024;
+        level.l2_source = Some(Source::Fallback);
+        level.set_sources(Source::Sysfs);
+
+        assert_eq!(level.l1_cache.source, Some(Source::Sysfs));
+        assert_eq!(level.l2_source, Some(Source::Fallback));
+        assert_eq!(level.l3_source, None);
+
+        level.assume_defaults();
+        assert_eq!(
+            level.format_sources(),
+            vec![
+                "L1 Data: 48.00 KB (via sysfs)",
+                "L2: 2.00 MB (via fallback)",
+                "L3: 8.00 MB (via assumed)"
+            ]
+        );
+        assert_eq!(Source::from_name("assumed"), Some(Source::Assumed));
+    }
+
+    #[test]
+    fn warns_about_an_inverted_hierarchy() {
+        let mut level = ProcessorLevel::new(LevelKey::Default);
+        level.l1_cache.data_size = 48 * 1024;
+        level.l2_cache = 2 * 1024 * 1024;
+        level.l3_cache = 32 * 1024 * 1024;
+        assert_eq!(level.hierarchy_warning(), None);
+
+        // An L2 read as bytes instead of KB
+        level.l2_cache = 2048;
+        assert_eq!(
+            level.hierarchy_warning().as_deref(),
+            Some("Warning: L1 (48.00 KB) is larger than L2 (2.00 KB)")
+        );
+
+        level.drop_inverted_caches();
+        assert_eq!(level.l2_cache, 0);
+        assert_eq!(level.l3_cache, 32 * 1024 * 1024);
+        assert_eq!(level.hierarchy_warning(), None);
+    }
+
+    #[test]
+    fn effective_data_size_prefers_the_unified_l1() {
+        let split = CacheInfo {
+            instruction_size: 32 * 1024,
+            data_size: 48 * 1024,
+            ..Default::default()
+        };
+        assert_eq!(split.effective_data_size(), 48 * 1024);
+
+        let unified = CacheInfo {
+            unified_size: 64 * 1024,
+            ..Default::default()
+        };
+        assert_eq!(unified.effective_data_size(), 64 * 1024);
+    }
+
+    #[test]
+    fn formats_unified_and_split_l1_sizes_together() {
+        let cache = CacheInfo {
+            unified_size: 64 * 1024,
+            data_size: 32 * 1024,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            cache.format(),
+            vec![
+                "L1 Cache (Unified): 64.00 KB".to_string(),
+                "L1 Data Cache: 32.00 KB".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn notes_an_instruction_only_or_lopsided_l1() {
+        let instruction_only = CacheInfo {
+            instruction_size: 32 * 1024,
+            ..Default::default()
+        };
+        assert_eq!(
+            instruction_only.format(),
+            vec![
+                "L1 Instruction Cache: 32.00 KB".to_string(),
+                "Note: L1 Instruction-only detected; data cache size unknown".to_string(),
+            ]
+        );
+
+        let lopsided = CacheInfo {
+            instruction_size: 32 * 1024,
+            data_size: 2 * 1024 * 1024,
+            ..Default::default()
+        };
+        assert!(lopsided.format()[2].starts_with("Note: L1 instruction and data sizes differ 4x"));
+
+        let apple = CacheInfo {
+            instruction_size: 192 * 1024,
+            data_size: 128 * 1024,
+            ..Default::default()
+        };
+        assert_eq!(apple.format().len(), 2);
+    }
+}
@@ -0,0 +1,111 @@
+//! Detection replayed from tool output captured on another machine
+//!
+//! `sysfs_root` and `proc_root` replay Linux trees; this does the same for
+//! the text the other platforms' tools print, so a bug report carrying a
+//! `sysctl hw machdep.cpu` or `wmic` dump reproduces its detection anywhere.
+//! A pasted `/proc/cpuinfo` gets the same treatment.
+
+use std::collections::HashMap;
+use std::io;
+
+use crate::cache::{LevelKey, ProcessorLevel, Source};
+use crate::error::CacheError;
+use crate::processor::{
+    parse_cpuinfo_architecture, parse_kstat_cpu_info, parse_lscpu, parse_wmic_caches,
+    parse_wmic_name, ProcessorInfo, RawLog,
+};
+
+/// Output of one detection tool, as it printed it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capture<'a> {
+    /// `sysctl hw machdep.cpu`: "hw.l2cachesize: 4194304" lines, from
+    /// either an Apple Silicon or an Intel Mac
+    Sysctl(&'a str),
+    /// `wmic cpu get name,L1CacheSize,L2CacheSize,L3CacheSize /value`
+    Wmic(&'a str),
+    /// `kstat -p cpu_info`
+    Kstat(&'a str),
+    /// `lscpu`
+    Lscpu(&'a str),
+    /// `/proc/cpuinfo` of a Linux (or Hurd) machine: the model, x86
+    /// signature and memory encryption, and on x86 the last-level cache
+    Cpuinfo(&'a str),
+}
+
+impl ProcessorInfo {
+    /// Replaces the levels with what `capture` reports, the way detection
+    /// would have read it live
+    pub fn collect_from_capture(&mut self, capture: Capture<'_>) -> Result<&mut Self, CacheError> {
+        self.performance_levels.clear();
+
+        match capture {
+            Capture::Sysctl(output) => {
+                let values: HashMap<&str, &str> = output
+                    .lines()
+                    .filter_map(|line| line.split_once(':'))
+                    .map(|(key, value)| (key.trim(), value.trim()))
+                    .collect();
+                let sysctl = |key: &str, raw_log: &mut RawLog| match values.get(key) {
+                    Some(value) => {
+                        raw_log.record(key, value);
+                        Ok(value.to_string())
+                    }
+                    None => {
+                        raw_log.record(key, "<error: not captured>");
+                        Err(io::Error::new(io::ErrorKind::NotFound, key.to_string()))
+                    }
+                };
+
+                if let Some(model) = values.get("machdep.cpu.brand_string") {
+                    self.model_name = model.to_string();
+                }
+
+                // Only Apple Silicon has performance levels
+                if values.contains_key("hw.nperflevels") {
+                    self.collect_perflevel_cache_info(sysctl)?;
+                } else {
+                    self.collect_flat_sysctl_cache_info(sysctl)?;
+                }
+            }
+            Capture::Wmic(output) => {
+                self.raw_log.record("wmic", output);
+                if let Some(model) = parse_wmic_name(output) {
+                    self.model_name = model;
+                }
+                self.insert_captured_level(parse_wmic_caches, output, Source::Wmic);
+            }
+            Capture::Kstat(output) => {
+                self.raw_log.record("kstat -p cpu_info", output);
+                self.insert_captured_level(parse_kstat_cpu_info, output, Source::Kstat);
+            }
+            Capture::Lscpu(output) => {
+                self.raw_log.record("lscpu", output);
+                self.insert_captured_level(parse_lscpu, output, Source::Fallback);
+            }
+            Capture::Cpuinfo(output) => {
+                self.raw_log.record("/proc/cpuinfo", output);
+                if let Some(architecture) = parse_cpuinfo_architecture(output) {
+                    self.architecture = architecture.to_string();
+                }
+                self.apply_cpuinfo(output);
+                self.insert_cpuinfo_cache_level(output);
+            }
+        }
+
+        Ok(self)
+    }
+
+    fn insert_captured_level(
+        &mut self,
+        parse: fn(&str, &mut ProcessorLevel),
+        output: &str,
+        source: Source,
+    ) {
+        let mut proc_level = ProcessorLevel::new(LevelKey::Default);
+        parse(output, &mut proc_level);
+        proc_level.set_sources(source);
+
+        self.performance_levels
+            .insert(LevelKey::Default, proc_level);
+    }
+}
@@ -0,0 +1,98 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+const BOLD: &str = "\x1b[1m";
+const DIM_RED: &str = "\x1b[2;31m";
+const GREEN: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+const SIZE_UNITS: [&str; 4] = ["KB", "MB", "GB", "B"];
+
+/// Adds ANSI colors to a plain-text report: headers (lines underlined by a
+/// row of `-` or `=`) bold, "Not detected" dim red and sizes green
+pub fn colorize(report: &str) -> String {
+    let lines: Vec<&str> = report.lines().collect();
+    let mut result = Vec::with_capacity(lines.len());
+
+    for (i, line) in lines.iter().enumerate() {
+        let underlined = lines
+            .get(i + 1)
+            .is_some_and(|next| !next.is_empty() && next.chars().all(|c| c == '-' || c == '='));
+
+        if underlined && !line.is_empty() {
+            result.push(format!("{}{}{}", BOLD, line, RESET));
+        } else {
+            let line = line.replace("Not detected", &format!("{}Not detected{}", DIM_RED, RESET));
+            result.push(highlight_sizes(&line));
+        }
+    }
+
+    result.join("\n")
+}
+
+// Wraps every "<number> <unit>" that starts a word, like "32.00 KB", in green
+fn highlight_sizes(line: &str) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut rest = line;
+
+    while let Some(start) = rest.find(|c: char| c.is_ascii_digit()) {
+        let (before, candidate) = rest.split_at(start);
+        let number_len = candidate
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(candidate.len());
+
+        let starts_word = !before.ends_with(|c: char| c.is_ascii_alphanumeric() || c == '.');
+        let unit_len = candidate[number_len..].strip_prefix(' ').and_then(|after| {
+            SIZE_UNITS
+                .iter()
+                .find(|unit| {
+                    after.starts_with(*unit)
+                        && !after[unit.len()..].starts_with(|c: char| c.is_ascii_alphabetic())
+                })
+                .map(|unit| unit.len() + 1)
+        });
+
+        result.push_str(before);
+        match unit_len {
+            Some(unit_len) if starts_word => {
+                let (size, after) = candidate.split_at(number_len + unit_len);
+                result.push_str(&format!("{}{}{}", GREEN, size, RESET));
+                rest = after;
+            }
+            _ => {
+                let (number, after) = candidate.split_at(number_len);
+                result.push_str(number);
+                rest = after;
+            }
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_headers_sizes_and_missing_values() {
+        let report = "Default\n-------\nL1 Data Cache: 48.00 KB\nL2 Cache: Not detected\nL3 Cache: 32.00 MB (4.00 MB/core)";
+
+        assert_eq!(
+            colorize(report),
+            "\x1b[1mDefault\x1b[0m\n-------\n\
+             L1 Data Cache: \x1b[32m48.00 KB\x1b[0m\n\
+             L2 Cache: \x1b[2;31mNot detected\x1b[0m\n\
+             L3 Cache: \x1b[32m32.00 MB\x1b[0m (\x1b[32m4.00 MB\x1b[0m/core)"
+        );
+    }
+
+    #[test]
+    fn leaves_numbers_without_units_alone() {
+        let line = "Blocks: MC=96, KC=256, NC=4080";
+
+        assert_eq!(colorize(line), line);
+    }
+}
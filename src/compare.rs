@@ -0,0 +1,199 @@
+//! Cache differences between two machines, or two runs on one
+//!
+//! Fleet tools compare a stored report against the machine they run on to
+//! catch a hardware generation (or a kernel) that reports different caches.
+
+use std::fmt;
+
+use crate::cache::{LevelKey, ProcessorLevel};
+use crate::processor::ProcessorInfo;
+use crate::size::CacheSize;
+
+/// One cache size that differs, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldDiff {
+    pub field: &'static str,
+    pub a: usize,
+    pub b: usize,
+}
+
+impl FieldDiff {
+    /// How much larger `b` is, negative when it is smaller
+    pub fn delta(&self) -> i128 {
+        self.b as i128 - self.a as i128
+    }
+}
+
+impl fmt::Display for FieldDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.delta() < 0 { '-' } else { '+' };
+        write!(
+            f,
+            "{}: {} -> {} ({}{})",
+            self.field,
+            CacheSize(self.a),
+            CacheSize(self.b),
+            sign,
+            CacheSize(self.a.abs_diff(self.b))
+        )
+    }
+}
+
+/// How one level differs; a level only one side has is compared against
+/// an empty one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LevelDiff {
+    pub key: LevelKey,
+    pub level_name: String,
+    pub in_a: bool,
+    pub in_b: bool,
+    pub fields: Vec<FieldDiff>,
+}
+
+impl fmt::Display for LevelDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.in_a, self.in_b) {
+            (true, false) => write!(f, "{} (only in the first)", self.level_name)?,
+            (false, true) => write!(f, "{} (only in the second)", self.level_name)?,
+            _ => write!(f, "{}", self.level_name)?,
+        }
+
+        for field in &self.fields {
+            write!(f, "\n  {}", field)?;
+        }
+        Ok(())
+    }
+}
+
+// Every size a LevelDiff looks at
+fn sizes(level: &ProcessorLevel) -> [(&'static str, usize); 5] {
+    [
+        ("L1 Instruction", level.l1_cache.instruction_size),
+        ("L1 Data", level.l1_cache.effective_data_size()),
+        ("L2", level.l2_cache),
+        ("L3", level.l3_cache),
+        ("Line Size", level.l1_cache.line_size),
+    ]
+}
+
+/// The levels whose caches differ between `a` and `b`, in `LevelKey` order;
+/// empty when both report the same caches
+pub fn compare_levels(a: &ProcessorInfo, b: &ProcessorInfo) -> Vec<LevelDiff> {
+    let mut keys: Vec<LevelKey> = a
+        .performance_levels
+        .keys()
+        .chain(b.performance_levels.keys())
+        .copied()
+        .collect();
+    keys.sort();
+    keys.dedup();
+
+    keys.into_iter()
+        .filter_map(|key| {
+            let empty = ProcessorLevel::new(key);
+            let level_a = a.performance_levels.get(&key);
+            let level_b = b.performance_levels.get(&key);
+
+            let fields: Vec<FieldDiff> = sizes(level_a.unwrap_or(&empty))
+                .into_iter()
+                .zip(sizes(level_b.unwrap_or(&empty)))
+                .filter(|((_, size_a), (_, size_b))| size_a != size_b)
+                .map(|((field, a), (_, b))| FieldDiff { field, a, b })
+                .collect();
+
+            (!fields.is_empty()).then(|| LevelDiff {
+                key,
+                level_name: level_a.or(level_b).unwrap_or(&empty).level_name.clone(),
+                in_a: level_a.is_some(),
+                in_b: level_b.is_some(),
+                fields,
+            })
+        })
+        .collect()
+}
+
+/// The primary-level caches `alternate` reports differently from
+/// `detected`; sizes it doesn't report at all aren't disagreements
+pub fn cross_check(detected: &ProcessorInfo, alternate: &ProcessorInfo) -> Vec<FieldDiff> {
+    let (Some(detected), Some(alternate)) = (detected.primary_level(), alternate.primary_level())
+    else {
+        return Vec::new();
+    };
+
+    sizes(detected)
+        .into_iter()
+        .zip(sizes(alternate))
+        .filter(|&((_, a), (_, b))| b > 0 && a != b)
+        .map(|((field, a), (_, b))| FieldDiff { field, a, b })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine(levels: &[(LevelKey, usize, usize)]) -> ProcessorInfo {
+        let mut processor = ProcessorInfo::new();
+        for &(key, l1_data, l2) in levels {
+            let mut level = ProcessorLevel::new(key);
+            level.l1_cache.data_size = l1_data;
+            level.l2_cache = l2;
+            processor.performance_levels.insert(key, level);
+        }
+        processor
+    }
+
+    #[test]
+    fn reports_only_what_differs() {
+        let old = machine(&[(LevelKey::Performance, 64 * 1024, 1024 * 1024)]);
+        let new = machine(&[
+            (LevelKey::Performance, 64 * 1024, 2 * 1024 * 1024),
+            (LevelKey::Efficiency(1), 32 * 1024, 0),
+        ]);
+
+        assert!(compare_levels(&old, &old).is_empty());
+
+        let diffs = compare_levels(&old, &new);
+        assert_eq!(diffs.len(), 2);
+        assert_eq!(
+            diffs[0].fields,
+            [FieldDiff {
+                field: "L2",
+                a: 1024 * 1024,
+                b: 2 * 1024 * 1024
+            }]
+        );
+        assert_eq!(diffs[0].fields[0].delta(), 1024 * 1024);
+        assert_eq!(
+            diffs[0].to_string(),
+            "Performance Cores\n  L2: 1.00 MB -> 2.00 MB (+1.00 MB)"
+        );
+
+        assert!(!diffs[1].in_a && diffs[1].in_b);
+        assert_eq!(
+            diffs[1].to_string(),
+            "Efficiency Cores (Level 1) (only in the second)\n  L1 Data: 0 B -> 32.00 KB (+32.00 KB)"
+        );
+
+        let reversed = compare_levels(&new, &old);
+        assert_eq!(reversed[0].fields[0].delta(), -1024 * 1024);
+    }
+
+    #[test]
+    fn cross_checks_only_reported_sizes() {
+        let sysfs = machine(&[(LevelKey::Default, 48 * 1024, 2 * 1024 * 1024)]);
+        let agrees = machine(&[(LevelKey::Default, 0, 2 * 1024 * 1024)]);
+        let disagrees = machine(&[(LevelKey::Default, 32 * 1024, 2 * 1024 * 1024)]);
+
+        assert!(cross_check(&sysfs, &agrees).is_empty());
+        assert!(cross_check(&sysfs, &ProcessorInfo::new()).is_empty());
+        assert_eq!(
+            cross_check(&sysfs, &disagrees),
+            [FieldDiff {
+                field: "L1 Data",
+                a: 48 * 1024,
+                b: 32 * 1024
+            }]
+        );
+    }
+}
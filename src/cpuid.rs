@@ -0,0 +1,454 @@
+//! Cache detection straight from the CPUID instruction on x86
+//!
+//! Only built with the `cpuid` feature. It works without any OS support, so
+//! it also covers containers where sysfs isn't mounted.
+
+#[cfg(target_arch = "x86")]
+use core::arch::x86::{__cpuid, __cpuid_count};
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::{__cpuid, __cpuid_count};
+
+/// One cache as described by a deterministic cache parameters leaf
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuidCache {
+    pub level: usize,
+    pub kind: CpuidCacheKind,
+    pub size: usize,
+    pub line_size: usize,
+    pub ways: usize,
+    /// Upper bound on the logical CPUs sharing the cache
+    pub max_sharing: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuidCacheKind {
+    Data,
+    Instruction,
+    Unified,
+}
+
+// Intel puts the deterministic cache parameters at leaf 4, AMD at 0x8000001D
+const INTEL_CACHE_LEAF: u32 = 4;
+const AMD_CACHE_LEAF: u32 = 0x8000_001D;
+const AMD_TOPOLOGY_EXTENSIONS: u32 = 1 << 22;
+
+/// Caches reported by CPUID, empty when the CPU has no cache parameters leaf
+pub fn caches() -> Vec<CpuidCache> {
+    let vendor_leaf = __cpuid(0);
+    let extended_leaf = __cpuid(0x8000_0000);
+
+    let leaf = if vendor_leaf.eax >= INTEL_CACHE_LEAF && is_vendor(vendor_leaf.ebx, *b"Genu") {
+        INTEL_CACHE_LEAF
+    } else if extended_leaf.eax >= AMD_CACHE_LEAF
+        && __cpuid(0x8000_0001).ecx & AMD_TOPOLOGY_EXTENSIONS != 0
+    {
+        AMD_CACHE_LEAF
+    } else {
+        return Vec::new();
+    };
+
+    // Subleaves count up until one reports the null cache type
+    (0..16)
+        .map(|subleaf| __cpuid_count(leaf, subleaf))
+        .map_while(|result| decode(result.eax, result.ebx, result.ecx))
+        .collect()
+}
+
+fn is_vendor(ebx: u32, prefix: [u8; 4]) -> bool {
+    ebx == u32::from_le_bytes(prefix)
+}
+
+// Both vendors share the layout: EAX holds type, level and sharing, EBX line
+// size, partitions and ways, ECX the number of sets, all stored minus one
+fn decode(eax: u32, ebx: u32, ecx: u32) -> Option<CpuidCache> {
+    let kind = match eax & 0x1f {
+        1 => CpuidCacheKind::Data,
+        2 => CpuidCacheKind::Instruction,
+        3 => CpuidCacheKind::Unified,
+        _ => return None,
+    };
+
+    let line_size = (ebx & 0xfff) as usize + 1;
+    let partitions = ((ebx >> 12) & 0x3ff) as usize + 1;
+    let ways = (ebx >> 22) as usize + 1;
+    let sets = ecx as usize + 1;
+
+    Some(CpuidCache {
+        level: ((eax >> 5) & 0x7) as usize,
+        kind,
+        size: ways * partitions * line_size * sets,
+        line_size,
+        ways,
+        max_sharing: ((eax >> 14) & 0xfff) as usize + 1,
+    })
+}
+
+/// One TLB as described by Intel's leaf 0x18 or AMD's 0x80000005/6
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpuidTlb {
+    pub level: usize,
+    pub kind: CpuidCacheKind,
+    /// Page sizes it translates, "4K", "2M", "4M" and "1G"
+    pub page_sizes: Vec<&'static str>,
+    pub entries: usize,
+    /// Equal to `entries` when fully associative, 0 when unknown
+    pub ways: usize,
+}
+
+const INTEL_TLB_LEAF: u32 = 0x18;
+const AMD_L1_TLB_LEAF: u32 = 0x8000_0005;
+const AMD_L2_TLB_LEAF: u32 = 0x8000_0006;
+
+/// TLBs reported by CPUID, empty when the CPU has no TLB leaf
+pub fn tlbs() -> Vec<CpuidTlb> {
+    let vendor_leaf = __cpuid(0);
+
+    if is_vendor(vendor_leaf.ebx, *b"Genu") {
+        if vendor_leaf.eax < INTEL_TLB_LEAF {
+            return Vec::new();
+        }
+
+        // Subleaf 0 EAX is the highest subleaf, and unlike leaf 4 a null
+        // subleaf may sit between valid ones
+        let subleaves = __cpuid_count(INTEL_TLB_LEAF, 0).eax.min(64);
+        return (0..=subleaves)
+            .map(|subleaf| __cpuid_count(INTEL_TLB_LEAF, subleaf))
+            .filter_map(|result| decode_intel_tlb(result.ebx, result.ecx, result.edx))
+            .collect();
+    }
+
+    if __cpuid(0x8000_0000).eax < AMD_L2_TLB_LEAF {
+        return Vec::new();
+    }
+    let l1 = __cpuid(AMD_L1_TLB_LEAF);
+    let l2 = __cpuid(AMD_L2_TLB_LEAF);
+
+    decode_amd_tlbs([l1.eax, l1.ebx], [l2.eax, l2.ebx])
+}
+
+// EDX holds type and level, EBX the page sizes and ways, ECX the sets
+fn decode_intel_tlb(ebx: u32, ecx: u32, edx: u32) -> Option<CpuidTlb> {
+    let kind = match edx & 0x1f {
+        1 | 4 | 5 => CpuidCacheKind::Data,
+        2 => CpuidCacheKind::Instruction,
+        3 => CpuidCacheKind::Unified,
+        _ => return None,
+    };
+
+    let page_sizes = [(0, "4K"), (1, "2M"), (2, "4M"), (3, "1G")]
+        .into_iter()
+        .filter(|&(bit, _)| ebx & (1 << bit) != 0)
+        .map(|(_, name)| name)
+        .collect();
+    let ways = (ebx >> 16) as usize;
+    let entries = ways * ecx as usize;
+
+    Some(CpuidTlb {
+        level: ((edx >> 5) & 0x7) as usize,
+        kind,
+        page_sizes,
+        entries,
+        ways,
+    })
+}
+
+// AMD packs a data and an instruction TLB per register, EAX for the large
+// pages and EBX for 4K: 8 bits each of ways and entries in the L1 leaf, a
+// 4 bit encoded associativity and 12 bits of entries in the L2 one
+fn decode_amd_tlbs(l1: [u32; 2], l2: [u32; 2]) -> Vec<CpuidTlb> {
+    let mut tlbs = Vec::new();
+
+    for (level, registers) in [(1, l1), (2, l2)] {
+        for (register, page_sizes) in registers.into_iter().zip([vec!["2M", "4M"], vec!["4K"]]) {
+            for (kind, half) in [
+                (CpuidCacheKind::Data, register >> 16),
+                (CpuidCacheKind::Instruction, register & 0xffff),
+            ] {
+                let (entries, ways) = if level == 1 {
+                    let entries = (half & 0xff) as usize;
+                    let ways = match half >> 8 {
+                        0xff => entries,
+                        ways => ways as usize,
+                    };
+                    (entries, ways)
+                } else {
+                    let entries = (half & 0xfff) as usize;
+                    (entries, amd_l2_ways(half >> 12, entries))
+                };
+
+                if entries > 0 {
+                    tlbs.push(CpuidTlb {
+                        level,
+                        kind,
+                        page_sizes: page_sizes.clone(),
+                        entries,
+                        ways,
+                    });
+                }
+            }
+        }
+    }
+
+    tlbs
+}
+
+// The L2 leaf's associativity codes stand for ranges, taken at their
+// lower bound
+fn amd_l2_ways(code: u32, entries: usize) -> usize {
+    match code {
+        1 => 1,
+        2 => 2,
+        3 => 3,
+        4 => 4,
+        5 => 6,
+        6 => 8,
+        8 => 16,
+        0xa => 32,
+        0xb => 48,
+        0xc => 64,
+        0xd => 96,
+        0xe => 128,
+        0xf => entries,
+        _ => 0,
+    }
+}
+
+// AMD lists its encryption features at 0x8000001F, Intel flags TME in
+// leaf 7 ECX
+const AMD_ENCRYPTION_LEAF: u32 = 0x8000_001F;
+const INTEL_TME: u32 = 1 << 13;
+
+/// Memory encryption the CPU supports, "SME", "SEV", "TME" and so on
+///
+/// CPUID only tells what the hardware is capable of, not whether firmware or
+/// the kernel turned it on.
+pub fn memory_encryption() -> Vec<&'static str> {
+    let amd_eax = if __cpuid(0x8000_0000).eax >= AMD_ENCRYPTION_LEAF {
+        __cpuid(AMD_ENCRYPTION_LEAF).eax
+    } else {
+        0
+    };
+    let intel_ecx = if __cpuid(0).eax >= 7 {
+        __cpuid_count(7, 0).ecx
+    } else {
+        0
+    };
+
+    decode_encryption(amd_eax, intel_ecx)
+}
+
+fn decode_encryption(amd_eax: u32, intel_ecx: u32) -> Vec<&'static str> {
+    let mut features: Vec<_> = [(0, "SME"), (1, "SEV"), (3, "SEV-ES"), (4, "SEV-SNP")]
+        .into_iter()
+        .filter(|&(bit, _)| amd_eax & (1 << bit) != 0)
+        .map(|(_, name)| name)
+        .collect();
+
+    if intel_ecx & INTEL_TME != 0 {
+        features.push("TME");
+    }
+
+    features
+}
+
+// AMX is in leaf 7: TILE, BF16 and INT8 in subleaf 0 EDX, FP16 in subleaf
+// 1 EAX and COMPLEX in its EDX
+const AMX_SUBLEAF_0_EDX: [(u32, &str); 3] = [(24, "AMX-TILE"), (22, "AMX-BF16"), (25, "AMX-INT8")];
+const AMX_FP16: u32 = 1 << 21;
+const AMX_COMPLEX: u32 = 1 << 8;
+
+/// Matrix extensions the CPU supports, "AMX-TILE", "AMX-BF16" and so on
+///
+/// As with `memory_encryption`, this is the hardware's capability; the OS
+/// still has to enable the tile state before AMX instructions run.
+pub fn matrix_extensions() -> Vec<&'static str> {
+    if __cpuid(0).eax < 7 {
+        return Vec::new();
+    }
+
+    // Subleaf 0 EAX is the highest subleaf there is
+    let subleaf_0 = __cpuid_count(7, 0);
+    let (eax_1, edx_1) = if subleaf_0.eax >= 1 {
+        let subleaf_1 = __cpuid_count(7, 1);
+        (subleaf_1.eax, subleaf_1.edx)
+    } else {
+        (0, 0)
+    };
+
+    decode_matrix_extensions(subleaf_0.edx, eax_1, edx_1)
+}
+
+fn decode_matrix_extensions(edx_0: u32, eax_1: u32, edx_1: u32) -> Vec<&'static str> {
+    let mut extensions: Vec<_> = AMX_SUBLEAF_0_EDX
+        .into_iter()
+        .filter(|&(bit, _)| edx_0 & (1 << bit) != 0)
+        .map(|(_, name)| name)
+        .collect();
+
+    // The other AMX types only mean something with tiles to run on
+    if extensions.first() == Some(&"AMX-TILE") {
+        if eax_1 & AMX_FP16 != 0 {
+            extensions.push("AMX-FP16");
+        }
+        if edx_1 & AMX_COMPLEX != 0 {
+            extensions.push("AMX-COMPLEX");
+        }
+    }
+
+    extensions
+}
+
+// Leaf 1 ECX bit 31 is reserved for hypervisors to set, which then put
+// their vendor string in leaf 0x40000000
+const HYPERVISOR_PRESENT: u32 = 1 << 31;
+const HYPERVISOR_VENDOR_LEAF: u32 = 0x4000_0000;
+
+/// The hypervisor or emulator this runs under, "QEMU TCG", "KVM" and the
+/// like, None on bare metal
+pub fn hypervisor() -> Option<String> {
+    if __cpuid(1).ecx & HYPERVISOR_PRESENT == 0 {
+        return None;
+    }
+
+    let vendor = __cpuid(HYPERVISOR_VENDOR_LEAF);
+    let mut bytes = Vec::with_capacity(12);
+    for register in [vendor.ebx, vendor.ecx, vendor.edx] {
+        bytes.extend_from_slice(&register.to_le_bytes());
+    }
+
+    Some(hypervisor_name(&bytes))
+}
+
+fn hypervisor_name(vendor: &[u8]) -> String {
+    let vendor = String::from_utf8_lossy(vendor);
+    let vendor = vendor.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+
+    match vendor {
+        "TCGTCGTCGTCG" => "QEMU TCG",
+        "KVMKVMKVM" => "KVM",
+        "Microsoft Hv" => "Hyper-V",
+        "VMwareVMware" => "VMware",
+        "XenVMMXenVMM" => "Xen",
+        "VBoxVBoxVBox" => "VirtualBox",
+        "bhyve bhyve" => "bhyve",
+        "" => "unknown hypervisor",
+        other => other,
+    }
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_cache_parameter_leaves() {
+        // Leaf 4 subleaves 0 and 3 of a Skylake client core
+        assert_eq!(
+            decode(0x1c00_4121, 0x01c0_003f, 0x0000_003f),
+            Some(CpuidCache {
+                level: 1,
+                kind: CpuidCacheKind::Data,
+                size: 32 * 1024,
+                line_size: 64,
+                ways: 8,
+                max_sharing: 2,
+            })
+        );
+        assert_eq!(
+            decode(0x1c03_c163, 0x03c0_003f, 0x0000_1fff),
+            Some(CpuidCache {
+                level: 3,
+                kind: CpuidCacheKind::Unified,
+                size: 8 * 1024 * 1024,
+                line_size: 64,
+                ways: 16,
+                max_sharing: 16,
+            })
+        );
+        assert_eq!(decode(0, 0, 0), None);
+    }
+
+    #[test]
+    fn decodes_tlb_leaves() {
+        // Leaf 0x18 subleaf 1 of an Alder Lake P-core: a 4-way L1 DTLB
+        // of 64 4K entries
+        assert_eq!(
+            decode_intel_tlb(0x0004_0001, 0x0000_0010, 0x0000_0024),
+            Some(CpuidTlb {
+                level: 1,
+                kind: CpuidCacheKind::Data,
+                page_sizes: vec!["4K"],
+                entries: 64,
+                ways: 4,
+            })
+        );
+        assert_eq!(decode_intel_tlb(0, 0, 0), None);
+
+        // Zen 3 leaves 0x80000005 and 0x80000006 EAX and EBX
+        let tlbs = decode_amd_tlbs([0xff40_ff40, 0xff40_ff40], [0x6800_6200, 0x6800_6200]);
+        assert_eq!(tlbs.len(), 8);
+        assert_eq!(
+            tlbs[2],
+            CpuidTlb {
+                level: 1,
+                kind: CpuidCacheKind::Data,
+                page_sizes: vec!["4K"],
+                entries: 64,
+                ways: 64,
+            }
+        );
+        assert_eq!(
+            tlbs[6],
+            CpuidTlb {
+                level: 2,
+                kind: CpuidCacheKind::Data,
+                page_sizes: vec!["4K"],
+                entries: 2048,
+                ways: 8,
+            }
+        );
+        assert_eq!(amd_l2_ways(0xf, 64), 64);
+        assert_eq!(amd_l2_ways(0, 64), 0);
+    }
+
+    #[test]
+    fn decodes_memory_encryption_bits() {
+        // EPYC 7003 leaf 0x8000001F EAX
+        assert_eq!(
+            decode_encryption(0x0001_781f, 0),
+            vec!["SME", "SEV", "SEV-ES", "SEV-SNP"]
+        );
+        assert_eq!(decode_encryption(0, INTEL_TME), vec!["TME"]);
+        assert!(decode_encryption(0, 0).is_empty());
+    }
+
+    #[test]
+    fn decodes_amx_bits() {
+        // Sapphire Rapids has the first three, Granite Rapids adds the rest
+        let amx = 1 << 24 | 1 << 22 | 1 << 25;
+        assert_eq!(
+            decode_matrix_extensions(amx, 0, 0),
+            vec!["AMX-TILE", "AMX-BF16", "AMX-INT8"]
+        );
+        assert_eq!(
+            decode_matrix_extensions(amx, AMX_FP16, AMX_COMPLEX),
+            vec![
+                "AMX-TILE",
+                "AMX-BF16",
+                "AMX-INT8",
+                "AMX-FP16",
+                "AMX-COMPLEX"
+            ]
+        );
+        assert!(decode_matrix_extensions(0, AMX_FP16, 0).is_empty());
+    }
+
+    #[test]
+    fn names_hypervisor_vendors() {
+        assert_eq!(hypervisor_name(b"TCGTCGTCGTCG"), "QEMU TCG");
+        assert_eq!(hypervisor_name(b"KVMKVMKVM\0\0\0"), "KVM");
+        assert_eq!(hypervisor_name(b"ACRNACRNACRN"), "ACRNACRNACRN");
+        assert_eq!(hypervisor_name(&[0; 12]), "unknown hypervisor");
+    }
+}
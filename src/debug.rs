@@ -0,0 +1,94 @@
+//! A panic hook that shows how far detection got
+//!
+//! New detection backends tend to panic on some machine nobody on the team
+//! has. With the hook installed, `ProcessorInfo` snapshots itself at each
+//! step of detection, and a panic prints the last snapshot together with
+//! every raw value read after it.
+
+use std::cell::RefCell;
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::processor::ProcessorInfo;
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    // Detection runs on one thread, and the hook runs on the panicking one
+    static SNAPSHOT: RefCell<Option<String>> = const { RefCell::new(None) };
+    static READ_SINCE: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Installs the hook in front of the current one, which still runs after it
+pub fn install_debug_hook() {
+    if INSTALLED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if let Some(report) = debug_report() {
+            eprintln!("{}", report);
+        }
+        previous(info);
+    }));
+}
+
+pub(crate) fn checkpoint(processor: &ProcessorInfo) {
+    if !INSTALLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    SNAPSHOT.with(|snapshot| *snapshot.borrow_mut() = Some(format!("{:#?}", processor)));
+    READ_SINCE.with(|read| read.borrow_mut().clear());
+}
+
+// Called for every raw value, since a backend reads many of them between
+// two checkpoints
+pub(crate) fn record(source: &str, value: &str) {
+    if !INSTALLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    READ_SINCE.with(|read| read.borrow_mut().push(format!("{} = {:?}", source, value)));
+}
+
+fn debug_report() -> Option<String> {
+    let snapshot = SNAPSHOT.with(|snapshot| snapshot.borrow().clone())?;
+    let read_since = READ_SINCE.with(|read| read.borrow().clone());
+
+    let mut report = vec![
+        "ProcessorInfo collected before the panic:".to_string(),
+        snapshot,
+    ];
+    if !read_since.is_empty() {
+        report.push("Raw values read after that:".to_string());
+        report.extend(read_since);
+    }
+
+    Some(report.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_the_last_snapshot_and_later_reads() {
+        install_debug_hook();
+
+        let mut processor = ProcessorInfo::new();
+        processor.model_name = "Crashing CPU".to_string();
+        checkpoint(&processor);
+        record("/sys/devices/system/cpu/cpu0/cache/index0/size", "48K\n");
+
+        let report = debug_report().unwrap();
+        assert!(report.contains("model_name: \"Crashing CPU\""));
+        assert!(report.ends_with("/sys/devices/system/cpu/cpu0/cache/index0/size = \"48K\\n\""));
+
+        checkpoint(&processor);
+        assert!(!debug_report()
+            .unwrap()
+            .contains("Raw values read after that"));
+    }
+}
@@ -0,0 +1,46 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use crate::processor::ProcessorInfo;
+
+/// Why cache detection failed as a whole
+///
+/// Individual keys or files that can't be read are not errors, they are
+/// reported as 0 ("Not detected") instead.
+#[derive(Debug)]
+pub enum CacheError {
+    /// No detection path exists for this OS, named as in `std::env::consts::OS`
+    UnsupportedOs(String),
+    Io(io::Error),
+    /// `detect_with_timeout` ran out of time; holds what was collected so far
+    Timeout(Duration, Box<ProcessorInfo>),
+}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheError::UnsupportedOs(os) => write!(f, "unsupported operating system: {}", os),
+            CacheError::Io(err) => write!(f, "cache detection failed: {}", err),
+            CacheError::Timeout(timeout, _) => {
+                write!(f, "cache detection timed out after {:?}", timeout)
+            }
+        }
+    }
+}
+
+impl Error for CacheError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CacheError::UnsupportedOs(_) | CacheError::Timeout(..) => None,
+            CacheError::Io(err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for CacheError {
+    fn from(err: io::Error) -> Self {
+        CacheError::Io(err)
+    }
+}
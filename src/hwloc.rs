@@ -0,0 +1,229 @@
+//! Detection through hwloc, which knows the topology of far more platforms
+//! than the per-OS code
+//!
+//! Only built with the `hwloc` feature. The topology comes from the XML export
+//! of hwloc's `lstopo` tool rather than from linking libhwloc, so the build
+//! keeps needing nothing but a Rust toolchain.
+
+use std::io;
+use std::process::Command;
+
+use crate::cache::{LevelKey, ProcessorLevel};
+use crate::parse::parse_cpu_mask;
+use crate::processor::check_subprocess;
+
+/// Programs tried in order; `-` sends the export to stdout
+pub const LSTOPO_PROGRAMS: [&str; 2] = ["lstopo-no-graphics", "lstopo"];
+pub const LSTOPO_ARGS: [&str; 3] = ["--of", "xml", "-"];
+
+/// Runs lstopo and returns its XML export of the machine topology
+pub fn lstopo_xml() -> io::Result<String> {
+    check_subprocess("lstopo")?;
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, "lstopo not found");
+
+    for program in LSTOPO_PROGRAMS {
+        match Command::new(program).args(LSTOPO_ARGS).output() {
+            Ok(output) if output.status.success() => {
+                return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+            }
+            Ok(output) => {
+                last_error = io::Error::other(format!("{} exited with {}", program, output.status));
+            }
+            Err(err) => last_error = err,
+        }
+    }
+
+    Err(last_error)
+}
+
+/// Caches above the first PU of an lstopo XML export, None when the export
+/// has no processing unit or no caches
+pub fn parse_lstopo_xml(xml: &str) -> Option<ProcessorLevel> {
+    let mut proc_level = ProcessorLevel::new(LevelKey::Default);
+
+    // Open <object> tags from the root down to the current one
+    let mut ancestors: Vec<&str> = Vec::new();
+    let mut found_pu = false;
+    let mut l3_depth = None;
+    let mut l3_cores = 0;
+
+    for tag in xml.split('<').filter_map(|chunk| chunk.split('>').next()) {
+        if tag.starts_with("/object") {
+            ancestors.pop();
+
+            // Done once the walk leaves the L3 of the first PU
+            if l3_depth.is_some_and(|depth| ancestors.len() <= depth) {
+                break;
+            }
+            continue;
+        }
+
+        if !tag.starts_with("object ") {
+            continue;
+        }
+
+        let object_type = attribute(tag, "type").unwrap_or_default();
+
+        if found_pu {
+            if object_type == "Core" {
+                l3_cores += 1;
+            }
+        } else if object_type == "PU" {
+            found_pu = true;
+
+            // Caches are listed outermost first, the L3 is the last we need
+            for (depth, ancestor) in ancestors.iter().enumerate() {
+                if record_cache(&mut proc_level, ancestor) == Some(3) {
+                    l3_depth = Some(depth);
+                }
+            }
+
+            l3_cores = ancestors
+                .iter()
+                .skip(l3_depth.map_or(ancestors.len(), |depth| depth + 1))
+                .filter(|ancestor| attribute(ancestor, "type") == Some("Core"))
+                .count();
+
+            if l3_depth.is_none() {
+                break;
+            }
+        }
+
+        if !tag.ends_with('/') {
+            ancestors.push(tag);
+        }
+    }
+
+    if !found_pu || proc_level.l2_cache == 0 && proc_level.l1_cache.data_size == 0 {
+        return None;
+    }
+
+    if proc_level.l3_cache > 0 {
+        proc_level.l3_shared_cores = if l3_cores > 0 {
+            l3_cores
+        } else {
+            proc_level.l3_shared_cpus.len()
+        };
+    }
+
+    Some(proc_level)
+}
+
+// Stores a cache object into `proc_level`, returning its level
+fn record_cache(proc_level: &mut ProcessorLevel, tag: &str) -> Option<usize> {
+    // hwloc 2 has L1Cache, L1iCache, ... while 1.x only says "Cache";
+    // MemCache is a memory-side cache, not part of the CPU hierarchy
+    let object_type = attribute(tag, "type")?;
+    if !object_type.ends_with("Cache") || object_type == "MemCache" {
+        return None;
+    }
+
+    let level = attribute(tag, "depth")?.parse::<usize>().ok()?;
+    let size = attribute(tag, "cache_size")?.parse::<usize>().ok()?;
+    let line_size = attribute(tag, "cache_linesize")
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(0);
+    let shared_cpus = attribute(tag, "cpuset")
+        .map(parse_cpuset)
+        .unwrap_or_default();
+
+    // 0 unified, 1 data, 2 instruction
+    match (level, attribute(tag, "cache_type").unwrap_or("0")) {
+        (1, "1") => {
+            proc_level.l1_cache.data_size = size;
+            proc_level.l1_cache.line_size = line_size;
+        }
+        (1, "2") => proc_level.l1_cache.instruction_size = size,
+        (1, _) => {
+            proc_level.l1_cache.unified_size = size;
+            proc_level.l1_cache.line_size = line_size;
+        }
+        (2, _) => {
+            proc_level.l2_cache = size;
+            proc_level.l2_line_size = line_size;
+            proc_level.l2_shared_cpus = shared_cpus;
+        }
+        (3, _) => {
+            proc_level.l3_cache = size;
+            proc_level.l3_line_size = line_size;
+            proc_level.l3_shared_cpus = shared_cpus;
+        }
+        _ => {}
+    }
+
+    Some(level)
+}
+
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let start = tag.find(&format!(" {}=\"", name))? + name.len() + 3;
+    let length = tag[start..].find('"')?;
+
+    Some(&tag[start..start + length])
+}
+
+// "0x00000001,0x0000000f" -> the same groups parse_cpu_mask reads from sysfs
+fn parse_cpuset(cpuset: &str) -> Vec<usize> {
+    let groups: Vec<&str> = cpuset
+        .split(',')
+        .map(|group| group.trim_start_matches("0x"))
+        .collect();
+
+    parse_cpu_mask(&groups.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_caches_above_the_first_pu() {
+        // Trimmed `lstopo --of xml` of a 2-core, 4-thread machine
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<topology version="2.0">
+  <object type="Machine" os_index="0" cpuset="0x0000000f">
+    <object type="Package" os_index="0" cpuset="0x0000000f">
+      <object type="L3Cache" cpuset="0x0000000f" cache_size="8388608" depth="3" cache_linesize="64" cache_associativity="16" cache_type="0">
+        <object type="L2Cache" cpuset="0x00000003" cache_size="1048576" depth="2" cache_linesize="64" cache_associativity="8" cache_type="0">
+          <object type="L1Cache" cpuset="0x00000003" cache_size="49152" depth="1" cache_linesize="64" cache_associativity="12" cache_type="1">
+            <object type="L1iCache" cpuset="0x00000003" cache_size="32768" depth="1" cache_linesize="64" cache_associativity="8" cache_type="2">
+              <object type="Core" os_index="0" cpuset="0x00000003">
+                <object type="PU" os_index="0" cpuset="0x00000001"/>
+                <object type="PU" os_index="1" cpuset="0x00000002"/>
+              </object>
+            </object>
+          </object>
+        </object>
+        <object type="L2Cache" cpuset="0x0000000c" cache_size="1048576" depth="2" cache_linesize="64" cache_associativity="8" cache_type="0">
+          <object type="L1Cache" cpuset="0x0000000c" cache_size="49152" depth="1" cache_linesize="64" cache_associativity="12" cache_type="1">
+            <object type="L1iCache" cpuset="0x0000000c" cache_size="32768" depth="1" cache_linesize="64" cache_associativity="8" cache_type="2">
+              <object type="Core" os_index="1" cpuset="0x0000000c">
+                <object type="PU" os_index="2" cpuset="0x00000004"/>
+                <object type="PU" os_index="3" cpuset="0x00000008"/>
+              </object>
+            </object>
+          </object>
+        </object>
+      </object>
+    </object>
+  </object>
+</topology>"#;
+
+        let level = parse_lstopo_xml(xml).unwrap();
+
+        assert_eq!(level.l1_cache.data_size, 48 * 1024);
+        assert_eq!(level.l1_cache.instruction_size, 32 * 1024);
+        assert_eq!(level.l1_cache.line_size, 64);
+        assert_eq!(level.l2_cache, 1024 * 1024);
+        assert_eq!(level.l2_shared_cpus, vec![0, 1]);
+        assert_eq!(level.l3_cache, 8 * 1024 * 1024);
+        assert_eq!(level.l3_shared_cpus, vec![0, 1, 2, 3]);
+        assert_eq!(level.l3_shared_cores, 2);
+    }
+
+    #[test]
+    fn rejects_exports_without_caches() {
+        let xml = r#"<object type="Machine"><object type="PU" os_index="0"/></object>"#;
+
+        assert!(parse_lstopo_xml(xml).is_none());
+    }
+}
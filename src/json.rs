@@ -0,0 +1,366 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::iter::Peekable;
+use core::str::Chars;
+
+/// Version of the `--json` layout, bumped on every breaking change to it,
+/// reordering its fields or levels included
+pub const JSON_SCHEMA_VERSION: usize = 1;
+
+/// Minimal JSON tree, enough for the `--json` output without pulling in serde
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    UInt(usize),
+    Float(f64),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn from_option_f64(value: Option<f64>) -> Self {
+        value.map_or(Json::Null, Json::Float)
+    }
+
+    pub fn render(&self) -> String {
+        match self {
+            Json::Null => "null".to_string(),
+            Json::Bool(value) => value.to_string(),
+            Json::UInt(value) => value.to_string(),
+            // JSON has no NaN or infinity
+            Json::Float(value) if value.is_finite() => value.to_string(),
+            Json::Float(_) => "null".to_string(),
+            Json::Str(value) => json_escape(value),
+            Json::Array(items) => {
+                let items: Vec<String> = items.iter().map(Json::render).collect();
+                format!("[{}]", items.join(","))
+            }
+            Json::Object(fields) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(key, value)| format!("{}:{}", json_escape(key), value.render()))
+                    .collect();
+                format!("{{{}}}", fields.join(","))
+            }
+        }
+    }
+
+    /// Like `render`, but one value per line indented by two spaces, for
+    /// humans reading the output
+    pub fn render_pretty(&self) -> String {
+        let mut result = String::new();
+        self.write_pretty(0, &mut result);
+        result
+    }
+
+    fn write_pretty(&self, depth: usize, result: &mut String) {
+        let indent = "  ".repeat(depth + 1);
+
+        match self {
+            Json::Array(items) if !items.is_empty() => {
+                result.push_str("[\n");
+                for (i, item) in items.iter().enumerate() {
+                    result.push_str(&indent);
+                    item.write_pretty(depth + 1, result);
+                    result.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+                }
+                result.push_str(&"  ".repeat(depth));
+                result.push(']');
+            }
+            Json::Object(fields) if !fields.is_empty() => {
+                result.push_str("{\n");
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    result.push_str(&indent);
+                    result.push_str(&json_escape(key));
+                    result.push_str(": ");
+                    value.write_pretty(depth + 1, result);
+                    result.push_str(if i + 1 < fields.len() { ",\n" } else { "\n" });
+                }
+                result.push_str(&"  ".repeat(depth));
+                result.push('}');
+            }
+            value => result.push_str(&value.render()),
+        }
+    }
+
+    /// Reads a JSON document, None when it is malformed
+    ///
+    /// Numbers without a sign, fraction or exponent become `UInt`, every
+    /// other number `Float`, matching what `render` writes.
+    pub fn parse(text: &str) -> Option<Json> {
+        let mut parser = Parser {
+            chars: text.chars().peekable(),
+        };
+
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        parser.chars.peek().is_none().then_some(value)
+    }
+
+    /// Field of an object, None for missing fields and non-objects
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_usize(&self) -> Option<usize> {
+        match self {
+            Json::UInt(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Floats and integers alike, since `render` writes 2.0 as 2
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::UInt(value) => Some(*value as f64),
+            Json::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::Str(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+// Recursive descent over the characters of a document
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, expected: char) -> Option<()> {
+        self.skip_whitespace();
+        self.chars.next_if_eq(&expected).map(|_| ())
+    }
+
+    fn value(&mut self) -> Option<Json> {
+        self.skip_whitespace();
+
+        match *self.chars.peek()? {
+            'n' => self.literal("null", Json::Null),
+            't' => self.literal("true", Json::Bool(true)),
+            'f' => self.literal("false", Json::Bool(false)),
+            '"' => self.string().map(Json::Str),
+            '[' => self.array(),
+            '{' => self.object(),
+            _ => self.number(),
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> Option<Json> {
+        for expected in word.chars() {
+            self.chars.next_if_eq(&expected)?;
+        }
+        Some(value)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.expect('"')?;
+        let mut result = String::new();
+
+        loop {
+            match self.chars.next()? {
+                '"' => return Some(result),
+                '\\' => result.push(match self.chars.next()? {
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    'b' => '\u{8}',
+                    'f' => '\u{c}',
+                    'u' => {
+                        let digits: String = (0..4).filter_map(|_| self.chars.next()).collect();
+                        char::from_u32(u32::from_str_radix(&digits, 16).ok()?)?
+                    }
+                    c => c,
+                }),
+                c => result.push(c),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Option<Json> {
+        let mut digits = String::new();
+        while let Some(c) = self
+            .chars
+            .next_if(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+        {
+            digits.push(c);
+        }
+
+        if digits.bytes().all(|b| b.is_ascii_digit()) {
+            digits.parse().ok().map(Json::UInt)
+        } else {
+            digits.parse().ok().map(Json::Float)
+        }
+    }
+
+    fn array(&mut self) -> Option<Json> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+
+        if self.expect(']').is_some() {
+            return Some(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            if self.expect(']').is_some() {
+                return Some(Json::Array(items));
+            }
+            self.expect(',')?;
+        }
+    }
+
+    fn object(&mut self) -> Option<Json> {
+        self.expect('{')?;
+        let mut fields = Vec::new();
+
+        if self.expect('}').is_some() {
+            return Some(Json::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(':')?;
+            fields.push((key, self.value()?));
+
+            if self.expect('}').is_some() {
+                return Some(Json::Object(fields));
+            }
+            self.expect(',')?;
+        }
+    }
+}
+
+fn json_escape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len() + 2);
+
+    result.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    // Drops the whitespace between tokens, leaving string contents alone
+    fn strip_whitespace(json: &str) -> String {
+        let mut result = String::new();
+        let mut in_string = false;
+        let mut escaped = false;
+
+        for c in json.chars() {
+            if in_string {
+                in_string = escaped || c != '"';
+                escaped = !escaped && c == '\\';
+            } else if c == '"' {
+                in_string = true;
+            } else if c.is_whitespace() {
+                continue;
+            }
+            result.push(c);
+        }
+
+        result
+    }
+
+    #[test]
+    fn pretty_and_compact_forms_hold_the_same_value() {
+        let value = Json::Object(vec![
+            ("name".to_string(), Json::Str("Core \"A\" 1".to_string())),
+            ("empty".to_string(), Json::Array(vec![])),
+            (
+                "levels".to_string(),
+                Json::Array(vec![Json::Object(vec![
+                    ("size".to_string(), Json::UInt(32768)),
+                    ("latency".to_string(), Json::Null),
+                ])]),
+            ),
+        ]);
+
+        assert_eq!(
+            value.render_pretty(),
+            "{\n  \"name\": \"Core \\\"A\\\" 1\",\n  \"empty\": [],\n  \"levels\": [\n    {\n      \"size\": 32768,\n      \"latency\": null\n    }\n  ]\n}"
+        );
+        assert_eq!(strip_whitespace(&value.render_pretty()), value.render());
+    }
+
+    #[test]
+    fn parses_what_it_renders() {
+        let value = Json::Object(vec![
+            (
+                "name".to_string(),
+                Json::Str("Core \"A\"\n\u{1}".to_string()),
+            ),
+            ("ok".to_string(), Json::Bool(true)),
+            ("missing".to_string(), Json::Null),
+            (
+                "sizes".to_string(),
+                Json::Array(vec![Json::UInt(32768), Json::Float(-1.5)]),
+            ),
+            ("empty".to_string(), Json::Object(vec![])),
+        ]);
+
+        assert_eq!(Json::parse(&value.render()), Some(value.clone()));
+        assert_eq!(Json::parse(&value.render_pretty()), Some(value.clone()));
+        assert_eq!(
+            value
+                .get("sizes")
+                .and_then(Json::as_array)
+                .map(<[Json]>::len),
+            Some(2)
+        );
+        assert_eq!(
+            value.get("name").and_then(Json::as_str),
+            Some("Core \"A\"\n\u{1}")
+        );
+
+        assert_eq!(Json::parse("[1, 2"), None);
+        assert_eq!(Json::parse("{\"a\": 1} x"), None);
+        assert_eq!(Json::parse("nul"), None);
+    }
+}
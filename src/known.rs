@@ -0,0 +1,156 @@
+//! Published cache sizes of well-known CPUs, for `ProcessorInfo::self_test`
+//!
+//! Detection on supported hardware can still misparse a tool's output or a
+//! sysfs file. Comparing what it found against the sizes the vendor publishes
+//! for the same model catches that without needing a second machine.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::processor::ProcessorInfo;
+use crate::size::CacheSize;
+
+const KB: usize = 1024;
+const MB: usize = 1024 * KB;
+
+/// Caches of the primary level as the OS reports them for one core: the L2
+/// of its cluster on Apple Silicon, the L3 slice of its CCD on AMD; 0 where
+/// the OS reports none
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KnownCaches {
+    pub l1_data: usize,
+    pub l2: usize,
+    pub l3: usize,
+}
+
+// Keyed by a part of the brand string each model's contains
+fn known_cpus() -> HashMap<&'static str, KnownCaches> {
+    let caches = |l1_data, l2, l3| KnownCaches { l1_data, l2, l3 };
+
+    HashMap::from([
+        // Performance cores; the system level cache isn't reported
+        ("Apple M1", caches(128 * KB, 12 * MB, 0)),
+        ("Apple M2", caches(128 * KB, 16 * MB, 0)),
+        ("Apple M3", caches(128 * KB, 16 * MB, 0)),
+        ("i7-6700K", caches(32 * KB, 256 * KB, 8 * MB)),
+        ("i7-9750H", caches(32 * KB, 256 * KB, 12 * MB)),
+        ("i9-10900K", caches(32 * KB, 256 * KB, 20 * MB)),
+        // P-cores
+        ("i9-12900K", caches(48 * KB, 1280 * KB, 30 * MB)),
+        ("i9-13900K", caches(48 * KB, 2 * MB, 36 * MB)),
+        (
+            "Xeon(R) Platinum 8375C",
+            caches(48 * KB, 1280 * KB, 54 * MB),
+        ),
+        ("Ryzen 7 5800X", caches(32 * KB, 512 * KB, 32 * MB)),
+        ("Ryzen 7 5800X3D", caches(32 * KB, 512 * KB, 96 * MB)),
+        ("Ryzen 9 5950X", caches(32 * KB, 512 * KB, 32 * MB)),
+        ("Ryzen 9 7950X", caches(32 * KB, MB, 32 * MB)),
+        ("EPYC 7763", caches(32 * KB, 512 * KB, 32 * MB)),
+    ])
+}
+
+/// The published caches of `model_name` and the table key it matched, the
+/// longest one it contains so "Ryzen 7 5800X3D" doesn't pass for a 5800X
+pub fn known_caches(model_name: &str) -> Option<(&'static str, KnownCaches)> {
+    known_cpus()
+        .into_iter()
+        .filter(|(key, _)| model_name.contains(key))
+        .max_by_key(|(key, _)| key.len())
+}
+
+/// One detected size next to the published one, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldCheck {
+    pub field: &'static str,
+    pub expected: usize,
+    pub detected: usize,
+}
+
+impl FieldCheck {
+    /// Within 10% of the published size; caches are never that far off
+    /// without a parsing bug or a different part
+    pub fn passed(&self) -> bool {
+        self.detected > 0 && self.detected.abs_diff(self.expected) <= self.expected / 10
+    }
+}
+
+impl fmt::Display for FieldCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.passed() {
+            write!(f, "pass {}: {}", self.field, CacheSize(self.detected))
+        } else {
+            write!(
+                f,
+                "warn {}: detected {}, expected {}",
+                self.field,
+                CacheSize(self.detected),
+                CacheSize(self.expected)
+            )
+        }
+    }
+}
+
+impl ProcessorInfo {
+    /// The primary level's caches checked against the published sizes of
+    /// this model, None when the model isn't in the table
+    pub fn self_test(&self) -> Option<Vec<FieldCheck>> {
+        let (_, known) = known_caches(&self.model_name)?;
+
+        let checks = [
+            ("L1 Data", known.l1_data, self.l1_data_cache()),
+            ("L2", known.l2, self.l2_cache()),
+            ("L3", known.l3, self.l3_cache()),
+        ];
+        Some(
+            checks
+                .into_iter()
+                .filter(|&(_, expected, _)| expected > 0)
+                .map(|(field, expected, detected)| FieldCheck {
+                    field,
+                    expected,
+                    detected,
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::{LevelKey, ProcessorLevel};
+
+    #[test]
+    fn checks_detection_against_the_longest_matching_model() {
+        assert_eq!(
+            known_caches("AMD Ryzen 7 5800X3D 8-Core Processor").map(|(key, _)| key),
+            Some("Ryzen 7 5800X3D")
+        );
+        assert_eq!(
+            known_caches("Apple M1 Pro").map(|(key, _)| key),
+            Some("Apple M1")
+        );
+        assert_eq!(known_caches("Some Future CPU"), None);
+
+        let mut processor = ProcessorInfo::new();
+        processor.model_name = "AMD Ryzen 7 5800X 8-Core Processor".to_string();
+        let mut level = ProcessorLevel::new(LevelKey::Default);
+        level.l1_cache.data_size = 32 * KB;
+        level.l2_cache = 512 * KB;
+        // A total over the cores, as a buggy parser might report
+        level.l3_cache = 256 * MB;
+        processor
+            .performance_levels
+            .insert(LevelKey::Default, level);
+
+        let checks = processor.self_test().unwrap();
+        let passed: Vec<bool> = checks.iter().map(FieldCheck::passed).collect();
+        assert_eq!(passed, [true, true, false]);
+        assert_eq!(checks[0].to_string(), "pass L1 Data: 32.00 KB");
+        assert_eq!(
+            checks[2].to_string(),
+            "warn L3: detected 256.00 MB, expected 32.00 MB"
+        );
+    }
+}
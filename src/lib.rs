@@ -0,0 +1,93 @@
+//! Utils for matrix multiplication using SIMD Instruction
+//!
+//! The cache model and the block-size math only need `alloc`, so they build
+//! in `no_std` environments where cache sizes are supplied by hand. Detection
+//! through the OS is gated behind the default `std` feature.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "bench-detect")]
+mod bench;
+#[cfg(feature = "wasm")]
+mod browser;
+mod cache;
+#[cfg(feature = "std")]
+mod capture;
+mod color;
+#[cfg(feature = "std")]
+mod compare;
+#[cfg(all(feature = "cpuid", any(target_arch = "x86", target_arch = "x86_64")))]
+mod cpuid;
+#[cfg(feature = "std")]
+mod debug;
+#[cfg(feature = "std")]
+mod error;
+#[cfg(feature = "hwloc")]
+mod hwloc;
+mod json;
+#[cfg(feature = "std")]
+mod known;
+#[cfg(feature = "async")]
+mod nonblocking;
+mod parse;
+#[cfg(feature = "std")]
+mod persist;
+#[cfg(feature = "std")]
+mod processor;
+#[cfg(feature = "std")]
+mod provider;
+mod size;
+#[cfg(feature = "sysinfo")]
+mod sysinfo;
+mod tiling;
+#[cfg(feature = "std")]
+mod timeout;
+
+#[cfg(feature = "wasm")]
+pub use browser::browser_summary;
+pub use cache::{CacheInfo, LevelKey, ProcessorLevel, Source, TlbInfo};
+#[cfg(feature = "std")]
+pub use capture::Capture;
+pub use color::colorize;
+#[cfg(feature = "std")]
+pub use compare::{compare_levels, cross_check, FieldDiff, LevelDiff};
+#[cfg(feature = "std")]
+pub use debug::install_debug_hook;
+#[cfg(feature = "std")]
+pub use error::CacheError;
+pub use json::{Json, JSON_SCHEMA_VERSION};
+#[cfg(feature = "std")]
+pub use known::{known_caches, FieldCheck, KnownCaches};
+#[cfg(feature = "async")]
+pub use nonblocking::{detect_async, detect_async_with, BlockingJob, DetectFuture};
+pub use parse::{format_cpu_list, parse_cpu_list, parse_cpu_mask};
+#[cfg(feature = "std")]
+pub use persist::{cache_dir, CACHED_DETECTION_MAX_AGE};
+#[cfg(feature = "std")]
+pub use processor::{
+    detect, privilege_level, render, set_subprocesses_allowed, subprocesses_allowed,
+    unit_assumptions, CpuTopology, DisplayOptions, Endian, HugepageInfo, LevelOrder,
+    PrivilegeLevel, ProcessorInfo, RawLog, Summary, X86Signature,
+};
+#[cfg(all(feature = "cpuid", any(target_arch = "x86", target_arch = "x86_64")))]
+pub use provider::CpuidProvider;
+#[cfg(feature = "hwloc")]
+pub use provider::HwlocProvider;
+#[cfg(all(feature = "std", target_os = "linux"))]
+pub use provider::LscpuProvider;
+#[cfg(feature = "std")]
+pub use provider::{
+    alternate_providers, detect_with, providers, CacheProvider, NativeProvider, SysfsProvider,
+};
+pub use size::{
+    format_size, parse_size_with_unit, try_parse_size_with_unit, CacheSize, ParseCacheSizeError,
+};
+#[cfg(feature = "sysinfo")]
+pub use sysinfo::{system_summary, SystemSummary};
+pub use tiling::{
+    dtype_size, BlockSizes, BlockingLevels, ElementCapacity, MatrixShape, MemoryFootprint, MR, NR,
+};
+#[cfg(feature = "std")]
+pub use timeout::detect_with_timeout;
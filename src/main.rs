@@ -1,458 +1,854 @@
-use std::collections::HashMap;
 use std::env;
-use std::fs::File;
-use std::io::{self, Read};
-use std::process::Command;
-
-// Define structs to store cache information
-#[derive(Debug, Default, Clone)]
-struct CacheInfo {
-    instruction_size: usize,
-    data_size: usize,
-    unified_size: usize,
+use std::error::Error;
+use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::process;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use matmul_utils::{
+    alternate_providers, colorize, compare_levels, cross_check, dtype_size, format_cpu_list,
+    install_debug_hook, set_subprocesses_allowed, BlockingLevels, CacheError, Capture, CpuTopology,
+    FieldCheck, Json, LevelOrder, MatrixShape, ProcessorInfo,
+};
+
+// Matrices analyzed when --m/--n/--k aren't given
+const DEFAULT_SHAPE: MatrixShape = MatrixShape {
+    m: 1024,
+    n: 1024,
+    k: 1024,
+};
+
+const USAGE: &str = "\
+Usage: matmul-utils [check | codegen | self-test | topology] [options]
+
+Commands:
+  check                    Exit 0 when caches were detected, 2 if partial, 3 if none
+  codegen                  Print the block sizes as Rust constants
+  self-test                Compare the caches with the published sizes of this CPU, exit 1 on a mismatch
+  topology                 List each CPU's core, node and cache sharing
+
+Output:
+  --format <format>        text, json, csv, toml, markdown, prometheus, or env
+                           (export MATMUL_L2=... lines for eval in a shell)
+  --json-pretty            Print the report as indented JSON
+  --json, --prometheus, --env
+                           Deprecated, same as --format json, prometheus, env
+  --output <file>          Write the report to a file instead of stdout
+  --also-json <file>       Also write the JSON report to a file
+  --color <when>           auto, always or never
+  --raw                    Include every raw query and its output
+  --diff-units             Show the units each source reports in, and wmic's values before and after scaling
+  --verbose                Narrate detection on stderr
+  --debug-panic            On a panic, print what detection had collected
+  --debug-json             Dump the full internal state as JSON, for bug reports
+  --plan                   Print what detection would read, then exit
+  --compare <file>         Print how a saved --json report differs from this machine
+  --stats                  Show each cache's share of the level total
+  --tree                   Draw the cache hierarchy as a tree, L3 down to L1
+  --sort <tier|name|size>  Order of the core levels, default tier (fastest first)
+  --elements <f32|f64>     Show each cache's capacity in elements and square tiles
+
+Detection:
+  --sysfs-root <dir>       Read a captured sysfs tree instead of /sys
+  --proc-root <dir>        Read cpuinfo, meminfo and cgroup from another procfs
+  --from-stdin             Analyze a /proc/cpuinfo piped in, without reading this machine
+  --strict                 Drop cache sizes that break L1 <= L2 <= L3
+  --assume-defaults        Fill undetected caches with conservative sizes
+  --no-cache               Detect again instead of reusing the last result
+  --no-subprocess          Never run sysctl, wmic, lscpu or other tools, for sandboxes
+  --memory-channels        Count populated memory channels with dmidecode (Linux, root)
+  --verify                 Cross-check against the other sources, exit 1 on a mismatch
+  --refresh-frequency <s>  Poll CPU frequencies every <s> seconds
+  --log <file>             Append a timestamped CSV row of CPU frequencies to <file>
+  --interval <s>           Seconds between --log rows, default 1
+  --log-bandwidth          Add a STREAM Triad sample to each --log row (bench-detect)
+
+Tiling:
+  --m, --n, --k <n>        Matrix shape to block for
+  --dtype <f32|f64>        Element type
+  --min-level <1|2>        Lowest cache level to block for, default 1
+  --max-level <2|3>        Highest cache level to block for, default 3
+  --recursion-depth        Show how many halvings a cache-oblivious matmul takes to fit C in L2
+  --select-best-dtype      Compare f32 and f64 blocks
+  --interval-bench         Time a matmul at the recommended blocks
+
+  -h, --help               Print this help
+";
+
+// When to color the text report
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
 }
 
-impl CacheInfo {
-    fn format(&self) -> Vec<String> {
-        let mut result = Vec::new();
-
-        if self.unified_size > 0 {
-            result.push(format!(
-                "L1 Cache (Unified): {}",
-                format_size(self.unified_size)
-            ));
-        } else {
-            if self.instruction_size > 0 {
-                result.push(format!(
-                    "L1 Instruction Cache: {}",
-                    format_size(self.instruction_size)
-                ));
-            }
+// What a report is rendered as
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    Csv,
+    Toml,
+    Markdown,
+    Prometheus,
+    Env,
+    Codegen,
+    DebugJson,
+}
 
-            if self.data_size > 0 {
-                result.push(format!("L1 Data Cache: {}", format_size(self.data_size)));
-            }
+impl OutputFormat {
+    // The names --format takes; codegen is a command of its own and
+    // --debug-json a diagnostic outside the supported formats
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(OutputFormat::Text),
+            "json" => Some(OutputFormat::Json),
+            "csv" => Some(OutputFormat::Csv),
+            "toml" => Some(OutputFormat::Toml),
+            "markdown" => Some(OutputFormat::Markdown),
+            "prometheus" => Some(OutputFormat::Prometheus),
+            "env" => Some(OutputFormat::Env),
+            _ => None,
         }
-
-        result
     }
 }
 
-#[derive(Debug, Default, Clone)]
-struct ProcessorLevel {
-    level_name: String,
-    l1_cache: CacheInfo,
-    l2_cache: usize,
-    l3_cache: usize,
+// Command-line options
+#[derive(Debug, Default)]
+struct Options {
+    raw: bool,
+    diff_units: bool,
+    verbose: bool,
+    debug_panic: bool,
+    strict: bool,
+    assume_defaults: bool,
+    stats: bool,
+    tree: bool,
+    no_cache: bool,
+    no_subprocess: bool,
+    memory_channels: bool,
+    verify: bool,
+    plan: bool,
+    codegen: bool,
+    check: bool,
+    self_test: bool,
+    topology: bool,
+    interval_bench: bool,
+    select_best_dtype: bool,
+    recursion_depth: bool,
+    refresh_frequency: Option<f64>,
+    log: Option<String>,
+    interval: Option<f64>,
+    log_bandwidth: bool,
+    format: OutputFormat,
+    json_pretty: bool,
+    deprecated: Vec<&'static str>,
+    output: Option<String>,
+    also_json: Option<String>,
+    compare: Option<String>,
+    color: ColorChoice,
+    sysfs_root: Option<String>,
+    proc_root: Option<String>,
+    from_stdin: bool,
+    m: Option<usize>,
+    n: Option<usize>,
+    k: Option<usize>,
+    dtype: Option<String>,
+    levels: BlockingLevels,
+    sort: LevelOrder,
+    elements: Option<String>,
+    help: bool,
+}
+
+// A matrix dimension: a positive whole number
+fn dimension(value: Option<String>, flag: &str) -> Result<usize, String> {
+    value
+        .and_then(|v| v.parse().ok())
+        .filter(|&size: &usize| size > 0)
+        .ok_or_else(|| format!("{} must be a positive whole number", flag))
 }
 
-impl ProcessorLevel {
-    fn new(name: &str) -> Self {
-        Self {
-            level_name: name.to_string(),
-            ..Default::default()
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self::default();
+        let (mut min_level, mut max_level) = (None, None);
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--raw" => options.raw = true,
+                "--diff-units" => options.diff_units = true,
+                "--verbose" => options.verbose = true,
+                "--debug-panic" => options.debug_panic = true,
+                "--strict" => options.strict = true,
+                "--assume-defaults" => options.assume_defaults = true,
+                "--stats" => options.stats = true,
+                "--tree" => options.tree = true,
+                "--no-cache" => options.no_cache = true,
+                "--no-subprocess" => options.no_subprocess = true,
+                "--from-stdin" => options.from_stdin = true,
+                "--memory-channels" => options.memory_channels = true,
+                "--verify" => options.verify = true,
+                "--plan" => options.plan = true,
+                "codegen" => options.codegen = true,
+                "check" => options.check = true,
+                "self-test" => options.self_test = true,
+                "topology" => options.topology = true,
+                "--interval-bench" => options.interval_bench = true,
+                "--select-best-dtype" => options.select_best_dtype = true,
+                "--recursion-depth" => options.recursion_depth = true,
+                "--refresh-frequency" => {
+                    options.refresh_frequency = Some(
+                        args.next()
+                            .and_then(|v| v.parse().ok())
+                            .filter(|seconds: &f64| *seconds > 0.0)
+                            .ok_or("--refresh-frequency must be a positive number of seconds")?,
+                    )
+                }
+                "--format" => {
+                    options.format = args
+                        .next()
+                        .and_then(|v| OutputFormat::from_name(&v))
+                        .ok_or(
+                            "--format must be text, json, csv, toml, markdown, prometheus or env",
+                        )?
+                }
+                "--debug-json" => options.format = OutputFormat::DebugJson,
+                "--log" => options.log = Some(args.next().ok_or("--log needs a file name")?),
+                "--interval" => {
+                    options.interval = Some(
+                        args.next()
+                            .and_then(|v| v.parse().ok())
+                            .filter(|seconds: &f64| *seconds > 0.0)
+                            .ok_or("--interval must be a positive number of seconds")?,
+                    )
+                }
+                "--log-bandwidth" => options.log_bandwidth = true,
+                "--json-pretty" => {
+                    options.format = OutputFormat::Json;
+                    options.json_pretty = true;
+                }
+                // Kept from before --format
+                "--json" => {
+                    options.format = OutputFormat::Json;
+                    options.deprecated.push("json");
+                }
+                "--prometheus" => {
+                    options.format = OutputFormat::Prometheus;
+                    options.deprecated.push("prometheus");
+                }
+                "--env" => {
+                    options.format = OutputFormat::Env;
+                    options.deprecated.push("env");
+                }
+                "--output" => {
+                    options.output = Some(args.next().ok_or("--output needs a file name")?)
+                }
+                "--also-json" => {
+                    options.also_json = Some(args.next().ok_or("--also-json needs a file name")?)
+                }
+                "--compare" => {
+                    options.compare = Some(args.next().ok_or("--compare needs a report file")?)
+                }
+                "--color" => {
+                    options.color = match args.next().as_deref() {
+                        Some("auto") => ColorChoice::Auto,
+                        Some("always") => ColorChoice::Always,
+                        Some("never") => ColorChoice::Never,
+                        _ => return Err("--color must be auto, always or never".to_string()),
+                    }
+                }
+                "--sysfs-root" => {
+                    options.sysfs_root = Some(args.next().ok_or("--sysfs-root needs a directory")?)
+                }
+                "--proc-root" => {
+                    options.proc_root = Some(args.next().ok_or("--proc-root needs a directory")?)
+                }
+                "--m" => options.m = Some(dimension(args.next(), "--m")?),
+                "--n" => options.n = Some(dimension(args.next(), "--n")?),
+                "--k" => options.k = Some(dimension(args.next(), "--k")?),
+                "--dtype" => {
+                    options.dtype = Some(
+                        args.next()
+                            .filter(|dtype| dtype_size(dtype).is_some())
+                            .ok_or("--dtype must be f32 or f64")?,
+                    )
+                }
+                "--min-level" => {
+                    min_level = Some(
+                        args.next()
+                            .and_then(|v| v.parse().ok())
+                            .ok_or("--min-level must be 1 or 2")?,
+                    )
+                }
+                "--max-level" => {
+                    max_level = Some(
+                        args.next()
+                            .and_then(|v| v.parse().ok())
+                            .ok_or("--max-level must be 2 or 3")?,
+                    )
+                }
+                "--elements" => {
+                    options.elements = Some(
+                        args.next()
+                            .filter(|dtype| dtype_size(dtype).is_some())
+                            .ok_or("--elements must be f32 or f64")?,
+                    )
+                }
+                "--sort" => {
+                    options.sort = args
+                        .next()
+                        .and_then(|v| LevelOrder::from_name(&v))
+                        .ok_or("--sort must be tier, name or size")?
+                }
+                "-h" | "--help" => options.help = true,
+                _ => return Err(format!("unknown option '{}'", arg)),
+            }
         }
-    }
 
-    fn format(&self) -> Vec<String> {
-        let mut result = Vec::new();
+        // The occupancy report needs the whole shape
+        let dimensions = [options.m, options.n, options.k];
+        if dimensions.iter().any(Option::is_some) && !dimensions.iter().all(Option::is_some) {
+            return Err("--m, --n and --k must be given together".to_string());
+        }
 
-        result.push(format!("\n{}", self.level_name));
-        result.push(format!("{}", "-".repeat(self.level_name.len())));
+        // Every level by default
+        if min_level.is_some() || max_level.is_some() {
+            let defaults = BlockingLevels::default();
+            options.levels = BlockingLevels::new(
+                min_level.unwrap_or(defaults.min),
+                max_level.unwrap_or(defaults.max),
+            )
+            .ok_or("--min-level must be 1 or 2 and --max-level 2 or 3")?;
+        }
 
-        // Add L1 cache info
-        result.extend(self.l1_cache.format());
+        Ok(options)
+    }
 
-        // Add L2 and L3 cache info
-        result.push(format!("L2 Cache: {}", format_size(self.l2_cache)));
+    fn format(&self) -> OutputFormat {
+        if self.codegen {
+            OutputFormat::Codegen
+        } else {
+            self.format
+        }
+    }
 
-        if self.l3_cache > 0 {
-            result.push(format!("L3 Cache: {}", format_size(self.l3_cache)));
+    // Colors only go to a terminal, and never into machine-readable output
+    fn use_color(&self) -> bool {
+        if self.format() != OutputFormat::Text {
+            return false;
         }
 
-        result
+        match self.color {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                self.output.is_none()
+                    && env::var_os("NO_COLOR").is_none()
+                    && io::stdout().is_terminal()
+            }
+        }
     }
-}
 
-#[derive(Debug, Default)]
-struct ProcessorInfo {
-    architecture: String,
-    model_name: String,
-    performance_levels: HashMap<String, ProcessorLevel>,
+    // Results stored by an earlier run stand in for a plain live detection
+    // only: --raw and --diff-units want the queries, --verbose narrates
+    // them, --strict changes the results and check, --verify, --debug-json
+    // and captured trees are about detection itself
+    fn caches_detection(&self) -> bool {
+        !self.raw
+            && !self.verbose
+            && !self.diff_units
+            && !self.strict
+            && !self.check
+            && !self.verify
+            && self.format != OutputFormat::DebugJson
+            && self.sysfs_root.is_none()
+            && self.proc_root.is_none()
+            && !self.from_stdin
+    }
+
+    // Whether the report describes the machine this runs on, so measuring or
+    // probing it adds to the report
+    fn reads_this_machine(&self) -> bool {
+        self.sysfs_root.is_none() && !self.from_stdin
+    }
+
+    fn matrix_shape(&self) -> Option<MatrixShape> {
+        Some(MatrixShape {
+            m: self.m?,
+            n: self.n?,
+            k: self.k?,
+        })
+    }
 }
 
-impl ProcessorInfo {
-    fn new() -> Self {
-        Self {
-            architecture: env::consts::ARCH.to_string(),
-            ..Default::default()
+fn main() -> Result<(), Box<dyn Error>> {
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("error: {}\nRun with --help for usage", err);
+            process::exit(2);
         }
+    };
+
+    for flag in &options.deprecated {
+        eprintln!("warning: --{} is deprecated, use --format {}", flag, flag);
     }
 
-    fn detect_architecture(&mut self) -> &mut Self {
-        self.architecture = match self.architecture.as_str() {
-            "x86" | "x86_64" => "x86".to_string(),
-            "aarch64" | "arm" | "arm64" => self.detect_arm_type(),
-            _ => format!("Unknown: {}", self.architecture),
-        };
+    if options.help {
+        print!("{}", USAGE);
+        return Ok(());
+    }
 
-        self.detect_model_name();
-        self
+    if options.debug_panic {
+        install_debug_hook();
+    }
+    if options.no_subprocess {
+        set_subprocesses_allowed(false);
     }
 
-    fn detect_arm_type(&mut self) -> String {
-        #[cfg(target_os = "macos")]
-        {
-            if let Ok(output) = Command::new("sysctl")
-                .args(&["-n", "machdep.cpu.brand_string"])
-                .output()
-            {
-                let cpu_info = String::from_utf8_lossy(&output.stdout);
-                if cpu_info.contains("Apple") {
-                    return "Apple Silicon".to_string();
-                }
-            }
-        }
+    let mut processor = ProcessorInfo::new();
+    processor.sysfs_root = options.sysfs_root.clone();
+    processor.proc_root = options.proc_root.clone();
+    processor.raw_log.verbose = options.verbose;
+    processor.strict = options.strict;
+    processor.stats = options.stats;
+    processor.level_order = options.sort;
+    processor.element_dtype = options.elements.clone();
+
+    if options.plan {
+        println!("{}", processor.detection_plan().join("\n"));
+        return Ok(());
+    }
 
-        "ARM".to_string()
-    }
-
-    fn detect_model_name(&mut self) {
-        #[cfg(target_os = "linux")]
-        {
-            if let Ok(mut file) = File::open("/proc/cpuinfo") {
-                let mut contents = String::new();
-                if file.read_to_string(&mut contents).is_ok() {
-                    for line in contents.lines() {
-                        if line.starts_with("model name") {
-                            if let Some(model) = line.split(':').nth(1) {
-                                self.model_name = model.trim().to_string();
-                                break;
-                            }
-                        }
-                    }
-                }
-            }
+    // Reads per-CPU sysfs entries only, the cache summary isn't needed
+    if options.topology {
+        let topology = processor.cpu_topology();
+        if topology.is_empty() {
+            return Err("no per-CPU topology available from sysfs".into());
         }
 
-        #[cfg(target_os = "macos")]
-        {
-            if let Ok(output) = Command::new("sysctl")
-                .args(&["-n", "machdep.cpu.brand_string"])
-                .output()
-            {
-                self.model_name = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        println!("{}", topology_table(&topology));
+        return Ok(());
+    }
+
+    if let Some(path) = &options.log {
+        return log_frequencies(
+            &mut processor,
+            path,
+            options.interval.unwrap_or(1.0),
+            options.log_bandwidth,
+        );
+    }
+
+    // Frequencies change all the time while caches don't, so this polls
+    // cpufreq alone and never runs cache detection
+    if let Some(seconds) = options.refresh_frequency {
+        loop {
+            let frequencies: Vec<String> = processor
+                .current_frequencies_mhz()
+                .iter()
+                .map(|(cpu, mhz)| format!("cpu{}: {:.0} MHz", cpu, mhz))
+                .collect();
+
+            if frequencies.is_empty() {
+                return Err("no CPU frequencies available from cpufreq".into());
             }
+            println!("{}", frequencies.join(", "));
+
+            thread::sleep(Duration::from_secs_f64(seconds));
         }
+    }
 
-        #[cfg(windows)]
-        {
-            if let Ok(output) = Command::new("wmic")
-                .args(&["cpu", "get", "name", "/value"])
-                .output()
-            {
-                let output_str = String::from_utf8_lossy(&output.stdout);
-                for line in output_str.lines() {
-                    if line.starts_with("Name=") {
-                        self.model_name = line.trim_start_matches("Name=").trim().to_string();
-                        break;
+    if options.from_stdin {
+        let mut dump = String::new();
+        io::stdin().read_to_string(&mut dump)?;
+        processor.collect_from_capture(Capture::Cpuinfo(&dump))?;
+    } else {
+        processor.detect_architecture();
+
+        let cached = options.caches_detection();
+        let loaded = cached && !options.no_cache && processor.load_cached_detection();
+        if !loaded {
+            // Still print what is known (architecture, model) on other OSes
+            match processor.collect_cache_info() {
+                Ok(_) => {
+                    if cached {
+                        // A read-only cache dir only means detecting again next time
+                        let _ = processor.store_cached_detection();
                     }
                 }
+                Err(CacheError::UnsupportedOs(os)) if options.check => {
+                    println!(
+                        "nothing detected: cache detection is not supported on {}",
+                        os
+                    );
+                    process::exit(3);
+                }
+                Err(err) if options.check => {
+                    println!("nothing detected: {}", err);
+                    process::exit(3);
+                }
+                Err(CacheError::UnsupportedOs(os)) => {
+                    eprintln!("Cache detection is not supported on {}", os);
+                }
+                Err(err) => return Err(err.into()),
             }
         }
     }
 
-    fn collect_cache_info(&mut self) -> io::Result<&mut Self> {
-        match env::consts::OS {
-            "macos" => {
-                #[cfg(target_os = "macos")]
-                self.collect_macos_cache_info()?;
-            }
-            "linux" => {
-                #[cfg(target_os = "linux")]
-                self.collect_linux_cache_info()?;
-            }
-            "windows" => {
-                #[cfg(windows)]
-                self.collect_windows_cache_info()?;
-            }
-            _ => {
-                eprintln!("Unsupported operating system: {}", env::consts::OS);
-            }
-        }
+    if options.check {
+        let (code, reason) = health_check(&processor);
+        println!("{}", reason);
+        process::exit(code);
+    }
 
-        Ok(self)
+    if options.self_test {
+        process::exit(self_test(&processor));
     }
 
-    #[cfg(target_os = "macos")]
-    fn collect_macos_cache_info(&mut self) -> io::Result<()> {
-        if self.architecture == "Apple Silicon" {
-            self.collect_apple_silicon_cache_info()
-        } else {
-            self.collect_intel_mac_cache_info()
-        }
+    if options.verify {
+        let disagreements = verify(&processor);
+        process::exit(if disagreements > 0 { 1 } else { 0 });
     }
 
-    #[cfg(target_os = "macos")]
-    fn collect_apple_silicon_cache_info(&mut self) -> io::Result<()> {
-        // Get number of performance levels
-        let perf_levels = run_sysctl("hw.nperflevels")?.parse::<usize>().unwrap_or(1);
+    if options.assume_defaults {
+        processor.assume_defaults();
+    }
 
-        // For each performance level
-        for level in 0..perf_levels {
-            let level_name = if level == 0 {
-                "Performance Cores".to_string()
-            } else {
-                format!("Efficiency Cores (Level {})", level)
-            };
-
-            let mut proc_level = ProcessorLevel::new(&level_name);
-
-            // L1 instruction cache
-            proc_level.l1_cache.instruction_size =
-                run_sysctl(&format!("hw.perflevel{}.l1icachesize", level))?
-                    .parse::<usize>()
-                    .unwrap_or(0);
-
-            // L1 data cache
-            proc_level.l1_cache.data_size =
-                run_sysctl(&format!("hw.perflevel{}.l1dcachesize", level))?
-                    .parse::<usize>()
-                    .unwrap_or(0);
-
-            // L2 cache
-            proc_level.l2_cache = run_sysctl(&format!("hw.perflevel{}.l2cachesize", level))?
-                .parse::<usize>()
-                .unwrap_or(0);
-
-            // L3 cache (shared across all cores usually)
-            if level == 0 {
-                proc_level.l3_cache = run_sysctl("hw.l3cachesize")?.parse::<usize>().unwrap_or(0);
-            }
+    if let Some(path) = &options.compare {
+        let saved = Json::parse(&fs::read_to_string(path)?)
+            .and_then(|json| ProcessorInfo::from_json(&json))
+            .ok_or_else(|| format!("{} is not a --json report from this version", path))?;
 
-            self.performance_levels.insert(level_name, proc_level);
+        let diffs = compare_levels(&saved, &processor);
+        if diffs.is_empty() {
+            println!("Same caches as {}", path);
         }
+        for diff in diffs {
+            println!("{}", diff);
+        }
+        return Ok(());
+    }
 
-        Ok(())
+    let processor_info = &mut processor;
+
+    if options.memory_channels
+        && options.reads_this_machine()
+        && processor_info.detect_memory_channels().is_none()
+    {
+        eprintln!("warning: no memory channels found, dmidecode needs root and SMBIOS data");
     }
 
-    #[cfg(target_os = "macos")]
-    fn collect_intel_mac_cache_info(&mut self) -> io::Result<()> {
-        let mut proc_level = ProcessorLevel::new("Default");
+    // Benchmarking only makes sense against the live machine
+    #[cfg(feature = "bench-detect")]
+    if options.reads_this_machine() {
+        processor_info.measure_latencies();
+        processor_info.measure_memory_bandwidth();
+    }
 
-        // Try unified L1 cache first
-        match run_sysctl("hw.l1cachesize") {
-            Ok(value) if !value.is_empty() => {
-                proc_level.l1_cache.unified_size = value.parse::<usize>().unwrap_or(0);
-            }
-            _ => {
-                // Try separate instruction and data caches
-                if let Ok(value) = run_sysctl("hw.l1icachesize") {
-                    proc_level.l1_cache.instruction_size = value.parse::<usize>().unwrap_or(0);
-                }
+    let mut report = render(processor_info, &options, options.format());
+    if options.use_color() {
+        report = colorize(&report);
+    }
+    write_report(&report, options.output.as_deref())?;
 
-                if let Ok(value) = run_sysctl("hw.l1dcachesize") {
-                    proc_level.l1_cache.data_size = value.parse::<usize>().unwrap_or(0);
-                }
-            }
+    if let Some(path) = &options.also_json {
+        write_report(
+            &render(processor_info, &options, OutputFormat::Json),
+            Some(path),
+        )?;
+    }
+
+    Ok(())
+}
+
+// Appends one "unix_time,cpu0_mhz,..." row every `seconds` until killed,
+// writing the header first when the file is new, for throttling studies
+fn log_frequencies(
+    processor: &mut ProcessorInfo,
+    path: &str,
+    seconds: f64,
+    bandwidth: bool,
+) -> Result<(), Box<dyn Error>> {
+    if bandwidth && !cfg!(feature = "bench-detect") {
+        return Err("--log-bandwidth needs a build with the bench-detect feature".into());
+    }
+
+    // The columns stay those of the first sample when CPUs go offline
+    let cpus: Vec<usize> = processor
+        .current_frequencies_mhz()
+        .iter()
+        .map(|(cpu, _)| *cpu)
+        .collect();
+    if cpus.is_empty() {
+        return Err("no CPU frequencies available from cpufreq".into());
+    }
+
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    if file.metadata()?.len() == 0 {
+        let mut header = vec!["unix_time".to_string()];
+        header.extend(cpus.iter().map(|cpu| format!("cpu{}_mhz", cpu)));
+        if bandwidth {
+            header.push("bandwidth_gbps".to_string());
         }
+        writeln!(file, "{}", header.join(","))?;
+    }
 
-        // L2 cache
-        if let Ok(value) = run_sysctl("hw.l2cachesize") {
-            proc_level.l2_cache = value.parse::<usize>().unwrap_or(0);
+    loop {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs_f64();
+        let frequencies = processor.current_frequencies_mhz();
+        let mut row = vec![format!("{:.3}", now)];
+        row.extend(cpus.iter().map(|cpu| {
+            frequencies
+                .iter()
+                .find(|(online, _)| online == cpu)
+                .map_or(String::new(), |(_, mhz)| format!("{:.0}", mhz))
+        }));
+
+        #[cfg(feature = "bench-detect")]
+        if bandwidth {
+            processor.measure_memory_bandwidth();
+            row.push(
+                processor
+                    .memory_bandwidth_gbps
+                    .map_or(String::new(), |gbps| format!("{:.2}", gbps)),
+            );
         }
 
-        // L3 cache
-        if let Ok(value) = run_sysctl("hw.l3cachesize") {
-            proc_level.l3_cache = value.parse::<usize>().unwrap_or(0);
+        writeln!(file, "{}", row.join(","))?;
+        thread::sleep(Duration::from_secs_f64(seconds));
+    }
+}
+
+// Renders the report in one format; where it goes is up to the caller
+fn render(processor_info: &mut ProcessorInfo, options: &Options, format: OutputFormat) -> String {
+    let mut report = Vec::new();
+
+    match format {
+        OutputFormat::Codegen => report.push(processor_info.to_rust_consts()),
+        OutputFormat::Prometheus => report.push(processor_info.to_prometheus()),
+        OutputFormat::Env => report.push(processor_info.to_shell_exports()),
+        OutputFormat::Csv => report.push(processor_info.to_csv()),
+        OutputFormat::Toml => report.push(processor_info.to_toml()),
+        OutputFormat::Markdown => report.push(processor_info.to_markdown()),
+        OutputFormat::DebugJson => report.push(processor_info.to_debug_json().render_pretty()),
+        OutputFormat::Json if options.json_pretty => {
+            report.push(processor_info.to_json().render_pretty())
         }
+        OutputFormat::Json => report.push(processor_info.to_json_string()),
+        OutputFormat::Text => {
+            if options.raw {
+                report.push(format!("{}\n", processor_info.raw_log.format().join("\n")));
+            }
 
-        self.performance_levels
-            .insert("Default".to_string(), proc_level);
-
-        Ok(())
-    }
-
-    #[cfg(target_os = "linux")]
-    fn collect_linux_cache_info(&mut self) -> io::Result<()> {
-        let mut proc_level = ProcessorLevel::new("Default");
-
-        // Read cache information from sysfs
-        for i in 0..10 {
-            let cache_dir = format!("/sys/devices/system/cpu/cpu0/cache/index{}", i);
-
-            // Check if this cache index exists
-            let level = match read_file(&format!("{}/level", cache_dir)) {
-                Ok(content) => content.trim().parse::<usize>().unwrap_or(0),
-                Err(_) => continue,
-            };
-
-            // Get cache type
-            let cache_type = match read_file(&format!("{}/type", cache_dir)) {
-                Ok(content) => content.trim().to_string(),
-                Err(_) => continue,
-            };
-
-            // Get cache size
-            let size_str = match read_file(&format!("{}/size", cache_dir)) {
-                Ok(content) => content.trim().to_string(),
-                Err(_) => continue,
-            };
-
-            // Parse the size (e.g., "32K" or "4M")
-            let size = parse_size_with_unit(&size_str);
-
-            // Store the cache size based on its level and type
-            match level {
-                1 => match cache_type.as_str() {
-                    "Data" => proc_level.l1_cache.data_size = size,
-                    "Instruction" => proc_level.l1_cache.instruction_size = size,
-                    "Unified" => proc_level.l1_cache.unified_size = size,
-                    _ => {}
-                },
-                2 => proc_level.l2_cache = size,
-                3 => proc_level.l3_cache = size,
-                _ => {} // Ignore other levels
+            if options.diff_units {
+                report.push(format!("{}\n", processor_info.unit_report().join("\n")));
             }
-        }
 
-        self.performance_levels
-            .insert("Default".to_string(), proc_level);
-
-        Ok(())
-    }
-
-    #[cfg(windows)]
-    fn collect_windows_cache_info(&mut self) -> io::Result<()> {
-        let mut proc_level = ProcessorLevel::new("Default");
-
-        // Use wmic to get cache information on Windows
-        if let Ok(output) = Command::new("wmic")
-            .args(&[
-                "cpu",
-                "get",
-                "L1CacheSize,L2CacheSize,L3CacheSize",
-                "/value",
-            ])
-            .output()
-        {
-            let output_str = String::from_utf8_lossy(&output.stdout);
-
-            // Parse the output to extract cache sizes
-            for line in output_str.lines() {
-                if line.starts_with("L1CacheSize=") {
-                    if let Ok(size) = line
-                        .trim_start_matches("L1CacheSize=")
-                        .trim()
-                        .parse::<usize>()
-                    {
-                        proc_level.l1_cache.unified_size = size * 1024; // Windows reports in KB
-                    }
-                } else if line.starts_with("L2CacheSize=") {
-                    if let Ok(size) = line
-                        .trim_start_matches("L2CacheSize=")
-                        .trim()
-                        .parse::<usize>()
-                    {
-                        proc_level.l2_cache = size * 1024; // Windows reports in KB
-                    }
-                } else if line.starts_with("L3CacheSize=") {
-                    if let Ok(size) = line
-                        .trim_start_matches("L3CacheSize=")
-                        .trim()
-                        .parse::<usize>()
-                    {
-                        proc_level.l3_cache = size * 1024; // Windows reports in KB
+            if options.tree {
+                report.push(processor_info.display_tree());
+            } else {
+                report.push(processor_info.display());
+            }
+
+            if let Some(shape) = options.matrix_shape() {
+                let dtype = options.dtype.as_deref().unwrap_or("f64");
+                let bytes_per_elem = dtype_size(dtype).unwrap_or(8);
+
+                for level in processor_info.levels_sorted(options.sort) {
+                    report.push(
+                        level
+                            .format_tiling_for_levels(&shape, dtype, bytes_per_elem, options.levels)
+                            .join("\n"),
+                    );
+                    if options.recursion_depth {
+                        report.extend(level.format_recursion_depth(&shape, bytes_per_elem));
                     }
                 }
             }
-        }
 
-        self.performance_levels
-            .insert("Default".to_string(), proc_level);
+            if options.select_best_dtype {
+                let shape = options.matrix_shape().unwrap_or(DEFAULT_SHAPE);
+                for level in processor_info.levels_sorted(options.sort) {
+                    report.push(level.format_dtype_comparison(&shape).join("\n"));
+                }
+            }
 
-        Ok(())
+            if options.interval_bench {
+                report.push(interval_bench(processor_info, options));
+            }
+        }
     }
 
-    fn display(&mut self) -> String {
-        let mut result = Vec::new();
-
-        result.push(format!(
-            "Architecture: {} - {}",
-            self.architecture,
-            env::consts::ARCH.to_string()
-        ));
+    report.join("\n")
+}
 
-        if !self.model_name.is_empty() {
-            result.push(format!("CPU Model: {}", self.model_name));
+// Writes a rendered report to a file, or to stdout when there's no path
+fn write_report(report: &str, path: Option<&str>) -> io::Result<()> {
+    match path {
+        Some(path) => fs::write(path, format!("{}\n", report)),
+        None => {
+            println!("{}", report);
+            Ok(())
         }
+    }
+}
 
-        result.push("\nCache Information:".to_string());
-        result.push("==================".to_string());
-
-        for (_, level) in &self.performance_levels {
-            result.extend(level.format());
+// One row per logical CPU, "-" for whatever the kernel didn't report
+fn topology_table(topology: &[CpuTopology]) -> String {
+    let known = |value: Option<usize>| value.map_or("-".to_string(), |value| value.to_string());
+    let shared = |cpus: &[usize]| {
+        if cpus.is_empty() {
+            "-".to_string()
+        } else {
+            format_cpu_list(cpus)
         }
-
-        result.join("\n")
+    };
+
+    let mut rows = vec![format!(
+        "{:<5}{:<9}{:<6}{:<6}{:<12}{}",
+        "CPU", "Package", "Core", "Node", "L2 shared", "L3 shared"
+    )];
+    for entry in topology {
+        rows.push(format!(
+            "{:<5}{:<9}{:<6}{:<6}{:<12}{}",
+            entry.cpu,
+            known(entry.package),
+            known(entry.core),
+            known(entry.numa_node),
+            shared(&entry.l2_shared_cpus),
+            shared(&entry.l3_shared_cpus)
+        ));
     }
-}
 
-// Helper functions
+    rows.join("\n")
+}
 
-#[cfg(target_os = "macos")]
-fn run_sysctl(parameter: &str) -> io::Result<String> {
-    let output = Command::new("sysctl").args(&["-n", parameter]).output()?;
+// Prints how each alternate source compares with the detection, returning
+// how many disagree
+fn verify(processor: &ProcessorInfo) -> usize {
+    let detected_via = processor
+        .primary_level()
+        .and_then(|level| level.l2_source)
+        .map_or("detection", |source| source.as_str());
+
+    // Checking a source against itself proves nothing
+    let alternates: Vec<_> = alternate_providers()
+        .into_iter()
+        .filter(|provider| provider.name() != detected_via)
+        .collect();
+    if alternates.is_empty() {
+        println!("No other source to check against in this build");
+    }
 
-    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
-}
+    let mut disagreements = 0;
+    for provider in alternates {
+        let diffs = match provider.collect() {
+            Ok(alternate) if alternate.primary_level().is_some() => {
+                cross_check(processor, &alternate)
+            }
+            Ok(_) => {
+                println!("{}: found no caches", provider.name());
+                continue;
+            }
+            Err(err) => {
+                println!("{}: unavailable ({})", provider.name(), err);
+                continue;
+            }
+        };
 
-#[cfg(target_os = "linux")]
-fn read_file(path: &str) -> io::Result<String> {
-    let mut file = File::open(path)?;
-    let mut content = String::new();
-    file.read_to_string(&mut content)?;
-    Ok(content)
-}
+        if diffs.is_empty() {
+            println!("{} agrees with {}", provider.name(), detected_via);
+            continue;
+        }
 
-fn parse_size_with_unit(size_str: &str) -> usize {
-    let numeric_part: String = size_str.chars().take_while(|c| c.is_digit(10)).collect();
+        disagreements += 1;
+        println!(
+            "{} disagrees with {} ({} -> {}):",
+            provider.name(),
+            detected_via,
+            detected_via,
+            provider.name()
+        );
+        for diff in diffs {
+            println!("  {}", diff);
+        }
+    }
 
-    let base_size = numeric_part.parse::<usize>().unwrap_or(0);
+    disagreements
+}
 
-    // Convert to bytes based on the unit
-    if size_str.ends_with('K') {
-        base_size * 1024
-    } else if size_str.ends_with('M') {
-        base_size * 1024 * 1024
-    } else if size_str.ends_with('G') {
-        base_size * 1024 * 1024 * 1024
+// Exit code and one-line reason for `check`: 0 when the primary level has
+// both L1 and L2, 2 when only some caches were found, 3 when none were
+fn health_check(processor_info: &ProcessorInfo) -> (i32, String) {
+    let l1 = processor_info.l1_data_cache();
+    let l2 = processor_info.l2_cache();
+    let l3 = processor_info.l3_cache();
+
+    if l1 > 0 && l2 > 0 {
+        (0, "ok: L1 and L2 detected".to_string())
+    } else if l1 > 0 || l2 > 0 || l3 > 0 {
+        let missing = match (l1, l2) {
+            (0, 0) => "L1 or L2",
+            (0, _) => "L1",
+            _ => "L2",
+        };
+        (
+            2,
+            format!("partially detected: no {} on the primary level", missing),
+        )
     } else {
-        base_size
+        (3, "nothing detected: no caches found".to_string())
     }
 }
 
-fn format_size(size: usize) -> String {
-    if size == 0 {
-        return "Not detected".to_string();
+// Prints pass or warn per cache of a CPU in the table of known ones: exit 0
+// when all pass, 1 on a mismatch and 2 for a model without published sizes
+fn self_test(processor_info: &ProcessorInfo) -> i32 {
+    let Some(checks) = processor_info.self_test() else {
+        println!(
+            "no published cache sizes for {:?}, nothing to compare",
+            processor_info.model_name
+        );
+        return 2;
+    };
+
+    for check in &checks {
+        println!("{}", check);
     }
 
-    if size < 1024 {
-        format!("{} B", size)
-    } else if size < 1024 * 1024 {
-        format!("{:.2} KB", size as f64 / 1024.0)
-    } else if size < 1024 * 1024 * 1024 {
-        format!("{:.2} MB", size as f64 / (1024.0 * 1024.0))
+    if checks.iter().all(FieldCheck::passed) {
+        0
     } else {
-        format!("{:.2} GB", size as f64 / (1024.0 * 1024.0 * 1024.0))
+        1
     }
 }
 
-fn main() -> io::Result<()> {
-    let mut processor = ProcessorInfo::new();
-    let processor_info = processor.detect_architecture().collect_cache_info()?;
-
-    println!("{}", processor_info.display());
+// Validates the detected caches by timing a matmul at the recommended blocks
+fn interval_bench(processor_info: &ProcessorInfo, options: &Options) -> String {
+    #[cfg(feature = "bench-detect")]
+    {
+        let shape = options.matrix_shape().unwrap_or(DEFAULT_SHAPE);
+
+        match processor_info.measure_matmul_gflops(&shape) {
+            Some((blocks, gflops)) => format!(
+                "\nMatmul Benchmark (f64, {}x{}x{})\nBlocks: MC={}, KC={}, NC={}\nMeasured: {:.2} GFLOPS",
+                shape.m, shape.n, shape.k, blocks.mc, blocks.kc, blocks.nc, gflops
+            ),
+            None => "\nMatmul Benchmark: insufficient cache data for tiling".to_string(),
+        }
+    }
 
-    Ok(())
+    #[cfg(not(feature = "bench-detect"))]
+    {
+        let _ = (processor_info, options);
+        "\n--interval-bench needs a build with the bench-detect feature".to_string()
+    }
 }
@@ -0,0 +1,143 @@
+//! Detection for async runtimes
+//!
+//! Detection reads files and waits on tools like sysctl and wmic, which
+//! would stall every task sharing an executor thread. These futures hand it
+//! to a blocking pool instead and resolve once it's done, without tying the
+//! crate to any one runtime.
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use crate::error::CacheError;
+use crate::processor::{detect, ProcessorInfo};
+
+/// Blocking work handed to a spawner, see `detect_async_with`
+pub type BlockingJob = Box<dyn FnOnce() + Send + 'static>;
+
+/// `detect` on a thread of its own, awaitable from any runtime
+pub fn detect_async() -> DetectFuture {
+    detect_async_with(|job| {
+        // A failed spawn drops the job, which fails the future
+        let _ = thread::Builder::new()
+            .name("matmul-utils-detect".to_string())
+            .spawn(job);
+    })
+}
+
+/// `detect` run by `spawn`, such as `|job| { tokio::task::spawn_blocking(job); }`
+/// or `|job| { smol::unblock(job).detach(); }`
+///
+/// The future fails with an I/O error if `spawn` drops the job unrun or the
+/// detection panics.
+pub fn detect_async_with(spawn: impl FnOnce(BlockingJob)) -> DetectFuture {
+    let shared = Arc::new(Mutex::new(Shared::default()));
+    let job = Job {
+        shared: Arc::clone(&shared),
+    };
+    spawn(Box::new(move || job.run()));
+
+    DetectFuture { shared }
+}
+
+/// Resolves to the result of `detect`, see `detect_async`
+pub struct DetectFuture {
+    shared: Arc<Mutex<Shared>>,
+}
+
+#[derive(Default)]
+struct Shared {
+    result: Option<Result<ProcessorInfo, CacheError>>,
+    waker: Option<Waker>,
+}
+
+impl Future for DetectFuture {
+    type Output = Result<ProcessorInfo, CacheError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut shared = self.shared.lock().unwrap_or_else(|err| err.into_inner());
+
+        match shared.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                shared.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+// Fills in the result when run, and an error when dropped without one, so
+// the future can't hang on a job that never ran or panicked
+struct Job {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Job {
+    fn run(self) {
+        let result = detect();
+        self.finish(result);
+    }
+
+    fn finish(&self, result: Result<ProcessorInfo, CacheError>) {
+        let mut shared = self.shared.lock().unwrap_or_else(|err| err.into_inner());
+        if shared.result.is_none() {
+            shared.result = Some(result);
+        }
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        let done = self
+            .shared
+            .lock()
+            .map_or(true, |shared| shared.result.is_some());
+        if !done {
+            self.finish(Err(io::Error::other("detection job never completed").into()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::task::Wake;
+
+    // Just enough executor to drive one future on the test thread
+    struct Unpark(thread::Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn resolves_to_the_detection_and_fails_on_a_dropped_job() {
+        let processor = block_on(detect_async());
+        assert!(processor.is_ok() || matches!(processor, Err(CacheError::UnsupportedOs(_))));
+
+        let dropped = block_on(detect_async_with(drop));
+        assert!(matches!(dropped, Err(CacheError::Io(_))));
+    }
+}
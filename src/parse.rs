@@ -0,0 +1,100 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Parses a sysfs CPU list such as "0-3,8,10-11"
+pub fn parse_cpu_list(list: &str) -> Vec<usize> {
+    let mut cpus = Vec::new();
+
+    for range in list.split(',').map(str::trim).filter(|r| !r.is_empty()) {
+        match range.split_once('-') {
+            Some((start, end)) => {
+                if let (Ok(start), Ok(end)) = (start.parse::<usize>(), end.parse::<usize>()) {
+                    cpus.extend(start..=end);
+                }
+            }
+            None => {
+                if let Ok(cpu) = range.parse::<usize>() {
+                    cpus.push(cpu);
+                }
+            }
+        }
+    }
+
+    cpus
+}
+
+/// Writes CPUs back as a sysfs-style list, "0-3,8" for [0, 1, 2, 3, 8]
+pub fn format_cpu_list(cpus: &[usize]) -> String {
+    let mut sorted = cpus.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for cpu in sorted {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == cpu => *end = cpu,
+            _ => ranges.push((cpu, cpu)),
+        }
+    }
+
+    let ranges: Vec<String> = ranges
+        .iter()
+        .map(|&(start, end)| {
+            if start == end {
+                format!("{}", start)
+            } else {
+                format!("{}-{}", start, end)
+            }
+        })
+        .collect();
+    ranges.join(",")
+}
+
+/// Parses a sysfs CPU bitmask such as "00000000,0000000f", written as
+/// comma-separated 32-bit hex groups with the most significant group first
+pub fn parse_cpu_mask(mask: &str) -> Vec<usize> {
+    let mut cpus = Vec::new();
+    let digits = mask.chars().filter(|c| *c != ',').rev();
+
+    for (position, digit) in digits.enumerate() {
+        let Some(value) = digit.to_digit(16) else {
+            return Vec::new();
+        };
+
+        for bit in 0..4 {
+            if value & (1 << bit) != 0 {
+                cpus.push(position * 4 + bit);
+            }
+        }
+    }
+
+    cpus
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn parses_comma_grouped_cpu_masks() {
+        assert_eq!(parse_cpu_mask("0000000f"), vec![0, 1, 2, 3]);
+        assert_eq!(parse_cpu_mask("00000000,0000000f"), vec![0, 1, 2, 3]);
+        assert_eq!(parse_cpu_mask("00000001,00000000"), vec![32]);
+        assert_eq!(parse_cpu_mask("00000100,00000011"), vec![0, 4, 40]);
+        assert_eq!(parse_cpu_mask("00000000,00000000"), Vec::<usize>::new());
+        assert_eq!(parse_cpu_mask("zz"), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn formats_cpu_lists_as_ranges() {
+        assert_eq!(format_cpu_list(&[0, 1, 2, 3, 8, 10, 11]), "0-3,8,10-11");
+        assert_eq!(format_cpu_list(&[5, 4, 4]), "4-5");
+        assert_eq!(format_cpu_list(&[]), "");
+        assert_eq!(
+            parse_cpu_list(&format_cpu_list(&[1, 3, 4, 5])),
+            vec![1, 3, 4, 5]
+        );
+    }
+}
@@ -0,0 +1,256 @@
+//! Detection results kept in the user's cache directory between runs
+//!
+//! Build scripts and other short-lived tools may run detection many times a
+//! minute on the same machine, and the answer doesn't change. Results are
+//! stored as `to_json` output under a key made from the architecture and CPU
+//! model, which `detect_architecture` finds without collecting any caches.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::json::Json;
+use crate::parse::format_cpu_list;
+use crate::processor::ProcessorInfo;
+
+/// How long a stored result is trusted before detection runs again
+pub const CACHED_DETECTION_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// This tool's directory under the OS cache dir: `$XDG_CACHE_HOME` or
+/// `~/.cache`, `~/Library/Caches` on macOS and `%LOCALAPPDATA%` on Windows
+pub fn cache_dir() -> Option<PathBuf> {
+    let non_empty = |name| env::var_os(name).filter(|value| !value.is_empty());
+
+    let base = if cfg!(windows) {
+        PathBuf::from(non_empty("LOCALAPPDATA")?)
+    } else if cfg!(target_os = "macos") {
+        PathBuf::from(non_empty("HOME")?).join("Library/Caches")
+    } else {
+        match non_empty("XDG_CACHE_HOME") {
+            Some(cache_home) => PathBuf::from(cache_home),
+            None => PathBuf::from(non_empty("HOME")?).join(".cache"),
+        }
+    };
+
+    Some(base.join("matmul-utils"))
+}
+
+impl ProcessorInfo {
+    // The fingerprint of the machine before any caches are known, so a run
+    // can look up its result before detecting anything. A pinned process
+    // sees other caches, so its affinity is part of the key
+    fn cached_detection_path(&self, dir: &Path, allowed_cpus: Option<&[usize]>) -> PathBuf {
+        let machine = ProcessorInfo {
+            architecture: self.architecture.clone(),
+            model_name: self.model_name.clone(),
+            ..ProcessorInfo::new()
+        };
+
+        let name = match allowed_cpus {
+            Some(cpus) => format!(
+                "{}-cpus-{}.json",
+                machine.fingerprint(),
+                format_cpu_list(cpus)
+            ),
+            None => format!("{}.json", machine.fingerprint()),
+        };
+        dir.join(name)
+    }
+
+    /// Takes the detection results a previous run stored for this machine,
+    /// if they are younger than `CACHED_DETECTION_MAX_AGE`
+    ///
+    /// Call after `detect_architecture` in place of `collect_cache_info`;
+    /// returns false, leaving `self` alone, when there is nothing usable.
+    pub fn load_cached_detection(&mut self) -> bool {
+        cache_dir().is_some_and(|dir| self.load_cached_detection_from(&dir))
+    }
+
+    // `load_cached_detection` from results stored under `dir`
+    pub(crate) fn load_cached_detection_from(&mut self, dir: &Path) -> bool {
+        let allowed_cpus = if cfg!(target_os = "linux") {
+            self.read_allowed_cpus(
+                &self
+                    .sysfs_root
+                    .clone()
+                    .unwrap_or_else(|| "/sys".to_string()),
+            )
+        } else {
+            None
+        };
+        let path = self.cached_detection_path(dir, allowed_cpus.as_deref());
+
+        let fresh = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_some_and(|age| age < CACHED_DETECTION_MAX_AGE);
+        if !fresh {
+            return false;
+        }
+
+        let Some(cached) = fs::read_to_string(&path)
+            .ok()
+            .and_then(|contents| Json::parse(&contents))
+            .and_then(|json| ProcessorInfo::from_json(&json))
+            .filter(|cached| {
+                cached.architecture == self.architecture && cached.model_name == self.model_name
+            })
+        else {
+            return false;
+        };
+
+        // Everything detected comes from the cache, only what this run was
+        // asked to do is kept
+        *self = ProcessorInfo {
+            raw_log: mem::take(&mut self.raw_log),
+            sysfs_root: self.sysfs_root.take(),
+            proc_root: self.proc_root.take(),
+            strict: self.strict,
+            stats: self.stats,
+            level_order: self.level_order,
+            element_dtype: self.element_dtype.take(),
+            ..cached
+        };
+        self.raw_log
+            .note(&format!("Loaded cached detection from {}", path.display()));
+
+        true
+    }
+
+    /// Stores the detection results for `load_cached_detection`, returning
+    /// where they went
+    pub fn store_cached_detection(&self) -> io::Result<PathBuf> {
+        let dir = cache_dir()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no user cache directory"))?;
+        self.store_cached_detection_in(&dir)
+    }
+
+    // `store_cached_detection` under `dir`
+    pub(crate) fn store_cached_detection_in(&self, dir: &Path) -> io::Result<PathBuf> {
+        let path = self.cached_detection_path(dir, self.allowed_cpus.as_deref());
+
+        fs::create_dir_all(dir)?;
+        fs::write(&path, self.to_json().render())?;
+
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::{LevelKey, ProcessorLevel};
+    use crate::processor::{HugepageInfo, LevelOrder, X86Signature};
+
+    fn temp_cache_dir(name: &str) -> PathBuf {
+        env::temp_dir().join(format!(
+            "matmul-utils-cache-{}-{}",
+            name,
+            std::process::id()
+        ))
+    }
+
+    #[test]
+    fn reuses_a_stored_detection() {
+        let dir = temp_cache_dir("reuse");
+
+        let mut detected = ProcessorInfo::new();
+        detected.model_name = "Cached CPU".to_string();
+        let mut level = ProcessorLevel::new(LevelKey::Default);
+        level.l2_cache = 2 * 1024 * 1024;
+        detected.performance_levels.insert(LevelKey::Default, level);
+        let path = detected.store_cached_detection_in(&dir);
+
+        let mut same_machine = ProcessorInfo::new();
+        same_machine.model_name = "Cached CPU".to_string();
+        let loaded = same_machine.load_cached_detection_from(&dir);
+
+        let mut other_machine = ProcessorInfo::new();
+        other_machine.model_name = "Other CPU".to_string();
+        let loaded_other = other_machine.load_cached_detection_from(&dir);
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(path.unwrap().starts_with(&dir));
+        assert!(loaded);
+        assert_eq!(same_machine.l2_cache(), 2 * 1024 * 1024);
+        assert!(!loaded_other);
+        assert!(other_machine.performance_levels.is_empty());
+    }
+
+    #[test]
+    fn restores_every_stored_field_and_keeps_this_runs_options() {
+        let dir = temp_cache_dir("fields");
+
+        let mut detected = ProcessorInfo::new();
+        detected.model_name = "Cached CPU".to_string();
+        let mut level = ProcessorLevel::new(LevelKey::Performance);
+        level.l1_cache.data_size = 48 * 1024;
+        level.l2_cache = 2 * 1024 * 1024;
+        detected
+            .performance_levels
+            .insert(LevelKey::Performance, level);
+        detected.translated = true;
+        detected.effective_cpus = Some(2.5);
+        detected.x86_signature = Some(X86Signature {
+            family: 6,
+            model: 143,
+            stepping: 8,
+        });
+        detected.memory_bandwidth_gbps = Some(12.5);
+        detected.memory_channels = Some(8);
+        detected.hugepages = vec![HugepageInfo {
+            size: 2 * 1024 * 1024,
+            total: 4,
+            free: 2,
+            default: true,
+        }];
+        detected.assumed_defaults = vec!["Performance Cores: L3".to_string()];
+        detected.memory_encryption = Some("TME".to_string());
+        detected.matrix_extensions = vec!["AMX-TILE".to_string()];
+        detected.llc_shared_with_gpu = Some(false);
+        detected.emulated = Some("KVM".to_string());
+        detected.allowed_cpus = Some(vec![0, 1]);
+        detected.os_name = "Linux".to_string();
+        detected.kernel_version = "6.8.0".to_string();
+        detected.nontemporal_stores = true;
+        detected.limited_by_privileges = true;
+        detected.max_turbo_hz = Some(5_000_000_000);
+        detected.all_core_turbo_hz = Some(4_000_000_000);
+        detected.sve_vector_bits = Some(256);
+        detected.page_size = 4096;
+        detected.physical_cores = Some(8);
+        detected.logical_cores = Some(16);
+        detected.total_memory = Some(32 * 1024 * 1024 * 1024);
+        // Keyed as a run that isn't pinned finds it, whatever this one's
+        // affinity
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            detected.cached_detection_path(&dir, None),
+            detected.to_json().render(),
+        )
+        .unwrap();
+
+        let mut run = ProcessorInfo::new();
+        run.model_name = "Cached CPU".to_string();
+        // No sysfs there, so no affinity is read
+        run.sysfs_root = Some(dir.join("sys").to_string_lossy().into_owned());
+        run.stats = true;
+        run.level_order = LevelOrder::Size;
+        run.element_dtype = Some("f32".to_string());
+        run.raw_log.verbose = true;
+        let loaded = run.load_cached_detection_from(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(loaded);
+        assert_eq!(run.to_json(), detected.to_json());
+        assert!(run.sysfs_root.is_some());
+        assert!(run.stats);
+        assert_eq!(run.level_order, LevelOrder::Size);
+        assert_eq!(run.element_dtype.as_deref(), Some("f32"));
+        assert!(run.raw_log.verbose);
+    }
+}
@@ -0,0 +1,5873 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::process::Command;
+#[cfg(windows)]
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+#[cfg(windows)]
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "bench-detect")]
+use crate::bench;
+#[cfg(all(feature = "cpuid", any(target_arch = "x86", target_arch = "x86_64")))]
+use crate::cache::TlbInfo;
+use crate::cache::{LevelKey, ProcessorLevel, Source};
+#[cfg(all(feature = "cpuid", any(target_arch = "x86", target_arch = "x86_64")))]
+use crate::cpuid::{self, CpuidCacheKind};
+use crate::debug;
+use crate::error::CacheError;
+#[cfg(feature = "hwloc")]
+use crate::hwloc;
+use crate::json::{Json, JSON_SCHEMA_VERSION};
+use crate::parse::{format_cpu_list, parse_cpu_list, parse_cpu_mask};
+use crate::provider::{detect_with, providers};
+use crate::size::{format_size, try_parse_size_with_unit, CacheSize};
+#[cfg(feature = "sysinfo")]
+use crate::sysinfo;
+#[cfg(feature = "bench-detect")]
+use crate::tiling::MatrixShape;
+use crate::tiling::{dtype_size, BlockSizes};
+use crate::timeout;
+
+// OS interactions shared between detection and `--plan`
+const SYSFS_CACHE_INDICES: usize = 10;
+const SYSFS_CACHE_FILES: [&str; 9] = [
+    "level",
+    "type",
+    "size",
+    "coherency_line_size",
+    "number_of_sets",
+    "ways_of_associativity",
+    "shared_cpu_list",
+    "shared_cpu_map",
+    "id",
+];
+const PERFLEVEL_SYSCTLS: [&str; 6] = [
+    "logicalcpu",
+    "l1icachesize",
+    "l1dcachesize",
+    "l2cachesize",
+    "cpusperl2",
+    "l3cachesize",
+];
+const INTEL_MAC_SYSCTLS: [&str; 6] = [
+    "hw.l1cachesize",
+    "hw.l1icachesize",
+    "hw.l1dcachesize",
+    "hw.l2cachesize",
+    "hw.l3cachesize",
+    "hw.cachelinesize",
+];
+const WMIC_CPU_NAME: &[&str] = &["cpu", "get", "name", "/value"];
+const WMIC_CPU_CACHES: &[&str] = &[
+    "cpu",
+    "get",
+    "L1CacheSize,L2CacheSize,L3CacheSize",
+    "/value",
+];
+const CPU_NAME_REGISTRY: (&str, &str) = (
+    r"HKLM\HARDWARE\DESCRIPTION\System\CentralProcessor\0",
+    "ProcessorNameString",
+);
+const KSTAT_CPU_INFO: &[&str] = &["-p", "cpu_info"];
+
+/// Raw values returned by the OS, kept for the `--raw` dump
+///
+/// With `verbose` set it also narrates each query and detection decision on
+/// stderr as it happens, for `--verbose`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RawLog {
+    entries: Vec<(String, String)>,
+    pub verbose: bool,
+    // Some read failed with EACCES or its equivalent
+    permission_denied: bool,
+    // Entries before the first cache collection, which later ones keep
+    collection_start: Option<usize>,
+}
+
+impl RawLog {
+    pub(crate) fn record(&mut self, source: &str, value: &str) {
+        if self.verbose {
+            match value.trim() {
+                "" => eprintln!("{} not found, reporting 0", source),
+                trimmed if trimmed.starts_with("<error") => eprintln!("{}: {}", source, trimmed),
+                trimmed => eprintln!("querying {} = {}", source, trimmed),
+            }
+        }
+
+        debug::record(source, value);
+        self.entries.push((source.to_string(), value.to_string()));
+    }
+
+    // Drops what an earlier collection recorded, keeping what came before it
+    fn start_collection(&mut self) {
+        match self.collection_start {
+            Some(start) => self.entries.truncate(start),
+            None => self.collection_start = Some(self.entries.len()),
+        }
+        self.permission_denied = false;
+    }
+
+    /// One line of the `--verbose` narrative
+    pub(crate) fn note(&self, message: &str) {
+        if self.verbose {
+            eprintln!("{}", message);
+        }
+    }
+
+    pub fn format(&self) -> Vec<String> {
+        let mut result = Vec::new();
+
+        result.push("Raw Values:".to_string());
+        result.push("===========".to_string());
+
+        for (source, value) in &self.entries {
+            result.push(format!("{} = {:?}", source, value));
+        }
+
+        result
+    }
+}
+
+/// Detects the architecture, CPU model and cache hierarchy of this machine
+///
+/// Values the OS doesn't report come back as 0 ("Not detected") instead of
+/// failing; only an unsupported OS or a failed detection as a whole is an
+/// error.
+///
+/// Tries every `CacheProvider` of this build, most accurate first, see
+/// `providers`.
+pub fn detect() -> Result<ProcessorInfo, CacheError> {
+    detect_with(&providers())
+}
+
+// macOS numbers the CPUs of the slowest tier first, so counting up from the
+// last perflevel gives each tier its CPUs, cut into clusters of cpusperl2.
+// Left out entirely unless every tier reports both counts
+fn assign_l2_clusters(levels: &mut [(LevelKey, ProcessorLevel)], logical_cpus: &[usize]) {
+    let known = levels.len() == logical_cpus.len()
+        && logical_cpus.iter().all(|&cpus| cpus > 0)
+        && levels.iter().all(|(_, level)| level.l2_shared_cores > 0);
+    if !known {
+        return;
+    }
+
+    let mut first_cpu = 0;
+    for ((_, level), &cpus) in levels.iter_mut().zip(logical_cpus).rev() {
+        let tier: Vec<usize> = (first_cpu..first_cpu + cpus).collect();
+        level.l2_clusters = tier
+            .chunks(level.l2_shared_cores)
+            .map(<[usize]>::to_vec)
+            .collect();
+        first_cpu += cpus;
+    }
+}
+
+// The cache-bypassing store of the target, if it is known to have one:
+// MOVNTI is SSE2, baseline on x86_64, and STNP is baseline ARMv8-A
+fn nontemporal_store_instruction() -> Option<&'static str> {
+    #[cfg(target_arch = "x86_64")]
+    return Some("MOVNT");
+
+    #[cfg(target_arch = "x86")]
+    return std::arch::is_x86_feature_detected!("sse2").then_some("MOVNT");
+
+    #[cfg(target_arch = "aarch64")]
+    return Some("STNP");
+
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+    None
+}
+
+// The L1 data cache indexing the ISA documents: Intel and AMD index it with
+// the page offset bits while tagging it physically, and AArch64 requires data
+// caches to behave as physically indexed and tagged. 32-bit ARM is left out,
+// ARMv6 cores still had aliasing VIPT ones
+fn l1_data_indexing(architecture: &str) -> Option<&'static str> {
+    match architecture {
+        "x86" => Some("VIPT"),
+        "ARM" | "ARM (Windows)" | "Apple Silicon" if !cfg!(target_arch = "arm") => Some("PIPT"),
+        _ => None,
+    }
+}
+
+/// Whether this process runs as root or an elevated Administrator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrivilegeLevel {
+    Elevated,
+    Unprivileged,
+    /// The OS has no notion of it this build can check
+    Unknown,
+}
+
+/// This process's privileges: the effective user id on Unix, the token's
+/// elevation on Windows
+pub fn privilege_level() -> PrivilegeLevel {
+    // SAFETY: geteuid takes nothing and can't fail
+    #[cfg(unix)]
+    return match unsafe { geteuid() } {
+        0 => PrivilegeLevel::Elevated,
+        _ => PrivilegeLevel::Unprivileged,
+    };
+
+    // SAFETY: IsUserAnAdmin takes nothing and only inspects our token
+    #[cfg(windows)]
+    return match unsafe { IsUserAnAdmin() } {
+        0 => PrivilegeLevel::Unprivileged,
+        _ => PrivilegeLevel::Elevated,
+    };
+
+    #[cfg(not(any(unix, windows)))]
+    PrivilegeLevel::Unknown
+}
+
+#[cfg(unix)]
+extern "C" {
+    fn geteuid() -> u32;
+}
+
+static SUBPROCESSES_ALLOWED: AtomicBool = AtomicBool::new(true);
+
+/// Whether detection may run helper tools (sysctl, wmic, lscpu, lstopo, ...)
+/// for the rest of the process; some sandboxes kill a process that forks or
+/// execs
+///
+/// Without them detection relies on files, system calls and CPUID: macOS
+/// reads its sysctls through `sysctlbyname` and Windows asks
+/// GetLogicalProcessorInformation. What only a tool reports stays undetected.
+pub fn set_subprocesses_allowed(allowed: bool) {
+    SUBPROCESSES_ALLOWED.store(allowed, Ordering::Relaxed);
+}
+
+pub fn subprocesses_allowed() -> bool {
+    SUBPROCESSES_ALLOWED.load(Ordering::Relaxed)
+}
+
+// Checked before spawning anything, so a disallowed `program` fails like a
+// broken one instead of reaching fork
+pub(crate) fn check_subprocess(program: &str) -> io::Result<()> {
+    if subprocesses_allowed() {
+        return Ok(());
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("{} not run, subprocesses are disabled", program),
+    ))
+}
+
+#[cfg(windows)]
+#[link(name = "shell32")]
+extern "system" {
+    fn IsUserAnAdmin() -> i32;
+}
+
+// A sysfs cache `type` as "Data", "Instruction" or "Unified", whatever its
+// case; some kernels and vendor trees use other spellings of the three
+fn normalize_cache_type(raw: &str) -> Option<&'static str> {
+    match raw.trim().to_ascii_lowercase().as_str() {
+        "data" | "d" | "dcache" => Some("Data"),
+        "instruction" | "inst" | "i" | "icache" | "code" => Some("Instruction"),
+        "unified" | "u" | "ucache" | "combined" => Some("Unified"),
+        _ => None,
+    }
+}
+
+#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+const PR_SVE_GET_VL: i32 = 51;
+#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+const PR_SVE_VL_LEN_MASK: usize = 0xffff;
+
+#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+extern "C" {
+    fn prctl(option: i32, arg2: u64, arg3: u64, arg4: u64, arg5: u64) -> i32;
+}
+
+// Filled by the first `ProcessorInfo::shared` call
+static SHARED: OnceLock<Arc<ProcessorInfo>> = OnceLock::new();
+
+/// Byte order of the target
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    #[default]
+    Little,
+    Big,
+}
+
+impl Endian {
+    pub fn native() -> Self {
+        if cfg!(target_endian = "big") {
+            Endian::Big
+        } else {
+            Endian::Little
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Endian::Little => "little",
+            Endian::Big => "big",
+        }
+    }
+}
+
+/// How `display()` and the tiling report order the levels
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LevelOrder {
+    /// Fastest first, as `levels_by_tier`
+    #[default]
+    Tier,
+    /// By level name
+    Name,
+    /// Largest L2 first, ties in tier order
+    Size,
+}
+
+impl LevelOrder {
+    /// "tier", "name" or "size", as `--sort` takes them
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "tier" => Some(LevelOrder::Tier),
+            "name" => Some(LevelOrder::Name),
+            "size" => Some(LevelOrder::Size),
+            _ => None,
+        }
+    }
+}
+
+/// What `render` adds to the detected values
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DisplayOptions {
+    /// Each cache's share of its level's total
+    pub stats: bool,
+    pub level_order: LevelOrder,
+    /// "f32" or "f64" to show how many of them each cache holds
+    pub element_dtype: Option<String>,
+}
+
+/// x86 family, model and stepping as the kernel decodes them from CPUID
+/// leaf 1, the keys of microarchitecture dispatch tables
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct X86Signature {
+    pub family: u32,
+    pub model: u32,
+    pub stepping: u32,
+}
+
+/// One hugepage size the kernel offers, from /sys/kernel/mm/hugepages
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct HugepageInfo {
+    /// Page size in bytes
+    pub size: usize,
+    /// Pages reserved in the pool (`nr_hugepages`)
+    pub total: usize,
+    /// Reserved pages not yet in use (`free_hugepages`)
+    pub free: usize,
+    /// The size `MAP_HUGETLB` gets without asking for one, per /proc/meminfo
+    pub default: bool,
+}
+
+/// Where one logical CPU sits in the machine, for thread pinning
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CpuTopology {
+    pub cpu: usize,
+    /// Socket and core id as the kernel numbers them, None when unknown
+    pub package: Option<usize>,
+    pub core: Option<usize>,
+    pub numa_node: Option<usize>,
+    /// CPUs sharing this CPU's L2 and L3, itself included
+    pub l2_shared_cpus: Vec<usize>,
+    pub l3_shared_cpus: Vec<usize>,
+}
+
+/// The handful of numbers most tuning code needs, see
+/// `ProcessorInfo::summary`; 0 for anything not detected
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    /// Primary level caches in bytes, L1 data being the unified L1 on CPUs
+    /// without a split one
+    pub l1_data: usize,
+    pub l2: usize,
+    pub l3: usize,
+    /// `ProcessorInfo::max_line_size`
+    pub line_size: usize,
+    /// Logical CPUs this process may run on
+    pub logical_cpus: usize,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ProcessorInfo {
+    pub architecture: String,
+    pub model_name: String,
+    pub performance_levels: BTreeMap<LevelKey, ProcessorLevel>,
+    pub raw_log: RawLog,
+    /// Mount point of a (possibly captured) sysfs tree, `/sys` when unset
+    pub sysfs_root: Option<String>,
+    /// Mount point of a procfs to read instead of `/proc`, like another
+    /// container's bind-mounted one
+    pub proc_root: Option<String>,
+    /// x86_64 binary running under Rosetta 2 on Apple Silicon
+    pub translated: bool,
+    /// CPUs' worth of time the cgroup CPU quota allows, None when unlimited
+    pub effective_cpus: Option<f64>,
+    pub endianness: Endian,
+    /// Pointer width in bits
+    pub pointer_width: usize,
+    /// None on non-x86 CPUs or when the OS doesn't report it
+    pub x86_signature: Option<X86Signature>,
+    /// Single-thread STREAM Triad bandwidth, None unless measured
+    pub memory_bandwidth_gbps: Option<f64>,
+    /// Populated memory channels across all sockets, from the SMBIOS memory
+    /// devices; None unless `detect_memory_channels` could tell
+    pub memory_channels: Option<usize>,
+    /// Hugepage pools by size, empty outside Linux or without hugetlbfs
+    pub hugepages: Vec<HugepageInfo>,
+    /// Cores, SMT threads and RAM of the whole machine; None unless built
+    /// with the `sysinfo` feature
+    pub physical_cores: Option<usize>,
+    pub logical_cores: Option<usize>,
+    pub total_memory: Option<u64>,
+    /// Zero cache sizes that break L1 <= L2 <= L3 instead of only warning
+    pub strict: bool,
+    /// Caches `assume_defaults` filled in, as "Default: L2, L3"
+    pub assumed_defaults: Vec<String>,
+    /// Show each cache's share of its level's total in `display()`
+    pub stats: bool,
+    /// Memory encryption the CPU supports, like "SME, SEV" or "TME"; None
+    /// when there is none or it can't be told
+    pub memory_encryption: Option<String>,
+    /// Matrix units the CPU has, like "AMX-TILE" and "AMX-BF16"; empty when
+    /// it has none or they can't be told
+    pub matrix_extensions: Vec<String>,
+    /// Whether the integrated GPU allocates in the last-level cache, leaving
+    /// the CPU less of it under load; None when it can't be told
+    pub llc_shared_with_gpu: Option<bool>,
+    /// Hypervisor or emulator the CPU is virtualized by, like "KVM" or
+    /// "QEMU TCG"; its cache numbers may be made up
+    pub emulated: Option<String>,
+    /// CPUs the affinity mask allows when it leaves out some online ones;
+    /// caches are then those of the first of them, shared among these only
+    pub allowed_cpus: Option<Vec<usize>>,
+    /// Kernel name and release, like "Linux" and "6.8.0-45-generic"; empty
+    /// when unknown
+    pub os_name: String,
+    pub kernel_version: String,
+    /// Order of the levels in `display()`
+    pub level_order: LevelOrder,
+    /// "f32" or "f64" to show how many of them each cache holds in
+    /// `display()`
+    pub element_dtype: Option<String>,
+    /// Whether this build can stream stores past the caches (MOVNT on x86,
+    /// STNP on AArch64), worth it for a C matrix too large to stay cached
+    pub nontemporal_stores: bool,
+    /// Whether the last detection ran unprivileged and was refused some
+    /// source, like resctrl, that root or Administrator could read
+    pub limited_by_privileges: bool,
+    /// Highest clock any core reaches, from cpufreq
+    pub max_turbo_hz: Option<u64>,
+    /// Estimated clock with every core busy, the one a multithreaded matmul
+    /// sustains; None when cpufreq gives nothing to estimate it from
+    pub all_core_turbo_hz: Option<u64>,
+    /// SVE vector length in bits on AArch64 Linux, the width SVE kernels
+    /// tile their registers for; None without SVE
+    pub sve_vector_bits: Option<usize>,
+    /// Base page size in bytes, 16 KB on Apple Silicon and 4 KB on most
+    /// others; 0 when unknown, as for a captured tree
+    pub page_size: usize,
+}
+
+impl ProcessorInfo {
+    pub fn new() -> Self {
+        Self {
+            architecture: env::consts::ARCH.to_string(),
+            endianness: Endian::native(),
+            pointer_width: usize::BITS as usize,
+            ..Default::default()
+        }
+    }
+
+    /// This machine's detection, run once on first access and shared by
+    /// every caller after it
+    ///
+    /// Cloning the `Arc` is cheap, so worker threads can each keep a handle.
+    /// Concurrent first calls block until the one running detection is
+    /// done. When detection fails the result has the architecture and model
+    /// but no levels, like on an unsupported OS.
+    pub fn shared() -> Arc<ProcessorInfo> {
+        SHARED
+            .get_or_init(|| {
+                Arc::new(detect().unwrap_or_else(|_| {
+                    let mut processor = ProcessorInfo::new();
+                    processor.detect_architecture();
+                    processor
+                }))
+            })
+            .clone()
+    }
+
+    pub fn detect_architecture(&mut self) -> &mut Self {
+        // A new detection, whose raw log starts over
+        self.raw_log.entries.clear();
+        self.raw_log.collection_start = None;
+
+        self.architecture = match self.architecture.as_str() {
+            "x86" | "x86_64" => {
+                // Under Rosetta the hardware (and its sysctl keys) stays Apple Silicon
+                self.translated = self.detect_translation();
+                if self.translated {
+                    "Apple Silicon".to_string()
+                } else {
+                    "x86".to_string()
+                }
+            }
+            "aarch64" | "arm" | "arm64" => self.detect_arm_type(),
+            "powerpc" | "powerpc64" => "PowerPC".to_string(),
+            _ => format!("Unknown: {}", self.architecture),
+        };
+        self.raw_log.note(&format!(
+            "Detected {} (target arch {})",
+            self.architecture,
+            env::consts::ARCH
+        ));
+        self.raw_log
+            .note(&format!("Units: {}", unit_assumptions().join("; ")));
+
+        #[cfg(feature = "sysinfo")]
+        self.detect_system_summary();
+        self.detect_model_name();
+        self.detect_os_version();
+        self.nontemporal_stores = nontemporal_store_instruction().is_some();
+        self.sve_vector_bits = self.detect_sve_vector_bits();
+        self.page_size = self.detect_page_size();
+        self.detect_memory_encryption();
+        self.detect_matrix_extensions();
+        self.llc_shared_with_gpu = self.detect_llc_shared_with_gpu();
+        self.detect_emulation();
+        self.checkpoint();
+        self
+    }
+
+    // Progress for `install_debug_hook` and `detect_with_timeout`
+    fn checkpoint(&self) {
+        debug::checkpoint(self);
+        timeout::checkpoint(self);
+    }
+
+    // The page size belongs to the running kernel, a captured tree says
+    // nothing about its own
+    fn detect_page_size(&mut self) -> usize {
+        if self.sysfs_root.is_some() || self.proc_root.is_some() {
+            return 0;
+        }
+
+        #[cfg(target_os = "macos")]
+        if let Ok(value) = run_sysctl("hw.pagesize", &mut self.raw_log) {
+            return parse_sysctl_number(&value);
+        }
+
+        let page_size = system_page_size();
+        self.raw_log.record("page size", &page_size.to_string());
+        page_size
+    }
+
+    // Linux lets each process pick its SVE vector length up to what the
+    // hardware has, so prctl gives ours. A captured procfs only has the
+    // default new processes start with, in bytes
+    fn detect_sve_vector_bits(&mut self) -> Option<usize> {
+        #[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+        if self.proc_root.is_none() {
+            // SAFETY: PR_SVE_GET_VL takes no pointers, the other arguments
+            // are ignored
+            let result = unsafe { prctl(PR_SVE_GET_VL, 0, 0, 0, 0) };
+            self.raw_log
+                .record("prctl(PR_SVE_GET_VL)", &result.to_string());
+
+            // EINVAL without SVE
+            return (result >= 0).then(|| (result as usize & PR_SVE_VL_LEN_MASK) * 8);
+        }
+
+        if !cfg!(target_os = "linux") && self.proc_root.is_none() {
+            return None;
+        }
+
+        let path = self.proc_path("sys/abi/sve_default_vector_length");
+        read_file(&path, &mut self.raw_log)
+            .ok()
+            .and_then(|bytes| bytes.trim().parse::<usize>().ok())
+            .map(|bytes| bytes * 8)
+    }
+
+    // What sysfs and sysctl report depends on the kernel, so bug reports
+    // need its version next to the hardware
+    fn detect_os_version(&mut self) {
+        if !cfg!(any(target_os = "macos", windows)) || self.proc_root.is_some() {
+            let ostype = self.proc_path("sys/kernel/ostype");
+            let osrelease = self.proc_path("sys/kernel/osrelease");
+            let mut read = |path: &str| {
+                read_file(path, &mut self.raw_log)
+                    .ok()
+                    .map(|value| value.trim().to_string())
+                    .filter(|value| !value.is_empty())
+            };
+
+            if let (Some(name), Some(version)) = (read(&ostype), read(&osrelease)) {
+                self.os_name = name;
+                self.kernel_version = version;
+                return;
+            }
+
+            // uname would describe this machine, not the one the procfs is from
+            if self.proc_root.is_some() {
+                return;
+            }
+        }
+
+        #[cfg(target_os = "macos")]
+        if let (Ok(name), Ok(version)) = (
+            run_sysctl("kern.ostype", &mut self.raw_log),
+            run_sysctl("kern.osrelease", &mut self.raw_log),
+        ) {
+            self.os_name = name;
+            self.kernel_version = version;
+            return;
+        }
+
+        #[cfg(windows)]
+        if let Some(version) = windows_version(&mut self.raw_log) {
+            self.os_name = "Windows".to_string();
+            self.kernel_version = version;
+        }
+
+        // The BSDs and everything else without a procfs
+        #[cfg(not(windows))]
+        {
+            let mut uname = |flag: &str| {
+                let source = format!("uname {}", flag);
+                match run_tool("uname", &[flag]) {
+                    Ok(value) => {
+                        self.raw_log.record(&source, &value);
+                        Some(value.trim().to_string()).filter(|value| !value.is_empty())
+                    }
+                    Err(err) => {
+                        self.raw_log.record(&source, &format!("<error: {}>", err));
+                        None
+                    }
+                }
+            };
+
+            if let (Some(name), Some(version)) = (uname("-s"), uname("-r")) {
+                self.os_name = name;
+                self.kernel_version = version;
+            }
+        }
+    }
+
+    // The kernel's cpuinfo flags come first, CPUID covers everything else
+    // built with it
+    fn detect_memory_encryption(&mut self) {
+        #[cfg(all(feature = "cpuid", any(target_arch = "x86", target_arch = "x86_64")))]
+        if self.memory_encryption.is_none() && self.reads_live_proc() {
+            let features = cpuid::memory_encryption();
+            if !features.is_empty() {
+                self.raw_log
+                    .record("cpuid memory encryption", &features.join(", "));
+                self.memory_encryption = Some(features.join(", "));
+            }
+        }
+    }
+
+    // Like memory encryption, though the kernel only lists AMX once it
+    // enabled the tile state, which CPUID doesn't care about
+    fn detect_matrix_extensions(&mut self) {
+        #[cfg(all(feature = "cpuid", any(target_arch = "x86", target_arch = "x86_64")))]
+        if self.matrix_extensions.is_empty() && self.reads_live_proc() {
+            let extensions = cpuid::matrix_extensions();
+            if !extensions.is_empty() {
+                self.raw_log
+                    .record("cpuid matrix extensions", &extensions.join(", "));
+                self.matrix_extensions = extensions.into_iter().map(String::from).collect();
+            }
+        }
+    }
+
+    // Apple's system level cache serves the GPU too. Intel's client iGPU,
+    // always at PCI 00:02.0, allocates in the CPU's L3, while AMD's APUs,
+    // named "with Radeon Graphics" or "w/ Radeon 780M Graphics", reach
+    // memory past it through the data fabric
+    fn detect_llc_shared_with_gpu(&mut self) -> Option<bool> {
+        // The architecture is this host's, not a captured tree's
+        if self.architecture == "Apple Silicon" && self.sysfs_root.is_none() {
+            return Some(true);
+        }
+        if self.model_name.starts_with("AMD") && self.model_name.contains("Radeon") {
+            return Some(false);
+        }
+
+        let sysfs_root = self.sysfs_root.as_deref().unwrap_or("/sys");
+        let device = format!("{}/bus/pci/devices/0000:00:02.0", sysfs_root);
+        let mut read =
+            |file: &str| read_file(&format!("{}/{}", device, file), &mut self.raw_log).ok();
+        let (vendor, class) = (read("vendor")?, read("class")?);
+
+        // Display controllers are class 0x03xxxx
+        (vendor.trim() == "0x8086" && class.trim().starts_with("0x03")).then_some(true)
+    }
+
+    // CPUID names the hypervisor exactly, DMI only tells a QEMU machine
+    // and /sys/hypervisor a Xen guest
+    fn detect_emulation(&mut self) {
+        #[cfg(all(feature = "cpuid", any(target_arch = "x86", target_arch = "x86_64")))]
+        if self.sysfs_root.is_none() {
+            self.emulated = cpuid::hypervisor();
+            if let Some(hypervisor) = &self.emulated {
+                self.raw_log.record("cpuid hypervisor vendor", hypervisor);
+                return;
+            }
+        }
+
+        let sysfs_root = self.sysfs_root.as_deref().unwrap_or("/sys");
+        for (file, expected, name) in [
+            ("class/dmi/id/sys_vendor", "QEMU", "QEMU"),
+            ("hypervisor/type", "xen", "Xen"),
+        ] {
+            let path = format!("{}/{}", sysfs_root, file);
+            // Most machines have neither, so a missing file isn't logged
+            if let Ok(content) = fs::read_to_string(&path) {
+                self.raw_log.record(&path, &content);
+                if content.trim() == expected {
+                    self.emulated = Some(name.to_string());
+                    return;
+                }
+            }
+        }
+    }
+
+    fn detect_translation(&mut self) -> bool {
+        #[cfg(target_os = "macos")]
+        {
+            if let Ok(value) = run_sysctl("sysctl.proc_translated", &mut self.raw_log) {
+                return value == "1";
+            }
+        }
+
+        false
+    }
+
+    fn detect_arm_type(&mut self) -> String {
+        #[cfg(target_os = "macos")]
+        {
+            if let Ok(cpu_info) = run_sysctl("machdep.cpu.brand_string", &mut self.raw_log) {
+                if cpu_info.contains("Apple") {
+                    return "Apple Silicon".to_string();
+                }
+            }
+        }
+
+        if cfg!(all(windows, target_arch = "aarch64")) {
+            "ARM (Windows)".to_string()
+        } else {
+            "ARM".to_string()
+        }
+    }
+
+    // The `sysinfo` feature's answers win; a model name it found spares the
+    // per-OS readers below from asking for it again
+    #[cfg(feature = "sysinfo")]
+    fn detect_system_summary(&mut self) {
+        if self.sysfs_root.is_some() || self.proc_root.is_some() {
+            return;
+        }
+
+        let summary = sysinfo::system_summary();
+        self.raw_log
+            .record("sysinfo system summary", &format!("{:?}", summary));
+        if let Some(model) = summary.model_name {
+            self.model_name = model;
+        }
+        self.physical_cores = summary.physical_cores;
+        self.logical_cores = summary.logical_cores;
+        self.total_memory = summary.total_memory;
+    }
+
+    fn detect_model_name(&mut self) {
+        // Anything with a Linux-style procfs, Hurd included, has this file
+        if !cfg!(any(target_os = "macos", windows)) || self.proc_root.is_some() {
+            if let Ok(contents) = read_file(&self.proc_path("cpuinfo"), &mut self.raw_log) {
+                self.apply_cpuinfo(&contents);
+            }
+        }
+
+        // Another machine's procfs describes that machine, not this Mac
+        #[cfg(target_os = "macos")]
+        if self.proc_root.is_none() {
+            if self.model_name.is_empty() {
+                if let Ok(model) = run_sysctl("machdep.cpu.brand_string", &mut self.raw_log) {
+                    self.model_name = model;
+                }
+            }
+
+            // Only Intel Macs have these; under Rosetta they'd describe a
+            // made-up CPU
+            if !self.translated {
+                let mut read = |key| {
+                    run_sysctl(key, &mut self.raw_log)
+                        .ok()
+                        .and_then(|value| value.trim().parse::<u32>().ok())
+                };
+
+                if let (Some(family), Some(model), Some(stepping)) = (
+                    read("machdep.cpu.family"),
+                    read("machdep.cpu.model"),
+                    read("machdep.cpu.stepping"),
+                ) {
+                    self.x86_signature = Some(X86Signature {
+                        family,
+                        model,
+                        stepping,
+                    });
+                }
+            }
+        }
+
+        // wmic is deprecated and missing from recent Windows builds, the
+        // registry has the same brand string
+        #[cfg(windows)]
+        if self.model_name.is_empty() {
+            let source = format!(
+                "RegGetValueW {}\\{}",
+                CPU_NAME_REGISTRY.0, CPU_NAME_REGISTRY.1
+            );
+            match registry_cpu_name() {
+                Ok(model) => {
+                    self.raw_log.record(&source, &model);
+                    self.model_name = model;
+                    return;
+                }
+                Err(err) => self.raw_log.record(&source, &format!("<error: {}>", err)),
+            }
+        }
+
+        #[cfg(windows)]
+        if self.model_name.is_empty() {
+            if let Some(model) = run_wmic(WMIC_CPU_NAME, &mut self.raw_log)
+                .as_deref()
+                .and_then(parse_wmic_name)
+            {
+                self.model_name = model;
+            }
+        }
+    }
+
+    pub fn collect_cache_info(&mut self) -> Result<&mut Self, CacheError> {
+        // Start from a clean slate so repeated detection is deterministic
+        self.performance_levels.clear();
+        self.raw_log.start_collection();
+        self.checkpoint();
+
+        let privilege = privilege_level();
+        if privilege == PrivilegeLevel::Unprivileged {
+            self.raw_log
+                .note("Running unprivileged, some sources may be unreadable");
+        }
+
+        self.collect_from_best_backend()?;
+        self.checkpoint();
+        self.check_hierarchy();
+        self.assign_l1_indexing();
+        self.limited_by_privileges =
+            privilege == PrivilegeLevel::Unprivileged && self.raw_log.permission_denied;
+
+        for level in self.performance_levels.values() {
+            for line in level.format_sources() {
+                self.raw_log
+                    .note(&format!("{}: {}", level.level_name, line));
+            }
+        }
+
+        Ok(self)
+    }
+
+    // Only the running ISA is known, a captured tree may come from another
+    fn assign_l1_indexing(&mut self) {
+        if self.sysfs_root.is_some() {
+            return;
+        }
+        let Some(indexing) = l1_data_indexing(&self.architecture) else {
+            return;
+        };
+
+        for level in self.performance_levels.values_mut() {
+            if level.l1_cache.data_size > 0 || level.l1_cache.unified_size > 0 {
+                level.l1_indexing = Some(indexing.to_string());
+            }
+        }
+        self.raw_log.note(&format!(
+            "L1 data cache is {} on {}",
+            indexing, self.architecture
+        ));
+    }
+
+    fn collect_from_best_backend(&mut self) -> Result<(), CacheError> {
+        // A captured sysfs tree can be analyzed from any host
+        if let Some(root) = &self.sysfs_root {
+            self.raw_log
+                .note(&format!("Reading captured sysfs tree at {}", root));
+            self.collect_linux_cache_info()?;
+            return Ok(());
+        }
+
+        #[cfg(feature = "hwloc")]
+        match self.collect_hwloc_cache_info() {
+            Ok(()) => return Ok(()),
+            Err(err) => self.raw_log.note(&format!(
+                "hwloc unavailable ({}), using native detection",
+                err
+            )),
+        }
+
+        let native = self.collect_native_cache_info();
+
+        // CPUID only describes the core this thread happens to run on, so
+        // trusting it over the OS would fold a hybrid CPU into one level
+        #[cfg(all(feature = "cpuid", any(target_arch = "x86", target_arch = "x86_64")))]
+        if native.is_err() || self.primary_level().is_none() {
+            match &native {
+                Err(err) => self
+                    .raw_log
+                    .note(&format!("native detection failed ({}), using CPUID", err)),
+                Ok(()) => self
+                    .raw_log
+                    .note("native detection found no caches, using CPUID"),
+            }
+
+            let native_levels = std::mem::take(&mut self.performance_levels);
+            if self.collect_cpuid_cache_info() {
+                return Ok(());
+            }
+            self.performance_levels = native_levels;
+        }
+
+        native
+    }
+
+    // Logs every level whose sizes break L1 <= L2 <= L3, and with `strict`
+    // zeroes the outer cache of each inverted pair so it can't feed tiling
+    fn check_hierarchy(&mut self) {
+        for level in self.performance_levels.values_mut() {
+            let Some(warning) = level.hierarchy_warning() else {
+                continue;
+            };
+
+            self.raw_log
+                .note(&format!("{}: {}", level.level_name, warning));
+            if self.strict {
+                level.drop_inverted_caches();
+            }
+        }
+    }
+
+    // The per-OS queries alone, without the hwloc and CPUID shortcuts
+    pub(crate) fn collect_native_cache_info(&mut self) -> Result<(), CacheError> {
+        match env::consts::OS {
+            "macos" => {
+                #[cfg(target_os = "macos")]
+                self.collect_macos_cache_info()
+                    .or_else(|err| self.recover_from_missing_tool(err))?;
+            }
+            "linux" => {
+                #[cfg(target_os = "linux")]
+                self.collect_linux_cache_info()?;
+            }
+            "windows" => {
+                #[cfg(all(windows, target_arch = "aarch64"))]
+                self.collect_windows_glpi_cache_info()?;
+                #[cfg(all(windows, not(target_arch = "aarch64")))]
+                self.collect_windows_cache_info()?;
+            }
+            "illumos" | "solaris" => {
+                #[cfg(any(target_os = "illumos", target_os = "solaris"))]
+                self.collect_illumos_cache_info()?;
+            }
+            os => {
+                if !self.collect_generic_cache_info() {
+                    return Err(CacheError::UnsupportedOs(os.to_string()));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // A missing sysctl or wmic only costs the values it would have given:
+    // note it, try the generic readers and report whatever they find. Other
+    // errors still fail detection
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    fn recover_from_missing_tool(&mut self, err: io::Error) -> io::Result<()> {
+        if err.kind() != io::ErrorKind::NotFound {
+            return Err(err);
+        }
+
+        self.raw_log
+            .note(&format!("{}, trying other detection methods", err));
+        self.performance_levels.clear();
+        self.collect_generic_cache_info();
+
+        Ok(())
+    }
+
+    // The model, x86 signature, memory encryption and matrix extensions a
+    // /proc/cpuinfo names, whichever machine its text came from
+    pub(crate) fn apply_cpuinfo(&mut self, contents: &str) {
+        if let Some(model) = parse_cpuinfo_model_name(contents) {
+            self.model_name = model.to_string();
+        }
+        self.x86_signature = parse_cpuinfo_signature(contents);
+        self.memory_encryption = parse_cpuinfo_memory_encryption(contents);
+        self.matrix_extensions = parse_cpuinfo_matrix_extensions(contents);
+    }
+
+    // x86 procfs implementations (Linux, Hurd's procfs translator) give one
+    // cache as "cache size : 8192 KB": the last level on Intel, the L2 on
+    // AMD and Hygon; false without one
+    pub(crate) fn insert_cpuinfo_cache_level(&mut self, contents: &str) -> bool {
+        let Some(size) = parse_cpuinfo_cache_size(contents) else {
+            return false;
+        };
+
+        let mut proc_level = ProcessorLevel::new(LevelKey::Default);
+        let amd = contents.lines().any(|line| {
+            line.split_once(':').is_some_and(|(key, value)| {
+                key.trim() == "vendor_id" && matches!(value.trim(), "AuthenticAMD" | "HygonGenuine")
+            })
+        });
+        if amd {
+            proc_level.l2_cache = size;
+            self.raw_log
+                .note("Only the L2 is known, from the AMD /proc/cpuinfo cache size");
+        } else {
+            proc_level.l3_cache = size;
+            self.raw_log.note(
+                "Only the last-level cache is known, from /proc/cpuinfo; reporting it as the L3",
+            );
+        }
+        proc_level.set_sources(Source::Fallback);
+        self.performance_levels
+            .insert(LevelKey::Default, proc_level);
+
+        true
+    }
+
+    // Best effort for OSes without a branch of their own (Haiku, GNU/Hurd,
+    // the BSDs, ...): a Linux-style sysfs, the flat hw.* sysctl keys Intel
+    // Macs use, then the "cache size" of /proc/cpuinfo. False when none of
+    // them reported any cache
+    fn collect_generic_cache_info(&mut self) -> bool {
+        self.raw_log
+            .note("No dedicated detection for this OS, trying sysfs, sysctl and /proc/cpuinfo");
+
+        let found_caches = |processor: &Self| {
+            processor.l1_data_cache() > 0 || processor.l2_cache() > 0 || processor.l3_cache() > 0
+        };
+
+        if fs::metadata("/sys/devices/system/cpu/cpu0/cache").is_ok()
+            && self.collect_linux_cache_info().is_ok()
+            && found_caches(self)
+        {
+            return true;
+        }
+        self.performance_levels.clear();
+
+        if self.collect_intel_mac_cache_info().is_ok() && found_caches(self) {
+            return true;
+        }
+        self.performance_levels.clear();
+
+        read_file(&self.proc_path("cpuinfo"), &mut self.raw_log)
+            .is_ok_and(|contents| self.insert_cpuinfo_cache_level(&contents))
+    }
+
+    #[cfg(target_os = "macos")]
+    fn collect_macos_cache_info(&mut self) -> io::Result<()> {
+        if self.architecture == "Apple Silicon" {
+            self.raw_log
+                .note("Apple Silicon: querying per performance level hw.perflevelN.* keys");
+            self.collect_apple_silicon_cache_info()
+        } else {
+            self.raw_log
+                .note("Intel Mac: querying flat hw.* cache keys");
+            self.collect_intel_mac_cache_info()
+        }
+    }
+
+    /// Every sysctl, file and command detection would touch on this platform,
+    /// without touching any of them, for `--plan`
+    pub fn detection_plan(&self) -> Vec<String> {
+        let mut plan = Vec::new();
+
+        let os = if self.sysfs_root.is_some() {
+            "linux"
+        } else {
+            env::consts::OS
+        };
+
+        #[cfg(feature = "hwloc")]
+        if self.sysfs_root.is_none() {
+            plan.push(format!(
+                "run {} {}, the native queries below only if that fails",
+                hwloc::LSTOPO_PROGRAMS.join(" or "),
+                hwloc::LSTOPO_ARGS.join(" ")
+            ));
+        }
+
+        if os == "macos" && env::consts::ARCH == "x86_64" {
+            plan.push("sysctl sysctl.proc_translated".to_string());
+        }
+
+        match os {
+            "macos" => {
+                plan.push("sysctl machdep.cpu.brand_string".to_string());
+                plan.push("sysctl kern.ostype and kern.osrelease".to_string());
+                plan.push("sysctl hw.pagesize".to_string());
+
+                if env::consts::ARCH == "aarch64" {
+                    for key in ["hw.nperflevels", "hw.l3cachesize", "hw.cachelinesize"] {
+                        plan.push(format!("sysctl {}", key));
+                    }
+                    for key in PERFLEVEL_SYSCTLS {
+                        plan.push(format!(
+                            "sysctl hw.perflevelN.{} for each N below hw.nperflevels",
+                            key
+                        ));
+                    }
+                    plan.push(
+                        "sysctl hw.l1icachesize, hw.l1dcachesize and hw.l2cachesize if no hw.perflevelN keys exist"
+                            .to_string(),
+                    );
+                    plan.push(
+                        "sysctl hw.l2cachesize if hw.perflevel0.l2cachesize is empty".to_string(),
+                    );
+                } else {
+                    for key in INTEL_MAC_SYSCTLS {
+                        plan.push(format!("sysctl {}", key));
+                    }
+                    plan.push(
+                        "sysctl hw.perflevel0.l2cachesize if hw.l2cachesize is empty".to_string(),
+                    );
+                }
+            }
+            "linux" => {
+                let sysfs_root = self.sysfs_root.as_deref().unwrap_or("/sys");
+
+                if self.reads_live_proc() {
+                    plan.push(format!("read {}", self.proc_path("cpuinfo")));
+                }
+                plan.push(format!(
+                    "read {0} and {1}",
+                    self.proc_path("sys/kernel/ostype"),
+                    self.proc_path("sys/kernel/osrelease")
+                ));
+                if env::consts::ARCH == "aarch64" && self.proc_root.is_none() {
+                    plan.push("call prctl(PR_SVE_GET_VL)".to_string());
+                } else {
+                    plan.push(format!(
+                        "read {}",
+                        self.proc_path("sys/abi/sve_default_vector_length")
+                    ));
+                }
+                plan.push(format!(
+                    "read {0}/class/dmi/id/sys_vendor and {0}/hypervisor/type",
+                    sysfs_root
+                ));
+                if self.reads_live_proc() {
+                    plan.push(format!(
+                        "read {} and {}/devices/system/cpu/online; with a restricted affinity the first allowed CPU replaces cpu0 below",
+                        self.proc_path("self/status"),
+                        sysfs_root
+                    ));
+                }
+                for i in 0..SYSFS_CACHE_INDICES {
+                    for file in SYSFS_CACHE_FILES {
+                        plan.push(format!(
+                            "read {}/devices/system/cpu/cpu0/cache/index{}/{}",
+                            sysfs_root, i, file
+                        ));
+                    }
+                }
+                plan.push(format!(
+                    "read {}/devices/system/cpu/cpuN/topology/{{physical_package_id,core_id}} for each CPU sharing the L3",
+                    sysfs_root
+                ));
+                plan.push(format!(
+                    "read {0}/fs/resctrl/info/L3/cbm_mask, {0}/fs/resctrl/*/tasks and the schemata of our group",
+                    sysfs_root
+                ));
+                plan.push(format!(
+                    "list {}/devices/system/edac/cpu/cpu0/L*/ce_count",
+                    sysfs_root
+                ));
+                if self.sysfs_root.is_none() {
+                    plan.push("run lscpu if sysfs reports no caches".to_string());
+                }
+                if self.reads_live_proc() {
+                    plan.push(format!("read {}", self.proc_path("self/cgroup")));
+                }
+                plan.push(format!(
+                    "read {0}/fs/cgroup/<group>/cpu.max, {0}/fs/cgroup/cpu/cpu.cfs_quota_us and cpu.cfs_period_us",
+                    sysfs_root
+                ));
+                if self.reads_live_proc() {
+                    plan.push(format!("read {}", self.proc_path("meminfo")));
+                }
+                plan.push(format!(
+                    "read {}/kernel/mm/hugepages/hugepages-*kB/{{nr_hugepages,free_hugepages}}",
+                    sysfs_root
+                ));
+                plan.push(format!(
+                    "read {0}/devices/system/cpu/cpuN/cpufreq/cpuinfo_max_freq for each CPU, {0}/devices/system/cpu/cpufreq/boost and {0}/devices/system/cpu/intel_pstate/no_turbo",
+                    sysfs_root
+                ));
+            }
+            "windows" if env::consts::ARCH == "aarch64" => {
+                plan.push(format!(
+                    "call RegGetValueW for {}\\{}",
+                    CPU_NAME_REGISTRY.0, CPU_NAME_REGISTRY.1
+                ));
+                plan.push(format!(
+                    "run wmic {} if the registry has no name",
+                    WMIC_CPU_NAME.join(" ")
+                ));
+                plan.push("call GetLogicalProcessorInformation".to_string());
+                plan.push("call RtlGetVersion".to_string());
+            }
+            "windows" => {
+                plan.push("call RtlGetVersion".to_string());
+                plan.push(format!(
+                    "call RegGetValueW for {}\\{}",
+                    CPU_NAME_REGISTRY.0, CPU_NAME_REGISTRY.1
+                ));
+                plan.push(format!(
+                    "run wmic {} if the registry has no name",
+                    WMIC_CPU_NAME.join(" ")
+                ));
+                plan.push(format!("run wmic {}", WMIC_CPU_CACHES.join(" ")));
+            }
+            "illumos" | "solaris" => {
+                plan.push("run uname -s and uname -r".to_string());
+                plan.push(format!("run kstat {}", KSTAT_CPU_INFO.join(" ")));
+            }
+            _ => {
+                plan.push(format!(
+                    "read {} and {}, or run uname -s and uname -r",
+                    self.proc_path("sys/kernel/ostype"),
+                    self.proc_path("sys/kernel/osrelease")
+                ));
+                plan.push(
+                    "read /sys/devices/system/cpu/cpu0/cache/index*/ if it exists".to_string(),
+                );
+                for key in INTEL_MAC_SYSCTLS {
+                    plan.push(format!("sysctl {} if sysfs reports no caches", key));
+                }
+                plan.push(format!(
+                    "read {} for the model name and, without sysfs or sysctl caches, its cache size",
+                    self.proc_path("cpuinfo")
+                ));
+            }
+        }
+
+        #[cfg(all(feature = "cpuid", any(target_arch = "x86", target_arch = "x86_64")))]
+        if self.sysfs_root.is_none() {
+            plan.push(
+                "execute CPUID leaves 4 or 0x8000001D and 0x18 or 0x80000005/6 if the OS queries above find no caches"
+                    .to_string(),
+            );
+        }
+
+        #[cfg(feature = "bench-detect")]
+        if self.sysfs_root.is_none() {
+            plan.push("time pointer-chase loads over buffers sized to each cache".to_string());
+        }
+
+        plan
+    }
+
+    #[cfg(feature = "hwloc")]
+    pub(crate) fn collect_hwloc_cache_info(&mut self) -> io::Result<()> {
+        let source = format!("lstopo {}", hwloc::LSTOPO_ARGS.join(" "));
+
+        let xml = match hwloc::lstopo_xml() {
+            Ok(xml) => xml,
+            Err(err) => {
+                self.raw_log.record(&source, &format!("<error: {}>", err));
+                return Err(err);
+            }
+        };
+        self.raw_log.record(&source, &xml);
+
+        let mut proc_level = hwloc::parse_lstopo_xml(&xml)
+            .ok_or_else(|| io::Error::other("no caches in the lstopo export"))?;
+        proc_level.set_sources(Source::Hwloc);
+        self.performance_levels
+            .insert(LevelKey::Default, proc_level);
+
+        Ok(())
+    }
+
+    // False when the CPU has no cache parameters leaf
+    #[cfg(all(feature = "cpuid", any(target_arch = "x86", target_arch = "x86_64")))]
+    pub(crate) fn collect_cpuid_cache_info(&mut self) -> bool {
+        let caches = cpuid::caches();
+        if caches.is_empty() {
+            self.raw_log
+                .note("CPUID has no cache parameters leaf either");
+            return false;
+        }
+
+        let mut proc_level = ProcessorLevel::new(LevelKey::Default);
+
+        for cache in caches {
+            self.raw_log.record(
+                &format!("cpuid L{} {:?}", cache.level, cache.kind),
+                &format!(
+                    "size={} line_size={} ways={} max_sharing={}",
+                    cache.size, cache.line_size, cache.ways, cache.max_sharing
+                ),
+            );
+
+            match (cache.level, cache.kind) {
+                (1, CpuidCacheKind::Data) => {
+                    proc_level.l1_cache.data_size = cache.size;
+                    proc_level.l1_cache.line_size = cache.line_size;
+                    proc_level.l1_ways = cache.ways;
+                }
+                (1, CpuidCacheKind::Instruction) => {
+                    proc_level.l1_cache.instruction_size = cache.size
+                }
+                (1, CpuidCacheKind::Unified) => {
+                    proc_level.l1_cache.unified_size = cache.size;
+                    proc_level.l1_cache.line_size = cache.line_size;
+                    proc_level.l1_ways = cache.ways;
+                }
+                (2, _) => {
+                    proc_level.l2_cache = cache.size;
+                    proc_level.l2_line_size = cache.line_size;
+                    proc_level.l2_ways = cache.ways;
+                }
+                (3, _) => {
+                    proc_level.l3_cache = cache.size;
+                    proc_level.l3_line_size = cache.line_size;
+                    proc_level.l3_ways = cache.ways;
+                }
+                _ => {}
+            }
+        }
+
+        for tlb in cpuid::tlbs() {
+            let tlb = TlbInfo {
+                level: tlb.level,
+                kind: format!("{:?}", tlb.kind),
+                page_sizes: tlb.page_sizes.iter().map(|size| size.to_string()).collect(),
+                entries: tlb.entries,
+                ways: tlb.ways,
+            };
+            self.raw_log.record(
+                &format!("cpuid TLB {}", proc_level.tlbs.len()),
+                &tlb.format(),
+            );
+            proc_level.tlbs.push(tlb);
+        }
+
+        proc_level.set_sources(Source::Cpuid);
+        self.performance_levels
+            .insert(LevelKey::Default, proc_level);
+
+        true
+    }
+
+    #[cfg(target_os = "macos")]
+    fn collect_apple_silicon_cache_info(&mut self) -> io::Result<()> {
+        self.collect_perflevel_cache_info(run_sysctl)
+    }
+
+    // Each core tier has its own hw.perflevelN.* keys, 0 being the fastest;
+    // `sysctl` looks a key up so captured output can be replayed off macOS
+    pub(crate) fn collect_perflevel_cache_info(
+        &mut self,
+        mut sysctl: impl FnMut(&str, &mut RawLog) -> io::Result<String>,
+    ) -> io::Result<()> {
+        let mut read_size = |key: &str, raw_log: &mut RawLog| -> io::Result<usize> {
+            Ok(parse_sysctl_number(&sysctl(key, raw_log)?))
+        };
+
+        // Get number of performance levels
+        let perf_levels = read_size("hw.nperflevels", &mut self.raw_log)?.max(1);
+
+        // Any other key may be missing, which reads as 0 like an empty value
+        let mut read_size = |key: &str, raw_log: &mut RawLog| read_size(key, raw_log).unwrap_or(0);
+
+        // Shared by every tier when the OS reports it at all
+        let shared_l3 = read_size("hw.l3cachesize", &mut self.raw_log);
+
+        // macOS reports a single line size for the whole hierarchy
+        let line_size = read_size("hw.cachelinesize", &mut self.raw_log);
+
+        let mut levels = Vec::new();
+        let mut logical_cpus = Vec::new();
+        for level in 0..perf_levels {
+            let key = if level == 0 {
+                LevelKey::Performance
+            } else {
+                LevelKey::Efficiency(level)
+            };
+
+            let mut proc_level = ProcessorLevel::new(key);
+            let prefix = format!("hw.perflevel{}", level);
+
+            // PERFLEVEL_SYSCTLS lists these for `--plan`
+            proc_level.l1_cache.instruction_size =
+                read_size(&format!("{}.l1icachesize", prefix), &mut self.raw_log);
+            proc_level.l1_cache.data_size =
+                read_size(&format!("{}.l1dcachesize", prefix), &mut self.raw_log);
+            proc_level.l2_cache = read_size(&format!("{}.l2cachesize", prefix), &mut self.raw_log);
+            // Apple cores have no SMT, so CPUs per L2 are cores per cluster
+            proc_level.l2_shared_cores =
+                read_size(&format!("{}.cpusperl2", prefix), &mut self.raw_log);
+            logical_cpus.push(read_size(
+                &format!("{}.logicalcpu", prefix),
+                &mut self.raw_log,
+            ));
+
+            // A tier's own last-level cache wins over the chip-wide one
+            proc_level.l3_cache =
+                match read_size(&format!("{}.l3cachesize", prefix), &mut self.raw_log) {
+                    0 => shared_l3,
+                    size => size,
+                };
+
+            levels.push((key, proc_level));
+        }
+
+        // Some older and beta macOS releases report hw.nperflevels but none of
+        // the hw.perflevelN keys. The chip-wide keys Intel Macs use are still
+        // there and describe the performance cores, so one Default level from
+        // them beats a tier of zeros per perflevel.
+        let has_perflevel_keys = levels
+            .iter()
+            .any(|(_, level)| level.l1_cache.data_size > 0 || level.l2_cache > 0);
+        if !has_perflevel_keys {
+            self.raw_log.note(&format!(
+                "hw.nperflevels is {} but no hw.perflevelN keys exist, reading hw.l1dcachesize and hw.l2cachesize instead",
+                perf_levels
+            ));
+
+            let mut proc_level = ProcessorLevel::new(LevelKey::Default);
+            proc_level.l1_cache.instruction_size = read_size("hw.l1icachesize", &mut self.raw_log);
+            proc_level.l1_cache.data_size = read_size("hw.l1dcachesize", &mut self.raw_log);
+            proc_level.l2_cache = read_size("hw.l2cachesize", &mut self.raw_log);
+            proc_level.l3_cache = shared_l3;
+            levels = vec![(LevelKey::Default, proc_level)];
+        } else {
+            if levels[0].1.l2_cache == 0 {
+                // The flat key describes the performance cores, as above
+                levels[0].1.l2_cache = read_size("hw.l2cachesize", &mut self.raw_log);
+            }
+
+            assign_l2_clusters(&mut levels, &logical_cpus);
+        }
+
+        for (key, mut proc_level) in levels {
+            proc_level.l1_cache.line_size = line_size;
+            proc_level.l2_line_size = line_size;
+            proc_level.l3_line_size = line_size;
+
+            proc_level.set_sources(Source::Sysctl);
+            self.performance_levels.insert(key, proc_level);
+        }
+
+        Ok(())
+    }
+
+    fn collect_intel_mac_cache_info(&mut self) -> io::Result<()> {
+        self.collect_flat_sysctl_cache_info(run_sysctl)
+    }
+
+    // `sysctl` looks a key up, as for `collect_perflevel_cache_info`
+    pub(crate) fn collect_flat_sysctl_cache_info(
+        &mut self,
+        mut run_sysctl: impl FnMut(&str, &mut RawLog) -> io::Result<String>,
+    ) -> io::Result<()> {
+        let mut proc_level = ProcessorLevel::new(LevelKey::Default);
+
+        // Try unified L1 cache first
+        match run_sysctl("hw.l1cachesize", &mut self.raw_log) {
+            Ok(value) if !value.is_empty() => {
+                proc_level.l1_cache.unified_size = parse_sysctl_number(&value);
+            }
+            _ => {
+                // Try separate instruction and data caches
+                if let Ok(value) = run_sysctl("hw.l1icachesize", &mut self.raw_log) {
+                    proc_level.l1_cache.instruction_size = parse_sysctl_number(&value);
+                }
+
+                if let Ok(value) = run_sysctl("hw.l1dcachesize", &mut self.raw_log) {
+                    proc_level.l1_cache.data_size = parse_sysctl_number(&value);
+                }
+            }
+        }
+
+        // L2 cache, which some transitional configs only report per perflevel
+        for key in ["hw.l2cachesize", "hw.perflevel0.l2cachesize"] {
+            if let Ok(value) = run_sysctl(key, &mut self.raw_log) {
+                proc_level.l2_cache = parse_sysctl_number(&value);
+            }
+            if proc_level.l2_cache > 0 {
+                break;
+            }
+        }
+
+        // L3 cache
+        if let Ok(value) = run_sysctl("hw.l3cachesize", &mut self.raw_log) {
+            proc_level.l3_cache = parse_sysctl_number(&value);
+        }
+
+        // Line size (shared by all levels)
+        if let Ok(value) = run_sysctl("hw.cachelinesize", &mut self.raw_log) {
+            let line_size = parse_sysctl_number(&value);
+            proc_level.l1_cache.line_size = line_size;
+            proc_level.l2_line_size = line_size;
+            proc_level.l3_line_size = line_size;
+        }
+
+        proc_level.set_sources(Source::Sysctl);
+        self.performance_levels
+            .insert(LevelKey::Default, proc_level);
+
+        Ok(())
+    }
+
+    // Size implied by number_of_sets × ways_of_associativity × line size,
+    // None when the kernel doesn't expose the geometry
+    fn read_cache_geometry(&mut self, cache_dir: &str, line_size: usize) -> Option<usize> {
+        let mut read = |file: &str| {
+            read_file(&format!("{}/{}", cache_dir, file), &mut self.raw_log)
+                .ok()
+                .and_then(|content| content.trim().parse::<usize>().ok())
+        };
+
+        let sets = read("number_of_sets")?;
+        let ways = read("ways_of_associativity")?;
+
+        Some(sets * ways * line_size).filter(|&size| size > 0)
+    }
+
+    pub(crate) fn collect_linux_cache_info(&mut self) -> io::Result<()> {
+        let mut proc_level = ProcessorLevel::new(LevelKey::Default);
+        let sysfs_root = self
+            .sysfs_root
+            .clone()
+            .unwrap_or_else(|| "/sys".to_string());
+
+        let mut l3_id = None;
+
+        // A pinned process only ever touches the caches of its allowed CPUs
+        let allowed_cpus = self.read_allowed_cpus(&sysfs_root);
+        let cpu = allowed_cpus
+            .as_ref()
+            .and_then(|cpus| cpus.first().copied())
+            .unwrap_or(0);
+        if let Some(cpus) = &allowed_cpus {
+            self.raw_log.note(&format!(
+                "Affinity allows CPUs {}, reading the caches of cpu{}",
+                format_cpu_list(cpus),
+                cpu
+            ));
+        }
+
+        // Read cache information from sysfs
+        for i in 0..SYSFS_CACHE_INDICES {
+            let cache_dir = format!(
+                "{}/devices/system/cpu/cpu{}/cache/index{}",
+                sysfs_root, cpu, i
+            );
+
+            // Only level and size (or the geometry it follows from) are
+            // essential. Every other attribute is read on its own, since
+            // unprivileged users may be denied some of them
+            let level = match read_file(&format!("{}/level", cache_dir), &mut self.raw_log) {
+                Ok(content) => content.trim().parse::<usize>().unwrap_or(0),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    self.raw_log
+                        .note(&format!("index{} not present, skipping", i));
+                    continue;
+                }
+                Err(err) => {
+                    self.raw_log
+                        .note(&format!("index{} level unreadable ({}), skipping", i, err));
+                    continue;
+                }
+            };
+
+            // Line size is optional, not every kernel exposes it
+            let line_size = read_file(
+                &format!("{}/coherency_line_size", cache_dir),
+                &mut self.raw_log,
+            )
+            .map(|content| content.trim().parse::<usize>().unwrap_or(0))
+            .unwrap_or(0);
+
+            // Some kernels leave out size but keep sets, ways and line size
+            let (size_str, geometry_size) =
+                match read_file(&format!("{}/size", cache_dir), &mut self.raw_log) {
+                    Ok(content) => (content.trim().to_string(), None),
+                    Err(err) => match self.read_cache_geometry(&cache_dir, line_size) {
+                        Some(size) => {
+                            self.raw_log.note(&format!(
+                            "index{} size unreadable ({}), {} bytes from sets × ways × line size",
+                            i, err, size
+                        ));
+                            (String::new(), Some(size))
+                        }
+                        None => {
+                            self.raw_log
+                                .note(&format!("index{} size unreadable ({}), skipping", i, err));
+                            continue;
+                        }
+                    },
+                };
+
+            // Without a type an L1 can't be told apart, L2 and L3 don't need it
+            let raw_type = read_file(&format!("{}/type", cache_dir), &mut self.raw_log)
+                .map(|content| content.trim().to_string())
+                .unwrap_or_default();
+            let cache_type = match normalize_cache_type(&raw_type) {
+                Some(cache_type) => cache_type,
+                None if raw_type.is_empty() && level > 1 => "unknown",
+                None => {
+                    self.raw_log.note(&format!(
+                        "warning: index{} type {:?} is not Data, Instruction or Unified{}",
+                        i,
+                        raw_type,
+                        if level == 1 { ", ignoring it" } else { "" }
+                    ));
+                    "unknown"
+                }
+            };
+
+            // Parse the size (e.g., "32K" or "4M")
+            let mut size =
+                match geometry_size.map_or_else(|| try_parse_size_with_unit(&size_str), Ok) {
+                    Ok(size) => size,
+                    Err(_) if size_str.is_empty() => 0,
+                    Err(_) => {
+                        self.raw_log.note(&format!(
+                            "warning: index{} size {:?} is not a size, reporting 0",
+                            i, size_str
+                        ));
+                        0
+                    }
+                };
+
+            // Some kernels write a bare "256" meaning KB. No L2 or L3 is really
+            // under 4 KB, so such sizes are taken as KB unless sets × ways ×
+            // line size says they really are bytes
+            if level >= 2
+                && geometry_size.is_none()
+                && size > 0
+                && size < 4096
+                && size_str.bytes().all(|b| b.is_ascii_digit())
+            {
+                let geometry = self.read_cache_geometry(&cache_dir, line_size);
+                if geometry != Some(size) {
+                    self.raw_log.note(&format!(
+                        "index{} size {} has no unit and is implausibly small, reading it as KB",
+                        i, size_str
+                    ));
+                    size *= 1024;
+                }
+            }
+
+            self.raw_log.note(&format!(
+                "index{} is the L{} {} cache, {} bytes",
+                i, level, cache_type, size
+            ));
+
+            // Older kernels only expose the hex bitmask, not the list
+            let mut shared_cpus =
+                match read_file(&format!("{}/shared_cpu_list", cache_dir), &mut self.raw_log) {
+                    Ok(content) => parse_cpu_list(content.trim()),
+                    Err(_) => {
+                        read_file(&format!("{}/shared_cpu_map", cache_dir), &mut self.raw_log)
+                            .map(|content| parse_cpu_mask(content.trim()))
+                            .unwrap_or_default()
+                    }
+                };
+            if let Some(cpus) = &allowed_cpus {
+                shared_cpus.retain(|shared| cpus.contains(shared));
+            }
+
+            // Store the cache size based on its level and type
+            match level {
+                1 => match cache_type {
+                    "Data" => {
+                        proc_level.l1_cache.data_size = size;
+                        proc_level.l1_cache.line_size = line_size;
+                    }
+                    "Instruction" => proc_level.l1_cache.instruction_size = size,
+                    "Unified" => {
+                        proc_level.l1_cache.unified_size = size;
+                        proc_level.l1_cache.line_size = line_size;
+                    }
+                    _ => {}
+                },
+                2 => {
+                    proc_level.l2_cache = size;
+                    proc_level.l2_line_size = line_size;
+                    proc_level.l2_shared_cores =
+                        self.count_physical_cores(&sysfs_root, &shared_cpus);
+                    proc_level.l2_shared_cpus = shared_cpus;
+                }
+                3 => {
+                    proc_level.l3_cache = size;
+                    proc_level.l3_line_size = line_size;
+                    proc_level.l3_shared_cores =
+                        self.count_physical_cores(&sysfs_root, &shared_cpus);
+                    proc_level.l3_shared_cpus = shared_cpus;
+                    l3_id = read_file(&format!("{}/id", cache_dir), &mut self.raw_log)
+                        .ok()
+                        .and_then(|content| content.trim().parse::<usize>().ok());
+                }
+                _ => {} // Ignore other levels
+            }
+        }
+
+        // Containers may ship lscpu without a full sysfs
+        #[cfg(target_os = "linux")]
+        if self.sysfs_root.is_none()
+            && proc_level.l1_cache.data_size == 0
+            && proc_level.l1_cache.unified_size == 0
+            && proc_level.l2_cache == 0
+        {
+            self.raw_log
+                .note("sysfs reported no caches, falling back to lscpu");
+            if let Ok(output) = self.run_lscpu() {
+                proc_level.set_sources(Source::Sysfs);
+                parse_lscpu(&output, &mut proc_level);
+                proc_level.set_sources(Source::Fallback);
+            }
+        }
+
+        if proc_level.l3_cache > 0 {
+            if let Some((available, total)) = self.read_resctrl_l3_ways(&sysfs_root, l3_id) {
+                self.raw_log.note(&format!(
+                    "resctrl allows {} of {} L3 ways",
+                    available, total
+                ));
+                proc_level.available_l3_ways = Some(available);
+                proc_level.available_l3_bytes =
+                    Some(proc_level.l3_cache.saturating_mul(available) / total);
+            }
+        }
+
+        proc_level.ecc_protected = self.read_edac_cache_protection(&sysfs_root, cpu);
+
+        proc_level.set_sources(Source::Sysfs);
+        self.performance_levels
+            .insert(LevelKey::Default, proc_level);
+
+        self.allowed_cpus = allowed_cpus;
+
+        // cgroups only exist on Linux, so the quota is read alongside sysfs
+        self.effective_cpus = self.read_cgroup_cpu_quota(&sysfs_root);
+        self.hugepages = self.read_hugepages(&sysfs_root);
+        (self.max_turbo_hz, self.all_core_turbo_hz) = self.read_turbo_frequencies(&sysfs_root);
+
+        Ok(())
+    }
+
+    // cpuinfo_max_freq is each core's own ceiling. With turbo off every core
+    // can hold it at once. With turbo on, the favored cores of Turbo Boost
+    // Max 3.0 or AMD's preferred cores sit above the rest, and the rest's
+    // ceiling is the closest cpufreq gets to an all-core turbo. Identical
+    // ceilings leave it unknown, as does a hybrid chip's, whose lowest one
+    // is an efficiency core's
+    fn read_turbo_frequencies(&mut self, sysfs_root: &str) -> (Option<u64>, Option<u64>) {
+        let cpu_dir = format!("{}/devices/system/cpu", sysfs_root);
+
+        let read_number = |path: &str, raw_log: &mut RawLog| {
+            read_file(path, raw_log)
+                .ok()
+                .and_then(|content| content.trim().parse::<u64>().ok())
+        };
+
+        let ceilings: Vec<u64> = list_numbered_entries(&cpu_dir, "cpu")
+            .into_iter()
+            .filter_map(|cpu| {
+                read_number(
+                    &format!("{}/cpu{}/cpufreq/cpuinfo_max_freq", cpu_dir, cpu),
+                    &mut self.raw_log,
+                )
+            })
+            .map(|khz| khz * 1000)
+            .collect();
+        let (Some(&max), Some(&lowest)) = (ceilings.iter().max(), ceilings.iter().min()) else {
+            return (None, None);
+        };
+
+        // acpi-cpufreq and amd-pstate have the boost switch, intel_pstate
+        // its inverse
+        let turbo_off = read_number(&format!("{}/cpufreq/boost", cpu_dir), &mut self.raw_log)
+            == Some(0)
+            || read_number(
+                &format!("{}/intel_pstate/no_turbo", cpu_dir),
+                &mut self.raw_log,
+            ) == Some(1);
+        let hybrid = fs::metadata(format!("{}/types", cpu_dir)).is_ok();
+
+        let all_core = if turbo_off {
+            Some(max)
+        } else if lowest < max && !hybrid {
+            Some(lowest)
+        } else {
+            None
+        };
+
+        (Some(max), all_core)
+    }
+
+    // Hugepages relieve the TLB pressure of large matmuls; one entry per
+    // hugepages-<size>kB directory, smallest first
+    fn read_hugepages(&mut self, sysfs_root: &str) -> Vec<HugepageInfo> {
+        let hugepages_dir = format!("{}/kernel/mm/hugepages", sysfs_root);
+
+        // "Hugepagesize:       2048 kB", only meaningful for a live procfs
+        let default_size = if self.reads_live_proc() {
+            read_file(&self.proc_path("meminfo"), &mut self.raw_log)
+                .ok()
+                .and_then(|content| {
+                    content
+                        .lines()
+                        .find_map(|line| line.strip_prefix("Hugepagesize:"))
+                        .map(crate::size::parse_size_with_unit)
+                })
+        } else {
+            None
+        };
+
+        let mut sizes: Vec<(String, usize)> = fs::read_dir(&hugepages_dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                let kb = name
+                    .strip_prefix("hugepages-")?
+                    .strip_suffix("kB")?
+                    .parse::<usize>()
+                    .ok()?;
+                Some((name, kb * 1024))
+            })
+            .collect();
+        sizes.sort_by_key(|(_, size)| *size);
+
+        let mut hugepages = Vec::new();
+        for (name, size) in sizes {
+            let mut read_count = |file: &str| {
+                read_file(
+                    &format!("{}/{}/{}", hugepages_dir, name, file),
+                    &mut self.raw_log,
+                )
+                .ok()
+                .and_then(|content| content.trim().parse::<usize>().ok())
+                .unwrap_or(0)
+            };
+
+            hugepages.push(HugepageInfo {
+                size,
+                total: read_count("nr_hugepages"),
+                free: read_count("free_hugepages"),
+                default: default_size == Some(size),
+            });
+        }
+
+        hugepages
+    }
+
+    // Inside a container the quota, not the host core count, bounds how much
+    // parallel work pays off; tries cgroup v2 `cpu.max`, then v1 CFS files
+    fn read_cgroup_cpu_quota(&mut self, sysfs_root: &str) -> Option<f64> {
+        let cgroup_root = format!("{}/fs/cgroup", sysfs_root);
+
+        // Our own v2 group from /proc/self/cgroup ("0::/user.slice/..."), a
+        // captured tree is taken to be the container's root group
+        let group = if self.reads_live_proc() {
+            read_file(&self.proc_path("self/cgroup"), &mut self.raw_log)
+                .ok()
+                .and_then(|content| {
+                    content
+                        .lines()
+                        .find_map(|line| line.strip_prefix("0::"))
+                        .map(|path| path.trim().trim_end_matches('/').to_string())
+                })
+                .unwrap_or_default()
+        } else {
+            String::new()
+        };
+
+        if let Ok(content) = read_file(
+            &format!("{}{}/cpu.max", cgroup_root, group),
+            &mut self.raw_log,
+        ) {
+            // "max 100000" when unlimited, "200000 100000" for two CPUs
+            let mut fields = content.split_whitespace();
+            let quota = fields.next()?.parse::<f64>().ok()?;
+            let period = fields.next()?.parse::<f64>().ok()?;
+            return (period > 0.0).then(|| quota / period);
+        }
+
+        let quota = read_file(
+            &format!("{}/cpu/cpu.cfs_quota_us", cgroup_root),
+            &mut self.raw_log,
+        )
+        .ok()?;
+        let period = read_file(
+            &format!("{}/cpu/cpu.cfs_period_us", cgroup_root),
+            &mut self.raw_log,
+        )
+        .ok()?;
+
+        // A quota of -1 means unlimited
+        let quota = quota
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|quota| *quota > 0.0)?;
+        let period = period
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|period| *period > 0.0)?;
+        Some(quota / period)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn run_lscpu(&mut self) -> io::Result<String> {
+        check_subprocess("lscpu")?;
+        match Command::new("lscpu").env("LC_ALL", "C").output() {
+            Ok(output) => {
+                let output = String::from_utf8_lossy(&output.stdout).into_owned();
+                self.raw_log.record("lscpu", &output);
+                Ok(output)
+            }
+            Err(err) => {
+                self.raw_log.record("lscpu", &format!("<error: {}>", err));
+                Err(err)
+            }
+        }
+    }
+
+    // lscpu alone, for cross-checking what sysfs reports
+    #[cfg(target_os = "linux")]
+    pub(crate) fn collect_lscpu_cache_info(&mut self) -> io::Result<()> {
+        let output = self.run_lscpu()?;
+
+        let mut proc_level = ProcessorLevel::new(LevelKey::Default);
+        parse_lscpu(&output, &mut proc_level);
+        proc_level.set_sources(Source::Fallback);
+        self.performance_levels
+            .insert(LevelKey::Default, proc_level);
+
+        Ok(())
+    }
+
+    // EDAC drivers for CPU caches (Cortex-A, Octeon and other server ARM
+    // parts) register a "cpu" device with an error-counter block per cache
+    // level. Only ECC or parity protected caches can count errors, while
+    // their absence says nothing, so this never answers Some(false)
+    fn read_edac_cache_protection(&mut self, sysfs_root: &str, cpu: usize) -> Option<bool> {
+        let instance = format!("{}/devices/system/edac/cpu/cpu{}", sysfs_root, cpu);
+
+        let mut blocks: Vec<String> = fs::read_dir(&instance)
+            .ok()?
+            .flatten()
+            .filter(|entry| entry.path().join("ce_count").exists())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .filter(|name| name.starts_with('L') && name[1..].parse::<usize>().is_ok())
+            .collect();
+        if blocks.is_empty() {
+            return None;
+        }
+
+        blocks.sort();
+        self.raw_log.record(&instance, &blocks.join(", "));
+        self.raw_log.note(&format!(
+            "EDAC counts errors in the {} caches of cpu{}, so they are protected",
+            blocks.join(", "),
+            cpu
+        ));
+
+        Some(true)
+    }
+
+    // With Intel CAT the L3 is split into ways and each resctrl group gets a
+    // capacity bitmask; returns (ways we may use, total ways) for the group
+    // this process belongs to, in the L3 domain of CPU 0
+    fn read_resctrl_l3_ways(
+        &mut self,
+        sysfs_root: &str,
+        l3_id: Option<usize>,
+    ) -> Option<(usize, usize)> {
+        let resctrl = format!("{}/fs/resctrl", sysfs_root);
+
+        let full_mask = read_file(&format!("{}/info/L3/cbm_mask", resctrl), &mut self.raw_log)
+            .ok()
+            .and_then(|content| u64::from_str_radix(content.trim(), 16).ok())?;
+        let total = full_mask.count_ones() as usize;
+        if total == 0 {
+            return None;
+        }
+
+        // Tasks not placed in any group belong to the root group
+        let pid = std::process::id().to_string();
+        let group = fs::read_dir(&resctrl)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .find(|path| {
+                fs::read_to_string(path.join("tasks"))
+                    .map(|tasks| tasks.lines().any(|task| task.trim() == pid))
+                    .unwrap_or(false)
+            })
+            .map_or(resctrl.clone(), |path| path.to_string_lossy().into_owned());
+
+        let schemata = read_file(&format!("{}/schemata", group), &mut self.raw_log).ok()?;
+        let domains = schemata
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("L3:"))?;
+
+        // "L3:0=7ff;1=7ff", one capacity bitmask per L3 instance
+        let mut masks = domains.split(';').filter_map(|domain| {
+            let (id, mask) = domain.split_once('=')?;
+            Some((id.trim().parse::<usize>().ok()?, mask.trim()))
+        });
+        let mask = match l3_id {
+            Some(l3_id) => masks.find(|(id, _)| *id == l3_id),
+            None => masks.next(),
+        }
+        .and_then(|(_, mask)| u64::from_str_radix(mask, 16).ok())?;
+
+        Some(((mask & full_mask).count_ones() as usize, total))
+    }
+
+    // Cpus_allowed_list of /proc/self/status, None when it allows every
+    // online CPU or either list can't be read
+    pub(crate) fn read_allowed_cpus(&mut self, sysfs_root: &str) -> Option<Vec<usize>> {
+        if !self.reads_live_proc() {
+            return None;
+        }
+
+        let status = read_file(&self.proc_path("self/status"), &mut self.raw_log).ok()?;
+        let allowed = status
+            .lines()
+            .find_map(|line| line.strip_prefix("Cpus_allowed_list:"))
+            .map(|list| parse_cpu_list(list.trim()))?;
+
+        let online = read_file(
+            &format!("{}/devices/system/cpu/online", sysfs_root),
+            &mut self.raw_log,
+        )
+        .map(|content| parse_cpu_list(content.trim()))
+        .ok()?;
+
+        let restricted = online.iter().any(|cpu| !allowed.contains(cpu));
+        (restricted && !allowed.is_empty()).then_some(allowed)
+    }
+
+    // SMT siblings share a core, so count distinct (package, core) pairs;
+    // falls back to the number of logical CPUs when topology is unreadable
+    fn count_physical_cores(&mut self, sysfs_root: &str, cpus: &[usize]) -> usize {
+        let mut cores = Vec::new();
+
+        for cpu in cpus {
+            let topology_dir = format!("{}/devices/system/cpu/cpu{}/topology", sysfs_root, cpu);
+
+            let package_id = read_file(
+                &format!("{}/physical_package_id", topology_dir),
+                &mut self.raw_log,
+            );
+            let core_id = read_file(&format!("{}/core_id", topology_dir), &mut self.raw_log);
+
+            match (package_id, core_id) {
+                (Ok(package_id), Ok(core_id)) => {
+                    let core = (package_id.trim().to_string(), core_id.trim().to_string());
+                    if !cores.contains(&core) {
+                        cores.push(core);
+                    }
+                }
+                _ => return cpus.len(),
+            }
+        }
+
+        cores.len()
+    }
+
+    #[cfg(all(windows, not(target_arch = "aarch64")))]
+    fn collect_windows_cache_info(&mut self) -> io::Result<()> {
+        if !subprocesses_allowed() {
+            return self.collect_windows_glpi_cache_info();
+        }
+
+        let mut proc_level = ProcessorLevel::new(LevelKey::Default);
+
+        // Use wmic to get cache information on Windows
+        if let Some(output_str) = run_wmic(WMIC_CPU_CACHES, &mut self.raw_log) {
+            parse_wmic_caches(&output_str, &mut proc_level);
+        }
+
+        proc_level.set_sources(Source::Wmic);
+        self.performance_levels
+            .insert(LevelKey::Default, proc_level);
+
+        Ok(())
+    }
+
+    // wmic only reports L2/L3 totals (and often nothing) on Windows on ARM, so
+    // ask the kernel for the cache descriptors of the caches CPU 0 sits on;
+    // everywhere else too when wmic can't run
+    #[cfg(windows)]
+    fn collect_windows_glpi_cache_info(&mut self) -> io::Result<()> {
+        let mut proc_level = ProcessorLevel::new(LevelKey::Default);
+
+        for cache in logical_processor_caches()? {
+            if cache.processor_mask & 1 == 0 {
+                continue;
+            }
+
+            let descriptor = cache.descriptor;
+            let size = descriptor.size as usize;
+            let line_size = descriptor.line_size as usize;
+            let shared_cpus: Vec<usize> = (0..usize::BITS as usize)
+                .filter(|cpu| cache.processor_mask & (1 << cpu) != 0)
+                .collect();
+
+            self.raw_log.record(
+                &format!("GetLogicalProcessorInformation L{}", descriptor.level),
+                &format!(
+                    "type={} size={} line_size={} mask={:#x}",
+                    descriptor.cache_type, size, line_size, cache.processor_mask
+                ),
+            );
+
+            match (descriptor.level, descriptor.cache_type) {
+                (1, CACHE_UNIFIED) => {
+                    proc_level.l1_cache.unified_size = size;
+                    proc_level.l1_cache.line_size = line_size;
+                }
+                (1, CACHE_INSTRUCTION) => proc_level.l1_cache.instruction_size = size,
+                (1, CACHE_DATA) => {
+                    proc_level.l1_cache.data_size = size;
+                    proc_level.l1_cache.line_size = line_size;
+                }
+                (2, _) => {
+                    proc_level.l2_cache = size;
+                    proc_level.l2_line_size = line_size;
+                    proc_level.l2_shared_cpus = shared_cpus;
+                }
+                (3, _) => {
+                    proc_level.l3_cache = size;
+                    proc_level.l3_line_size = line_size;
+                    proc_level.l3_shared_cores = shared_cpus.len();
+                    proc_level.l3_shared_cpus = shared_cpus;
+                }
+                _ => {}
+            }
+        }
+
+        proc_level.set_sources(Source::Win32);
+        self.performance_levels
+            .insert(LevelKey::Default, proc_level);
+
+        Ok(())
+    }
+
+    #[cfg(any(target_os = "illumos", target_os = "solaris"))]
+    fn collect_illumos_cache_info(&mut self) -> io::Result<()> {
+        let mut proc_level = ProcessorLevel::new(LevelKey::Default);
+
+        let output = check_subprocess("kstat")
+            .and_then(|()| Command::new("kstat").args(KSTAT_CPU_INFO).output());
+        if let Ok(output) = output {
+            let output_str = String::from_utf8_lossy(&output.stdout);
+            self.raw_log.record("kstat -p cpu_info", &output_str);
+            parse_kstat_cpu_info(&output_str, &mut proc_level);
+        }
+
+        proc_level.set_sources(Source::Kstat);
+        self.performance_levels
+            .insert(LevelKey::Default, proc_level);
+
+        Ok(())
+    }
+
+    /// Block sizes for the main cores, None when too little of the cache
+    /// hierarchy was detected to derive them
+    pub fn block_sizes_for_dtype(&self, bytes_per_elem: usize) -> Option<BlockSizes> {
+        self.primary_level()?.block_sizes(bytes_per_elem)
+    }
+
+    /// Fills every cache detection missed with conservative defaults, adding
+    /// a level when there is none, so block sizes can always be derived; what
+    /// was assumed is kept in `assumed_defaults` and shown in the report
+    pub fn assume_defaults(&mut self) {
+        if self.performance_levels.is_empty() {
+            self.performance_levels
+                .insert(LevelKey::Default, ProcessorLevel::new(LevelKey::Default));
+        }
+
+        for level in self.performance_levels.values_mut() {
+            let assumed = level.assume_defaults();
+            if !assumed.is_empty() {
+                self.assumed_defaults
+                    .push(format!("{}: {}", level.level_name, assumed.join(", ")));
+            }
+        }
+    }
+
+    /// Level with the given display name, like "Performance Cores"
+    pub fn level(&self, name: &str) -> Option<&ProcessorLevel> {
+        self.levels().find(|level| level.level_name == name)
+    }
+
+    /// Every detected level, in display order
+    pub fn levels(&self) -> impl Iterator<Item = &ProcessorLevel> {
+        self.performance_levels.values()
+    }
+
+    /// Levels fastest first: performance cores (or the only level), then
+    /// efficiency tiers by perflevel index, then any other core groups
+    pub fn levels_by_tier(&self) -> Vec<&ProcessorLevel> {
+        let mut levels: Vec<_> = self.performance_levels.iter().collect();
+        levels.sort_by_key(|&(key, _)| match *key {
+            LevelKey::Performance | LevelKey::Default => (0, 0),
+            LevelKey::Efficiency(index) => (1, index),
+            LevelKey::Group(group) => (2, group),
+        });
+
+        levels.into_iter().map(|(_, level)| level).collect()
+    }
+
+    /// Levels in `order`
+    pub fn levels_sorted(&self, order: LevelOrder) -> Vec<&ProcessorLevel> {
+        let mut levels = self.levels_by_tier();
+        match order {
+            LevelOrder::Tier => {}
+            LevelOrder::Name => levels.sort_by(|a, b| a.level_name.cmp(&b.level_name)),
+            // A stable sort keeps tier order among equal L2s
+            LevelOrder::Size => levels.sort_by_key(|level| Reverse(level.l2_cache)),
+        }
+
+        levels
+    }
+
+    /// Performance cores on heterogeneous chips, the only level otherwise
+    pub fn primary_level(&self) -> Option<&ProcessorLevel> {
+        self.performance_levels.get(&self.primary_key()?)
+    }
+
+    /// The primary level's caches, the line size and the CPU count in one
+    /// flat struct, for callers that want none of the per-level detail
+    pub fn summary(&self) -> Summary {
+        let logical_cpus = match &self.allowed_cpus {
+            Some(cpus) => cpus.len(),
+            None => thread::available_parallelism().map_or(0, |cpus| cpus.get()),
+        };
+
+        Summary {
+            l1_data: self.l1_data_cache(),
+            l2: self.l2_cache(),
+            l3: self.l3_cache(),
+            line_size: self.max_line_size(),
+            logical_cpus,
+        }
+    }
+
+    /// Level with the smallest L2, to tile for when a thread may land on any
+    /// core: its `block_sizes` fit every level's caches
+    ///
+    /// Levels without a detected L2 are skipped; None when no level has one.
+    pub fn worst_case_level(&self) -> Option<&ProcessorLevel> {
+        self.performance_levels
+            .values()
+            .filter(|level| level.l2_cache > 0)
+            .min_by_key(|level| (level.l2_cache, level.l1_cache.effective_data_size()))
+    }
+
+    /// Level with the largest L2, to tile for when threads are pinned to the
+    /// cores it describes
+    pub fn best_case_level(&self) -> Option<&ProcessorLevel> {
+        self.performance_levels
+            .values()
+            .filter(|level| level.l2_cache > 0)
+            .max_by_key(|level| (level.l2_cache, level.l1_cache.effective_data_size()))
+    }
+
+    /// Whether the cores are asymmetric, P/E or big.LITTLE: more than one
+    /// level, and their caches differ
+    ///
+    /// Levels without any detected cache are left out, they can't be told
+    /// apart from the others.
+    pub fn is_heterogeneous(&self) -> bool {
+        let mut caches: Vec<_> = self
+            .performance_levels
+            .values()
+            .map(|level| {
+                (
+                    level.l1_cache.instruction_size,
+                    level.l1_cache.effective_data_size(),
+                    level.l2_cache,
+                    level.l3_cache,
+                )
+            })
+            .filter(|&caches| caches != (0, 0, 0, 0))
+            .collect();
+        caches.sort_unstable();
+        caches.dedup();
+
+        caches.len() > 1
+    }
+
+    /// L1 data cache of the primary level in bytes, the unified L1 on CPUs
+    /// without a split one, 0 when not detected
+    pub fn l1_data_cache(&self) -> usize {
+        self.primary_level()
+            .map_or(0, |level| level.l1_cache.effective_data_size())
+    }
+
+    /// L2 of the primary level in bytes, 0 when not detected
+    pub fn l2_cache(&self) -> usize {
+        self.primary_level().map_or(0, |level| level.l2_cache)
+    }
+
+    /// L3 of the primary level in bytes, 0 when not detected
+    pub fn l3_cache(&self) -> usize {
+        self.primary_level().map_or(0, |level| level.l3_cache)
+    }
+
+    /// Largest line size of any cache on any level, the alignment to give
+    /// buffers shared across the hierarchy; 64 when none was detected
+    pub fn max_line_size(&self) -> usize {
+        self.performance_levels
+            .values()
+            .flat_map(|level| {
+                [
+                    level.l1_cache.line_size,
+                    level.l2_line_size,
+                    level.l3_line_size,
+                ]
+            })
+            .max()
+            .filter(|line_size| *line_size > 0)
+            .unwrap_or(64)
+    }
+
+    fn primary_key(&self) -> Option<LevelKey> {
+        [LevelKey::Performance, LevelKey::Default]
+            .into_iter()
+            .find(|key| self.performance_levels.contains_key(key))
+    }
+
+    /// Short hex hash of the architecture, model and cache hierarchy, stable
+    /// across runs and builds so tuning results can be keyed on it
+    ///
+    /// Measured latencies and cgroup limits are left out, they vary between
+    /// runs on the same hardware.
+    pub fn fingerprint(&self) -> String {
+        let mut fields = vec![self.architecture.clone(), self.model_name.clone()];
+
+        for level in self.performance_levels.values() {
+            fields.push(format!(
+                "{}:{}/{}/{}:{}:{}:{}/{}/{}",
+                level.level_name,
+                level.l1_cache.instruction_size,
+                level.l1_cache.data_size,
+                level.l1_cache.unified_size,
+                level.l2_cache,
+                level.l3_cache,
+                level.l1_cache.line_size,
+                level.l2_line_size,
+                level.l3_line_size
+            ));
+        }
+
+        // FNV-1a, unlike std's hashers it is specified and won't change
+        let hash = fields
+            .join("\n")
+            .bytes()
+            .fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+            });
+
+        format!("{:016x}", hash)
+    }
+
+    /// Measures per-level load latency with a pointer-chase microbenchmark
+    ///
+    /// Each level is measured on its own cores, so hybrid chips show the
+    /// latency gap between their performance and efficiency cores. Levels the
+    /// thread can't be moved onto are left unmeasured, apart from the primary
+    /// level, which falls back to wherever the thread happens to run.
+    #[cfg(feature = "bench-detect")]
+    pub fn measure_latencies(&mut self) {
+        let primary_key = self.primary_key();
+
+        for (key, level) in self.performance_levels.iter_mut() {
+            let line_size = match level.l1_cache.line_size {
+                0 => 64,
+                line_size => line_size,
+            };
+            let l1_data = level.l1_cache.effective_data_size();
+            let (l2_size, l3_size) = (level.l2_cache, level.l3_cache);
+
+            // Half of each level keeps the working set clear of the next one out
+            let chase = || {
+                (
+                    bench::pointer_chase_latency_ns(l1_data / 2, line_size),
+                    bench::pointer_chase_latency_ns(l2_size / 2, line_size),
+                    bench::pointer_chase_latency_ns(l3_size / 2, line_size),
+                )
+            };
+            let latencies = match bench::on_cores(*key, &level.l2_shared_cpus, chase) {
+                Some(latencies) => latencies,
+                None if Some(*key) == primary_key => chase(),
+                None => continue,
+            };
+
+            (
+                level.l1_cache.latency_ns,
+                level.l2_latency_ns,
+                level.l3_latency_ns,
+            ) = latencies;
+        }
+    }
+
+    /// Measures sustained memory bandwidth with STREAM Triad over arrays well
+    /// past the primary level's L3; takes about a second
+    #[cfg(feature = "bench-detect")]
+    pub fn measure_memory_bandwidth(&mut self) {
+        self.memory_bandwidth_gbps = bench::stream_triad_gbps(self.l3_cache());
+    }
+
+    /// Counts the populated memory channels from `dmidecode -t 17`, which
+    /// needs root to read the SMBIOS tables; Linux only
+    ///
+    /// Not part of detection since it runs a privileged tool for a number
+    /// the caches don't depend on.
+    pub fn detect_memory_channels(&mut self) -> Option<usize> {
+        #[cfg(target_os = "linux")]
+        if self.sysfs_root.is_none() {
+            let source = "dmidecode -t 17";
+            match run_tool("dmidecode", &["-t", "17"]) {
+                Ok(output) => {
+                    self.raw_log.record(source, &output);
+                    self.memory_channels = parse_dmidecode_memory_channels(&output);
+                }
+                Err(err) => self.raw_log.record(source, &format!("<error: {}>", err)),
+            }
+        }
+
+        self.memory_channels
+    }
+
+    /// Runs an f64 matmul of `shape` at the primary level's recommended
+    /// blocks, returning the blocks used and the GFLOPS achieved
+    #[cfg(feature = "bench-detect")]
+    pub fn measure_matmul_gflops(&self, shape: &MatrixShape) -> Option<(BlockSizes, f64)> {
+        let blocks = self.block_sizes_for_dtype(8)?.fit_to(shape);
+
+        Some((blocks, bench::blocked_matmul_gflops(shape, blocks)?))
+    }
+
+    /// Current clock of each online CPU in MHz, read straight from cpufreq
+    /// so it can be polled without re-running detection; empty where cpufreq
+    /// isn't available
+    pub fn current_frequencies_mhz(&self) -> Vec<(usize, f64)> {
+        let cpu_dir = self.sysfs_cpu_dir();
+
+        // scaling_cur_freq is in kHz
+        list_numbered_entries(&cpu_dir, "cpu")
+            .into_iter()
+            .filter_map(|cpu| {
+                let path = format!("{}/cpu{}/cpufreq/scaling_cur_freq", cpu_dir, cpu);
+                let khz = fs::read_to_string(path).ok()?.trim().parse::<f64>().ok()?;
+                Some((cpu, khz / 1000.0))
+            })
+            .collect()
+    }
+
+    /// The `--tree` view: every level as `ProcessorLevel::format_tree`
+    ///
+    /// Where sysfs has the per-CPU topology, the tree shows every L2 inside
+    /// the detected core's L3 rather than that core's alone.
+    pub fn display_tree(&mut self) -> String {
+        let topology = self.cpu_topology();
+        let mut result = vec![format!(
+            "{} ({})",
+            self.model_name.trim(),
+            self.architecture
+        )];
+
+        for level in self.levels_sorted(self.level_order) {
+            let mut level = level.clone();
+            if level.l2_clusters.is_empty() {
+                let mut groups: Vec<Vec<usize>> = topology
+                    .iter()
+                    .filter(|entry| {
+                        level.l3_shared_cpus.is_empty() || level.l3_shared_cpus.contains(&entry.cpu)
+                    })
+                    .map(|entry| entry.l2_shared_cpus.clone())
+                    .filter(|cpus| !cpus.is_empty())
+                    .collect();
+                groups.sort();
+                groups.dedup();
+                level.l2_clusters = groups;
+            }
+
+            result.push(String::new());
+            result.extend(level.format_tree());
+        }
+
+        result.join("\n")
+    }
+
+    /// Package, core, NUMA node and L2/L3 sharing of every logical CPU from
+    /// sysfs; empty where there is no sysfs
+    pub fn cpu_topology(&mut self) -> Vec<CpuTopology> {
+        let cpu_dir = self.sysfs_cpu_dir();
+        let mut topology = Vec::new();
+
+        for cpu in list_numbered_entries(&cpu_dir, "cpu") {
+            let dir = format!("{}/cpu{}", cpu_dir, cpu);
+            let mut read_number = |file: &str| {
+                read_file(&format!("{}/{}", dir, file), &mut self.raw_log)
+                    .ok()
+                    .and_then(|content| content.trim().parse::<usize>().ok())
+            };
+
+            let mut entry = CpuTopology {
+                cpu,
+                package: read_number("topology/physical_package_id"),
+                core: read_number("topology/core_id"),
+                // The node shows up as a nodeN link next to the CPU's files
+                numa_node: list_numbered_entries(&dir, "node").first().copied(),
+                ..Default::default()
+            };
+
+            for index in 0..SYSFS_CACHE_INDICES {
+                let cache_dir = format!("{}/cache/index{}", dir, index);
+                let Ok(level) = read_file(&format!("{}/level", cache_dir), &mut self.raw_log)
+                else {
+                    break;
+                };
+                let shared_cpus =
+                    read_file(&format!("{}/shared_cpu_list", cache_dir), &mut self.raw_log)
+                        .map(|content| parse_cpu_list(content.trim()))
+                        .unwrap_or_default();
+
+                match level.trim() {
+                    "2" => entry.l2_shared_cpus = shared_cpus,
+                    "3" => entry.l3_shared_cpus = shared_cpus,
+                    _ => {}
+                }
+            }
+
+            topology.push(entry);
+        }
+
+        topology
+    }
+
+    fn proc_path(&self, file: &str) -> String {
+        format!("{}/{}", self.proc_root.as_deref().unwrap_or("/proc"), file)
+    }
+
+    // A captured sysfs tree has no matching procfs unless one is given too
+    fn reads_live_proc(&self) -> bool {
+        self.sysfs_root.is_none() || self.proc_root.is_some()
+    }
+
+    fn sysfs_cpu_dir(&self) -> String {
+        format!(
+            "{}/devices/system/cpu",
+            self.sysfs_root.as_deref().unwrap_or("/sys")
+        )
+    }
+
+    /// Renders the primary level as Rust constants, for compiling detected
+    /// sizes into kernels that can't detect them at runtime
+    pub fn to_rust_consts(&self) -> String {
+        let l1_instruction = self
+            .primary_level()
+            .map_or(0, |level| level.l1_cache.instruction_size);
+
+        let mut result = vec![format!(
+            "// Generated by `matmul-utils codegen` on {} ({})",
+            self.architecture,
+            self.model_name.lines().next().unwrap_or_default()
+        )];
+
+        for (name, value) in [
+            ("L1D_CACHE", self.l1_data_cache()),
+            ("L1I_CACHE", l1_instruction),
+            ("L2_CACHE", self.l2_cache()),
+            ("L3_CACHE", self.l3_cache()),
+            ("CACHE_LINE_SIZE", self.max_line_size()),
+        ] {
+            result.push(format!("pub const {}: usize = {};", name, value));
+        }
+
+        result.join("\n")
+    }
+
+    /// Renders the primary level as `export MATMUL_L2=1048576` lines, for
+    /// `eval $(matmul-utils --format env)` in shell scripts and Makefiles
+    pub fn to_shell_exports(&self) -> String {
+        let l1_instruction = self
+            .primary_level()
+            .map_or(0, |level| level.l1_cache.instruction_size);
+
+        [
+            ("L1D", self.l1_data_cache()),
+            ("L1I", l1_instruction),
+            ("L2", self.l2_cache()),
+            ("L3", self.l3_cache()),
+            ("LINE_SIZE", self.max_line_size()),
+        ]
+        .iter()
+        .map(|(name, value)| format!("export MATMUL_{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join("\n")
+    }
+
+    /// Renders the detected caches in the Prometheus text exposition format
+    pub fn to_prometheus(&self) -> String {
+        let mut result = vec![
+            "# HELP cpu_cache_bytes Size of each detected CPU cache in bytes".to_string(),
+            "# TYPE cpu_cache_bytes gauge".to_string(),
+        ];
+
+        for level in self.performance_levels.values() {
+            let caches = [
+                ("L1", "instruction", level.l1_cache.instruction_size),
+                ("L1", "data", level.l1_cache.data_size),
+                ("L1", "unified", level.l1_cache.unified_size),
+                ("L2", "unified", level.l2_cache),
+                ("L3", "unified", level.l3_cache),
+            ];
+
+            for (cache_level, cache_type, size) in caches {
+                if size == 0 {
+                    continue;
+                }
+
+                result.push(format!(
+                    "cpu_cache_bytes{{arch=\"{}\",core=\"{}\",level=\"{}\",type=\"{}\"}} {}",
+                    prometheus_escape(&self.architecture),
+                    prometheus_escape(&metric_label(&level.level_name)),
+                    cache_level,
+                    cache_type,
+                    size
+                ));
+            }
+        }
+
+        result.join("\n")
+    }
+
+    /// Renders one `level,l1i_bytes,...` row per processor level, for
+    /// spreadsheets and pandas
+    pub fn to_csv(&self) -> String {
+        let mut result = vec![
+            "level,l1i_bytes,l1d_bytes,l1_unified_bytes,l2_bytes,l3_bytes,line_size".to_string(),
+        ];
+
+        for level in self.levels_sorted(self.level_order) {
+            result.push(format!(
+                "{},{},{},{},{},{},{}",
+                csv_escape(&level.level_name),
+                level.l1_cache.instruction_size,
+                level.l1_cache.data_size,
+                level.l1_cache.unified_size,
+                level.l2_cache,
+                level.l3_cache,
+                level.l1_cache.line_size
+            ));
+        }
+
+        result.join("\n")
+    }
+
+    /// Renders the machine as top-level keys and each processor level as a
+    /// `[[levels]]` table, for build configs that read TOML
+    pub fn to_toml(&self) -> String {
+        let string = |value: &str| Json::Str(value.to_string()).render();
+
+        let mut result = vec![
+            format!("architecture = {}", string(&self.architecture)),
+            format!("model_name = {}", string(&self.model_name)),
+        ];
+
+        for level in self.levels_sorted(self.level_order) {
+            result.push(String::new());
+            result.push("[[levels]]".to_string());
+            result.push(format!("name = {}", string(&level.level_name)));
+            for (key, value) in [
+                ("l1i_bytes", level.l1_cache.instruction_size),
+                ("l1d_bytes", level.l1_cache.data_size),
+                ("l1_unified_bytes", level.l1_cache.unified_size),
+                ("l2_bytes", level.l2_cache),
+                ("l3_bytes", level.l3_cache),
+                ("line_size", level.l1_cache.line_size),
+            ] {
+                result.push(format!("{} = {}", key, value));
+            }
+        }
+
+        result.join("\n")
+    }
+
+    /// Renders the caches as a Markdown table, one row per processor level,
+    /// for pasting into issues and docs
+    pub fn to_markdown(&self) -> String {
+        let mut result = vec![
+            format!("**{}** ({})", self.model_name.trim(), self.architecture),
+            String::new(),
+            "| Level | L1 Instruction | L1 Data | L2 | L3 | Line Size |".to_string(),
+            "|---|---|---|---|---|---|".to_string(),
+        ];
+
+        for level in self.levels_sorted(self.level_order) {
+            let l1_data = level.l1_cache.effective_data_size();
+            result.push(format!(
+                "| {} | {} | {} | {} | {} | {} |",
+                level.level_name.replace('|', "\\|"),
+                format_size(level.l1_cache.instruction_size),
+                format_size(l1_data),
+                format_size(level.l2_cache),
+                format_size(level.l3_cache),
+                format_size(level.l1_cache.line_size)
+            ));
+        }
+
+        result.join("\n")
+    }
+
+    /// The `--diff-units` report: this platform's unit assumptions, then
+    /// every cache size wmic printed next to the bytes it was scaled to
+    pub fn unit_report(&self) -> Vec<String> {
+        let mut result = vec!["Units:".to_string(), "======".to_string()];
+        result.extend(unit_assumptions().iter().map(|unit| unit.to_string()));
+
+        let wmic_output =
+            self.raw_log.entries.iter().filter(|(source, value)| {
+                source.starts_with("wmic") && !value.starts_with("<error")
+            });
+        let mut scaled = 0;
+        for (_, output) in wmic_output {
+            for (key, value, kb) in parse_wmic_cache_lines(output) {
+                result.push(format!(
+                    "wmic {}={} -> {} KB x {} = {}",
+                    key,
+                    value,
+                    kb,
+                    WMIC_CACHE_UNIT,
+                    format_size(kb * WMIC_CACHE_UNIT)
+                ));
+                scaled += 1;
+            }
+        }
+        if cfg!(windows) && scaled == 0 {
+            result.push("wmic wasn't run or printed no cache sizes".to_string());
+        }
+
+        result
+    }
+
+    pub fn to_json_string(&self) -> String {
+        self.to_json().render()
+    }
+
+    /// `to_json` plus what it leaves out: the level map's keys, every raw
+    /// query and the options detection ran with, for `--debug-json`
+    ///
+    /// Meant for reproducing bug reports, so its layout isn't covered by
+    /// `JSON_SCHEMA_VERSION` and `from_json` doesn't read it back.
+    pub fn to_debug_json(&self) -> Json {
+        let optional = |value: &Option<String>| value.clone().map_or(Json::Null, Json::Str);
+
+        let Json::Object(mut fields) = self.to_json() else {
+            unreachable!("to_json always renders an object");
+        };
+        fields.extend([
+            (
+                "level_keys".to_string(),
+                Json::Array(
+                    self.performance_levels
+                        .keys()
+                        .map(|key| Json::Str(format!("{:?}", key)))
+                        .collect(),
+                ),
+            ),
+            (
+                "raw_log".to_string(),
+                Json::Array(
+                    self.raw_log
+                        .entries
+                        .iter()
+                        .map(|(source, value)| {
+                            Json::Array(vec![Json::Str(source.clone()), Json::Str(value.clone())])
+                        })
+                        .collect(),
+                ),
+            ),
+            (
+                "permission_denied".to_string(),
+                Json::Bool(self.raw_log.permission_denied),
+            ),
+            ("sysfs_root".to_string(), optional(&self.sysfs_root)),
+            ("proc_root".to_string(), optional(&self.proc_root)),
+            ("strict".to_string(), Json::Bool(self.strict)),
+            ("stats".to_string(), Json::Bool(self.stats)),
+            (
+                "level_order".to_string(),
+                Json::Str(format!("{:?}", self.level_order)),
+            ),
+            ("element_dtype".to_string(), optional(&self.element_dtype)),
+        ]);
+
+        Json::Object(fields)
+    }
+
+    /// The `--json` document as a tree, for rendering it compact or pretty
+    ///
+    /// Its order is stable, so detecting the same machine twice renders the
+    /// same bytes: fields as listed here, and `performance_levels` as in
+    /// `levels_by_tier`, performance cores (or the only level) first, then
+    /// efficiency tiers by index, then other core groups.
+    pub fn to_json(&self) -> Json {
+        let levels = self
+            .levels_by_tier()
+            .into_iter()
+            .map(ProcessorLevel::to_json)
+            .collect();
+
+        Json::Object(vec![
+            (
+                "schema_version".to_string(),
+                Json::UInt(JSON_SCHEMA_VERSION),
+            ),
+            (
+                "architecture".to_string(),
+                Json::Str(self.architecture.clone()),
+            ),
+            ("model_name".to_string(), Json::Str(self.model_name.clone())),
+            ("os_name".to_string(), Json::Str(self.os_name.clone())),
+            (
+                "kernel_version".to_string(),
+                Json::Str(self.kernel_version.clone()),
+            ),
+            ("translated".to_string(), Json::Bool(self.translated)),
+            (
+                "nontemporal_stores".to_string(),
+                Json::Bool(self.nontemporal_stores),
+            ),
+            (
+                "limited_by_privileges".to_string(),
+                Json::Bool(self.limited_by_privileges),
+            ),
+            ("page_size".to_string(), Json::UInt(self.page_size)),
+            (
+                "sve_vector_bits".to_string(),
+                self.sve_vector_bits.map_or(Json::Null, Json::UInt),
+            ),
+            (
+                "max_turbo_hz".to_string(),
+                self.max_turbo_hz
+                    .map_or(Json::Null, |hz| Json::Float(hz as f64)),
+            ),
+            (
+                "all_core_turbo_hz".to_string(),
+                self.all_core_turbo_hz
+                    .map_or(Json::Null, |hz| Json::Float(hz as f64)),
+            ),
+            (
+                "endianness".to_string(),
+                Json::Str(self.endianness.as_str().to_string()),
+            ),
+            ("pointer_width".to_string(), Json::UInt(self.pointer_width)),
+            (
+                "effective_cpus".to_string(),
+                Json::from_option_f64(self.effective_cpus),
+            ),
+            (
+                "memory_bandwidth_gbps".to_string(),
+                Json::from_option_f64(self.memory_bandwidth_gbps),
+            ),
+            (
+                "memory_channels".to_string(),
+                self.memory_channels.map_or(Json::Null, Json::UInt),
+            ),
+            (
+                "memory_encryption".to_string(),
+                self.memory_encryption.clone().map_or(Json::Null, Json::Str),
+            ),
+            (
+                "llc_shared_with_gpu".to_string(),
+                self.llc_shared_with_gpu.map_or(Json::Null, Json::Bool),
+            ),
+            (
+                "matrix_extensions".to_string(),
+                Json::Array(
+                    self.matrix_extensions
+                        .iter()
+                        .map(|extension| Json::Str(extension.clone()))
+                        .collect(),
+                ),
+            ),
+            (
+                "allowed_cpus".to_string(),
+                self.allowed_cpus.as_ref().map_or(Json::Null, |cpus| {
+                    Json::Array(cpus.iter().map(|cpu| Json::UInt(*cpu)).collect())
+                }),
+            ),
+            (
+                "emulated".to_string(),
+                self.emulated.clone().map_or(Json::Null, Json::Str),
+            ),
+            (
+                "x86_signature".to_string(),
+                self.x86_signature.map_or(Json::Null, |signature| {
+                    Json::Object(vec![
+                        ("family".to_string(), Json::UInt(signature.family as usize)),
+                        ("model".to_string(), Json::UInt(signature.model as usize)),
+                        (
+                            "stepping".to_string(),
+                            Json::UInt(signature.stepping as usize),
+                        ),
+                    ])
+                }),
+            ),
+            (
+                "hugepages".to_string(),
+                Json::Array(
+                    self.hugepages
+                        .iter()
+                        .map(|hugepage| {
+                            Json::Object(vec![
+                                ("size".to_string(), Json::UInt(hugepage.size)),
+                                ("total".to_string(), Json::UInt(hugepage.total)),
+                                ("free".to_string(), Json::UInt(hugepage.free)),
+                                ("default".to_string(), Json::Bool(hugepage.default)),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ),
+            (
+                "physical_cores".to_string(),
+                self.physical_cores.map_or(Json::Null, Json::UInt),
+            ),
+            (
+                "logical_cores".to_string(),
+                self.logical_cores.map_or(Json::Null, Json::UInt),
+            ),
+            (
+                "total_memory".to_string(),
+                Json::from_option_f64(self.total_memory.map(|bytes| bytes as f64)),
+            ),
+            (
+                "assumed_defaults".to_string(),
+                Json::Array(
+                    self.assumed_defaults
+                        .iter()
+                        .map(|assumed| Json::Str(assumed.clone()))
+                        .collect(),
+                ),
+            ),
+            ("performance_levels".to_string(), Json::Array(levels)),
+        ])
+    }
+
+    /// Reads back what `to_json` wrote, None for another schema version
+    ///
+    /// Only detection results come back; the byte order and pointer width
+    /// are this build's and options like `strict` keep their defaults.
+    pub fn from_json(json: &Json) -> Option<Self> {
+        if json.get("schema_version")?.as_usize()? != JSON_SCHEMA_VERSION {
+            return None;
+        }
+
+        let text = |key| json.get(key)?.as_str().map(str::to_string);
+        let mut processor = ProcessorInfo::new();
+        processor.architecture = text("architecture")?;
+        processor.model_name = text("model_name")?;
+        // Fields added since version 1 are optional, so older reports load
+        processor.os_name = text("os_name").unwrap_or_default();
+        processor.kernel_version = text("kernel_version").unwrap_or_default();
+        processor.translated = json.get("translated")?.as_bool()?;
+        processor.nontemporal_stores = json
+            .get("nontemporal_stores")
+            .and_then(Json::as_bool)
+            .unwrap_or(false);
+        processor.limited_by_privileges = json
+            .get("limited_by_privileges")
+            .and_then(Json::as_bool)
+            .unwrap_or(false);
+        // Floats, since 32-bit targets can't hold 5 GHz in a usize
+        let hz = |key| json.get(key).and_then(Json::as_f64).map(|hz| hz as u64);
+        processor.max_turbo_hz = hz("max_turbo_hz");
+        processor.sve_vector_bits = json.get("sve_vector_bits").and_then(Json::as_usize);
+        processor.page_size = json.get("page_size").and_then(Json::as_usize).unwrap_or(0);
+        processor.all_core_turbo_hz = hz("all_core_turbo_hz");
+        processor.effective_cpus = json.get("effective_cpus").and_then(Json::as_f64);
+        processor.memory_bandwidth_gbps = json.get("memory_bandwidth_gbps").and_then(Json::as_f64);
+        processor.physical_cores = json.get("physical_cores").and_then(Json::as_usize);
+        processor.logical_cores = json.get("logical_cores").and_then(Json::as_usize);
+        // A float, since 32-bit targets can't hold 8 GB in a usize
+        processor.total_memory = json
+            .get("total_memory")
+            .and_then(Json::as_f64)
+            .map(|bytes| bytes as u64);
+        processor.memory_channels = json.get("memory_channels").and_then(Json::as_usize);
+        processor.memory_encryption = text("memory_encryption");
+        processor.llc_shared_with_gpu = json.get("llc_shared_with_gpu").and_then(Json::as_bool);
+        processor.matrix_extensions = json
+            .get("matrix_extensions")
+            .and_then(Json::as_array)
+            .map_or_else(Vec::new, |extensions| {
+                extensions
+                    .iter()
+                    .filter_map(|extension| Some(extension.as_str()?.to_string()))
+                    .collect()
+            });
+        processor.emulated = text("emulated");
+        processor.allowed_cpus = match json.get("allowed_cpus").unwrap_or(&Json::Null) {
+            Json::Null => None,
+            cpus => Some(
+                cpus.as_array()?
+                    .iter()
+                    .map(Json::as_usize)
+                    .collect::<Option<_>>()?,
+            ),
+        };
+
+        processor.x86_signature = json.get("x86_signature").and_then(|signature| {
+            let field = |key| Some(signature.get(key)?.as_usize()? as u32);
+            Some(X86Signature {
+                family: field("family")?,
+                model: field("model")?,
+                stepping: field("stepping")?,
+            })
+        });
+
+        for hugepage in json.get("hugepages")?.as_array()? {
+            let field = |key| hugepage.get(key)?.as_usize();
+            processor.hugepages.push(HugepageInfo {
+                size: field("size")?,
+                total: field("total")?,
+                free: field("free")?,
+                default: hugepage.get("default")?.as_bool()?,
+            });
+        }
+
+        for assumed in json.get("assumed_defaults")?.as_array()? {
+            processor
+                .assumed_defaults
+                .push(assumed.as_str()?.to_string());
+        }
+
+        for level in json.get("performance_levels")?.as_array()? {
+            let level = ProcessorLevel::from_json(level)?;
+            let key = LevelKey::from_name(&level.level_name)?;
+            processor.performance_levels.insert(key, level);
+        }
+
+        Some(processor)
+    }
+
+    /// The text report, rendered with the options this carries
+    pub fn display(&self) -> String {
+        render(self, &self.display_options())
+    }
+
+    /// `stats`, `level_order` and `element_dtype`, the options `display()`
+    /// renders with
+    pub fn display_options(&self) -> DisplayOptions {
+        DisplayOptions {
+            stats: self.stats,
+            level_order: self.level_order,
+            element_dtype: self.element_dtype.clone(),
+        }
+    }
+}
+
+/// The text report of `info`, as `display()` gives it, without detecting
+/// anything, so a `ProcessorInfo` read from JSON or a capture renders just
+/// like one detected live
+pub fn render(info: &ProcessorInfo, options: &DisplayOptions) -> String {
+    let mut result = Vec::new();
+
+    // The label is the machine, the parenthetical what this binary targets
+    result.push(format!(
+        "Architecture: {} ({} binary)",
+        info.architecture,
+        env::consts::ARCH
+    ));
+
+    result.push(format!(
+        "Byte Order: {}-endian, {}-bit pointers",
+        info.endianness.as_str(),
+        info.pointer_width
+    ));
+
+    if !info.model_name.is_empty() {
+        result.push(format!("CPU Model: {}", info.model_name));
+    }
+
+    // Right under the model, since it changes which kernel to pick
+    // more than any cache size
+    if !info.matrix_extensions.is_empty() {
+        result.push(format!(
+            "Matrix Extensions: {}",
+            info.matrix_extensions.join(", ")
+        ));
+    }
+
+    if !info.os_name.is_empty() {
+        result.push(format!("OS: {} {}", info.os_name, info.kernel_version));
+    }
+
+    let count = |cores: Option<usize>| cores.map_or("?".to_string(), |cores| cores.to_string());
+    if info.physical_cores.is_some() || info.logical_cores.is_some() {
+        result.push(format!(
+            "Cores: {} physical, {} logical",
+            count(info.physical_cores),
+            count(info.logical_cores)
+        ));
+    }
+
+    if let Some(bytes) = info.total_memory {
+        result.push(format!(
+            "Total Memory: {}",
+            format_size(usize::try_from(bytes).unwrap_or(usize::MAX))
+        ));
+    }
+
+    if let Some(signature) = info.x86_signature {
+        result.push(format!(
+            "CPU Family: {}, Model: {} (0x{:x}), Stepping: {}",
+            signature.family, signature.model, signature.model, signature.stepping
+        ));
+    }
+
+    if let Some(encryption) = &info.memory_encryption {
+        result.push(format!("Memory Encryption: {}", encryption));
+    }
+
+    if let Some(shared) = info.llc_shared_with_gpu {
+        result.push(format!(
+            "LLC Shared with GPU: {}",
+            if shared {
+                "yes, the GPU may take part of it"
+            } else {
+                "no"
+            }
+        ));
+    }
+
+    if info.nontemporal_stores {
+        result.push(format!(
+            "Non-temporal Stores: {}",
+            nontemporal_store_instruction().unwrap_or("yes")
+        ));
+    }
+
+    if info.page_size > 0 {
+        result.push(format!("Page Size: {}", format_size(info.page_size)));
+    }
+
+    if let Some(bits) = info.sve_vector_bits {
+        result.push(format!(
+            "SVE Vector Length: {} bits ({} f64 lanes)",
+            bits,
+            bits / 64
+        ));
+    }
+
+    if let Some(cpus) = &info.allowed_cpus {
+        result.push(format!(
+            "Note: affinity restricted to CPUs {}, caches shown are theirs alone",
+            format_cpu_list(cpus)
+        ));
+    }
+
+    if let Some(emulator) = &info.emulated {
+        result.push(format!(
+            "Note: running under {}, cache sizes may be synthetic",
+            emulator
+        ));
+    }
+
+    if info.limited_by_privileges {
+        let elevated = if cfg!(windows) {
+            "as Administrator"
+        } else {
+            "as root"
+        };
+        result.push(format!(
+            "Note: some sources were unreadable, running {} may reveal more detail",
+            elevated
+        ));
+    }
+
+    if info.translated {
+        result.push(format!(
+            "Note: {} binary running translated by Rosetta 2",
+            env::consts::ARCH
+        ));
+    }
+
+    if let Some(effective_cpus) = info.effective_cpus {
+        result.push(format!(
+            "Effective CPUs (cgroup quota): {:.2}",
+            effective_cpus
+        ));
+    }
+
+    if let Some(max_turbo) = info.max_turbo_hz {
+        let all_core = match info.all_core_turbo_hz {
+            Some(all_core) => format!("~{:.2} GHz (estimated)", all_core as f64 / 1e9),
+            None => "unknown".to_string(),
+        };
+        result.push(format!(
+            "Max Turbo: {:.2} GHz, All-core Turbo: {}",
+            max_turbo as f64 / 1e9,
+            all_core
+        ));
+    }
+
+    if let Some(bandwidth) = info.memory_bandwidth_gbps {
+        result.push(format!(
+            "Memory Bandwidth (STREAM Triad, 1 thread): {:.2} GB/s",
+            bandwidth
+        ));
+    }
+
+    if let Some(channels) = info.memory_channels {
+        result.push(format!("Memory Channels: {} populated", channels));
+    }
+
+    result.push("\nCache Information:".to_string());
+    result.push("==================".to_string());
+
+    for level in info.levels_sorted(options.level_order) {
+        result.extend(level.format());
+
+        if options.stats {
+            result.extend(level.format_stats());
+        }
+
+        if let Some(dtype) = &options.element_dtype {
+            if let Some(bytes_per_elem) = dtype_size(dtype) {
+                result.extend(level.format_elements(dtype, bytes_per_elem));
+            }
+        }
+
+        if let Some(warning) = level.line_size_warning() {
+            result.push(warning);
+        }
+        if let Some(warning) = level.hierarchy_warning() {
+            result.push(warning);
+        }
+    }
+
+    for assumed in &info.assumed_defaults {
+        result.push(format!(
+            "Note: assumed defaults, not detected ({})",
+            assumed
+        ));
+    }
+
+    if !info.hugepages.is_empty() {
+        result.push("\nHugepages".to_string());
+        result.push("---------".to_string());
+
+        for hugepage in &info.hugepages {
+            result.push(format!(
+                "{}: {} reserved, {} free{}",
+                CacheSize(hugepage.size),
+                hugepage.total,
+                hugepage.free,
+                if hugepage.default { " (default)" } else { "" }
+            ));
+        }
+    }
+
+    align_fields(&result.join("\n"))
+}
+
+// Helper functions
+
+// The report with its "Label: value" rows in columns, one layout per block of
+// rows between blank lines: labels left-aligned and padded to the longest,
+// and values that start with a size right-aligned on its unit. Headers,
+// notes and warnings are left as they are
+fn align_fields(report: &str) -> String {
+    let lines: Vec<&str> = report.lines().collect();
+    let mut result = Vec::with_capacity(lines.len());
+
+    for block in lines.split(|line| line.is_empty()) {
+        let rows: Vec<Option<(&str, &str, &str)>> = block
+            .iter()
+            .map(|line| {
+                if line.starts_with("Note: ") || line.starts_with("Warning: ") {
+                    return None;
+                }
+                let (label, value) = line.split_once(": ")?;
+                let (size, rest) = value.split_at(leading_size_len(value));
+                Some((label, size, rest))
+            })
+            .collect();
+
+        let label_width = rows.iter().flatten().map(|(label, ..)| label.len()).max();
+        let size_width = rows.iter().flatten().map(|(_, size, _)| size.len()).max();
+
+        for (line, row) in block.iter().zip(&rows) {
+            let (Some((label, size, rest)), Some(label_width), Some(size_width)) =
+                (row, label_width, size_width)
+            else {
+                result.push(line.to_string());
+                continue;
+            };
+
+            let label = format!("{}:", label);
+            let value = if size.is_empty() {
+                rest.to_string()
+            } else {
+                format!("{:>width$}{}", size, rest, width = size_width)
+            };
+            result.push(format!(
+                "{:<width$} {}",
+                label,
+                value,
+                width = label_width + 1
+            ));
+        }
+        result.push(String::new());
+    }
+    result.pop();
+
+    result.join("\n")
+}
+
+// Length of the "32.00 KB" or "Not detected" `format_size` put at the start
+// of `value`, 0 when it doesn't start with one
+fn leading_size_len(value: &str) -> usize {
+    if value.starts_with("Not detected") {
+        return "Not detected".len();
+    }
+
+    let number_len = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    if number_len == 0 {
+        return 0;
+    }
+
+    let after = &value[number_len..];
+    ["KB", "MB", "GB", "B"]
+        .iter()
+        .find(|unit| {
+            after.strip_prefix(' ').is_some_and(|after| {
+                after.starts_with(*unit)
+                    && !after[unit.len()..].starts_with(|c: char| c.is_ascii_alphabetic())
+            })
+        })
+        .map_or(0, |unit| number_len + 1 + unit.len())
+}
+
+// Numbers N of the `<prefix>N` entries in `dir`, sorted ("cpu0", "cpu12", ...)
+fn list_numbered_entries(dir: &str, prefix: &str) -> Vec<usize> {
+    let mut numbers: Vec<usize> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            name.strip_prefix(prefix)?.parse::<usize>().ok()
+        })
+        .collect();
+    numbers.sort_unstable();
+
+    numbers
+}
+
+// Stdout of a helper tool; one missing from PATH comes back as NotFound
+// naming it, so callers can tell that apart from the tool failing
+fn run_tool(program: &str, args: &[&str]) -> io::Result<String> {
+    check_subprocess(program)?;
+    match Command::new(program).args(args).env("LC_ALL", "C").output() {
+        Ok(output) => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} not found on PATH", program),
+        )),
+        Err(err) => Err(err),
+    }
+}
+
+fn run_sysctl(parameter: &str, raw_log: &mut RawLog) -> io::Result<String> {
+    #[cfg(target_os = "macos")]
+    let value = if subprocesses_allowed() {
+        run_tool("sysctl", &["-n", parameter])
+    } else {
+        sysctl_by_name(parameter)
+    };
+    #[cfg(not(target_os = "macos"))]
+    let value = run_tool("sysctl", &["-n", parameter]);
+
+    match value {
+        Ok(value) => {
+            raw_log.record(parameter, &value);
+            Ok(value.trim().to_string())
+        }
+        Err(err) => {
+            raw_log.record(parameter, &format!("<error: {}>", err));
+            Err(err)
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+extern "C" {
+    fn sysctlnametomib(name: *const std::ffi::c_char, mibp: *mut i32, sizep: *mut usize) -> i32;
+    fn sysctl(
+        name: *mut i32,
+        namelen: u32,
+        oldp: *mut std::ffi::c_void,
+        oldlenp: *mut usize,
+        newp: *mut std::ffi::c_void,
+        newlen: usize,
+    ) -> i32;
+}
+
+// Reads a sysctl without the tool, formatted as `sysctl -n` would. The value
+// is only bytes, so its type comes from the OID format the tool asks for too:
+// the undocumented {0, 4, mib...} node, answering a kind and a format string
+#[cfg(target_os = "macos")]
+pub(crate) fn sysctl_by_name(parameter: &str) -> io::Result<String> {
+    let name = std::ffi::CString::new(parameter).map_err(io::Error::other)?;
+    // CTL_MAXNAME, with room for the {0, 4} prefix of the format query
+    let mut mib = [0i32; 2 + 12];
+    mib[0] = 0;
+    mib[1] = 4;
+    let mut mib_len = mib.len() - 2;
+    // SAFETY: `name` is NUL-terminated and `mib[2..]` holds `mib_len` entries
+    if unsafe { sysctlnametomib(name.as_ptr(), mib[2..].as_mut_ptr(), &mut mib_len) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let query = |mib: &mut [i32]| -> io::Result<Vec<u8>> {
+        let mut length = 0;
+        // SAFETY: a null buffer only asks for the length
+        let size_query = unsafe {
+            sysctl(
+                mib.as_mut_ptr(),
+                mib.len() as u32,
+                std::ptr::null_mut(),
+                &mut length,
+                std::ptr::null_mut(),
+                0,
+            )
+        };
+        if size_query != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut buffer = vec![0u8; length];
+        // SAFETY: the buffer holds `length` bytes
+        let read = unsafe {
+            sysctl(
+                mib.as_mut_ptr(),
+                mib.len() as u32,
+                buffer.as_mut_ptr().cast(),
+                &mut length,
+                std::ptr::null_mut(),
+                0,
+            )
+        };
+        if read != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        buffer.truncate(length);
+        Ok(buffer)
+    };
+
+    let oid_format = query(&mut mib[..2 + mib_len])?;
+    let value = query(&mut mib[2..2 + mib_len])?;
+
+    // A u32 kind, then the NUL-terminated format
+    let format = oid_format.get(4..).unwrap_or_default();
+    let format = String::from_utf8_lossy(format.split(|&b| b == 0).next().unwrap_or_default());
+    format_sysctl_value(&format, &value).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "{} has format {:?}, not a number or string",
+                parameter, format
+            ),
+        )
+    })
+}
+
+// `sysctl -n` text of a raw value by its OID format: "A" a string, "I", "L"
+// and "Q" (un)signed ints, longs and quads, with arrays of them space
+// separated like hw.cacheconfig
+#[cfg(any(target_os = "macos", test))]
+fn format_sysctl_value(format: &str, value: &[u8]) -> Option<String> {
+    let unsigned = format.get(1..2) == Some("U");
+    let width = match format.get(..1)? {
+        "A" => {
+            let text = value.split(|&b| b == 0).next().unwrap_or_default();
+            return Some(String::from_utf8_lossy(text).into_owned());
+        }
+        "I" => 4,
+        "L" => usize::BITS as usize / 8,
+        "Q" => 8,
+        _ => return None,
+    };
+    if value.is_empty() || !value.len().is_multiple_of(width) {
+        return None;
+    }
+
+    let numbers: Vec<String> = value
+        .chunks(width)
+        .map(|bytes| match (width, unsigned) {
+            (4, true) => u32::from_ne_bytes(bytes.try_into().unwrap()).to_string(),
+            (4, false) => i32::from_ne_bytes(bytes.try_into().unwrap()).to_string(),
+            (_, true) => u64::from_ne_bytes(bytes.try_into().unwrap()).to_string(),
+            (_, false) => i64::from_ne_bytes(bytes.try_into().unwrap()).to_string(),
+        })
+        .collect();
+
+    Some(numbers.join(" "))
+}
+
+// Distinct channels among the installed "Memory Device" entries. Boards name
+// them in the bank locator ("P0 CHANNEL A", "NODE 1 CHANNEL 2 DIMM 0") or the
+// locator ("ChannelB-DIMM0", "CPU1_DIMM_C2"); the socket part is kept so the
+// same letter on two sockets counts twice. None when any installed device
+// names its channel some other way, or nothing is installed
+#[cfg(any(target_os = "linux", test))]
+fn parse_dmidecode_memory_channels(output: &str) -> Option<usize> {
+    let mut channels = std::collections::BTreeSet::new();
+
+    for device in output.split("\n\n") {
+        if !device.lines().any(|line| line.trim() == "Memory Device") {
+            continue;
+        }
+
+        let field = |name: &str| {
+            device.lines().find_map(|line| {
+                let (key, value) = line.trim().split_once(':')?;
+                (key == name).then(|| value.trim().to_ascii_lowercase())
+            })
+        };
+
+        let installed = field("Size").is_some_and(|size| {
+            !size.is_empty()
+                && !size.starts_with("no module")
+                && !size.starts_with("not installed")
+                && !size.starts_with('0')
+        });
+        if !installed {
+            continue;
+        }
+
+        let bank = field("Bank Locator").unwrap_or_default();
+        let locator = field("Locator").unwrap_or_default();
+        let channel = dmi_channel_key(&bank).or_else(|| dmi_channel_key(&locator));
+        channels.insert(channel.or_else(|| dmi_slot_key(&locator))?);
+    }
+
+    Some(channels.len()).filter(|&count| count > 0)
+}
+
+// "p0 channel a dimm 0" -> "p0 channel a"
+#[cfg(any(target_os = "linux", test))]
+fn dmi_channel_key(locator: &str) -> Option<String> {
+    let start = locator.find("channel")? + "channel".len();
+    let rest = &locator[start..];
+    let id_start = rest.len() - rest.trim_start_matches([' ', '_', '-']).len();
+    let id_len = rest[id_start..]
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .unwrap_or(rest.len() - id_start);
+    if id_len == 0 {
+        return None;
+    }
+
+    Some(locator[..start + id_start + id_len].to_string())
+}
+
+// Slots lettered by channel and numbered within it: "cpu1_dimm_c2" ->
+// "cpu1_dimm_c", "dimm a" -> "dimm a"
+#[cfg(any(target_os = "linux", test))]
+fn dmi_slot_key(locator: &str) -> Option<String> {
+    let slot = locator.rsplit([' ', '_', '-']).next()?;
+    let mut chars = slot.chars();
+    let letter = chars.next().filter(char::is_ascii_alphabetic)?;
+    if !chars.all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let prefix = &locator[..locator.len() - slot.len()];
+    Some(format!("{}{}", prefix, letter))
+}
+
+// "L1d cache:   384 KiB (8 instances)" from newer util-linux gives the total
+// over all instances, older ones print the per-core "32K" alone
+pub(crate) fn parse_lscpu(output: &str, proc_level: &mut ProcessorLevel) {
+    for line in output.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+
+        let (size, instances) = match value.split_once('(') {
+            Some((size, instances)) => {
+                let instances = instances
+                    .split_whitespace()
+                    .next()
+                    .and_then(|count| count.parse::<usize>().ok())
+                    .unwrap_or(1);
+                (size, instances.max(1))
+            }
+            None => (value, 1),
+        };
+        let size = crate::size::parse_size_with_unit(size) / instances;
+
+        match key.trim() {
+            "L1d cache" => proc_level.l1_cache.data_size = size,
+            "L1i cache" => proc_level.l1_cache.instruction_size = size,
+            "L2 cache" => proc_level.l2_cache = size,
+            "L3 cache" => proc_level.l3_cache = size,
+            _ => {}
+        }
+    }
+}
+
+// wmic's L1CacheSize, L2CacheSize and L3CacheSize are in KB
+const WMIC_CACHE_UNIT: usize = 1024;
+
+/// How each source this platform reads gives its sizes, and how detection
+/// scales them to bytes
+pub fn unit_assumptions() -> &'static [&'static str] {
+    if cfg!(target_os = "macos") {
+        &["sysctl hw.*cachesize: bytes, unscaled"]
+    } else if cfg!(windows) {
+        &[
+            "GetLogicalProcessorInformation: bytes, unscaled",
+            "wmic L*CacheSize: KB, x1024",
+        ]
+    } else if cfg!(target_os = "linux") {
+        &[
+            "sysfs cache/index*/size: K, M or G suffix, x1024 per step",
+            "lscpu: K, M, KiB or MiB suffix, x1024 per step",
+            "/proc/cpuinfo cache size: KB, x1024",
+        ]
+    } else {
+        &["sizes with a K, M or G suffix: x1024 per step"]
+    }
+}
+
+// `wmic ... /value` prints "L2CacheSize=2048" lines, in KB. Localized
+// Windows may change the key's case or group digits as "8.192", "8,192" or
+// "8 192", so keys are matched ignoring case and separators are dropped.
+// Gives each line's key, its value as printed and the KB it parsed to
+fn parse_wmic_cache_lines(output: &str) -> Vec<(&str, &str, usize)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.trim().split_once('=')?;
+            let key = key.trim();
+            let known = ["l1cachesize", "l2cachesize", "l3cachesize"]
+                .contains(&key.to_ascii_lowercase().as_str());
+            known.then_some((key, value.trim(), parse_wmic_number(value)?))
+        })
+        .collect()
+}
+
+pub(crate) fn parse_wmic_caches(output: &str, proc_level: &mut ProcessorLevel) {
+    for (key, _, kb) in parse_wmic_cache_lines(output) {
+        let size = kb * WMIC_CACHE_UNIT;
+        match key.to_ascii_lowercase().as_str() {
+            "l1cachesize" => proc_level.l1_cache.unified_size = size,
+            "l2cachesize" => proc_level.l2_cache = size,
+            _ => proc_level.l3_cache = size,
+        }
+    }
+}
+
+// Digits with any thousands separator a locale uses between them: '.', ',',
+// an apostrophe or a (narrow, no-break) space
+fn parse_wmic_number(value: &str) -> Option<usize> {
+    let value = value.trim();
+    let is_separator = |c: char| matches!(c, '.' | ',' | '\'' | ' ' | '\u{a0}' | '\u{202f}');
+    if !value.starts_with(|c: char| c.is_ascii_digit())
+        || !value.chars().all(|c| c.is_ascii_digit() || is_separator(c))
+    {
+        return None;
+    }
+
+    value
+        .chars()
+        .filter(char::is_ascii_digit)
+        .collect::<String>()
+        .parse()
+        .ok()
+}
+
+// The "Name=" line of `wmic cpu get name /value`, whatever the key's case
+pub(crate) fn parse_wmic_name(output: &str) -> Option<String> {
+    output
+        .lines()
+        .filter_map(|line| line.trim().split_once('='))
+        .find(|(key, _)| key.trim().eq_ignore_ascii_case("name"))
+        .map(|(_, name)| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+// `kstat -p` prints one "module:instance:name:statistic<TAB>value" line per
+// statistic; only the first CPU is looked at
+pub(crate) fn parse_kstat_cpu_info(output: &str, proc_level: &mut ProcessorLevel) {
+    for line in output.lines() {
+        let Some((key, value)) = line.split_once('\t') else {
+            continue;
+        };
+        if !key.starts_with("cpu_info:0:") {
+            continue;
+        }
+
+        let size = value.trim().parse::<usize>().unwrap_or(0);
+        match key.rsplit(':').next().unwrap_or("") {
+            "l1_icache_size" => proc_level.l1_cache.instruction_size = size,
+            "l1_dcache_size" => proc_level.l1_cache.data_size = size,
+            "l1_cache_size" => proc_level.l1_cache.unified_size = size,
+            "l2_cache_size" => proc_level.l2_cache = size,
+            "l3_cache_size" => proc_level.l3_cache = size,
+            _ => {}
+        }
+    }
+}
+
+// "model name" of the first processor; ARM kernels often leave it out
+pub(crate) fn parse_cpuinfo_model_name(contents: &str) -> Option<&str> {
+    contents.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        (key.trim() == "model name").then(|| value.trim())
+    })
+}
+
+// The ISA a /proc/cpuinfo was written on, named as `detect_architecture`
+// does, from the fields only its kernel port prints
+pub(crate) fn parse_cpuinfo_architecture(contents: &str) -> Option<&'static str> {
+    contents.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        match key.trim() {
+            "vendor_id" => Some("x86"),
+            "CPU implementer" => Some("ARM"),
+            "cpu" if value.trim().starts_with("POWER") => Some("PowerPC"),
+            _ => None,
+        }
+    })
+}
+
+// "cpu family", "model" and "stepping" of the first processor in
+// /proc/cpuinfo; ARM and POWER don't have these lines, so they get None
+fn parse_cpuinfo_signature(contents: &str) -> Option<X86Signature> {
+    let field = |name: &str| {
+        contents.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            if key.trim() == name {
+                value.trim().parse::<u32>().ok()
+            } else {
+                None
+            }
+        })
+    };
+
+    Some(X86Signature {
+        family: field("cpu family")?,
+        model: field("model")?,
+        stepping: field("stepping")?,
+    })
+}
+
+// The first "flags" line, the x86 features of the first processor
+fn parse_cpuinfo_flags(contents: &str) -> Option<&str> {
+    contents.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        (key.trim() == "flags").then_some(value)
+    })
+}
+
+// The flags name SME, SEV and TME support in lower case
+fn parse_cpuinfo_memory_encryption(contents: &str) -> Option<String> {
+    let flags = parse_cpuinfo_flags(contents)?;
+
+    let features: Vec<_> = [
+        ("sme", "SME"),
+        ("sev", "SEV"),
+        ("sev_es", "SEV-ES"),
+        ("sev_snp", "SEV-SNP"),
+        ("tme", "TME"),
+    ]
+    .into_iter()
+    .filter(|&(flag, _)| flags.split_whitespace().any(|f| f == flag))
+    .map(|(_, name)| name)
+    .collect();
+
+    if features.is_empty() {
+        None
+    } else {
+        Some(features.join(", "))
+    }
+}
+
+// AMX shows up as amx_tile, amx_bf16 and so on, in CPUID's order
+fn parse_cpuinfo_matrix_extensions(contents: &str) -> Vec<String> {
+    let Some(flags) = parse_cpuinfo_flags(contents) else {
+        return Vec::new();
+    };
+
+    [
+        ("amx_tile", "AMX-TILE"),
+        ("amx_bf16", "AMX-BF16"),
+        ("amx_int8", "AMX-INT8"),
+        ("amx_fp16", "AMX-FP16"),
+        ("amx_complex", "AMX-COMPLEX"),
+    ]
+    .into_iter()
+    .filter(|&(flag, _)| flags.split_whitespace().any(|f| f == flag))
+    .map(|(_, name)| name.to_string())
+    .collect()
+}
+
+// "cache size : 8192 KB" of the first processor, always in KB
+fn parse_cpuinfo_cache_size(contents: &str) -> Option<usize> {
+    contents.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        if key.trim() != "cache size" {
+            return None;
+        }
+
+        try_parse_size_with_unit(value)
+            .ok()
+            .filter(|size| *size > 0)
+    })
+}
+
+// Some locales group digits ("4,194,304" or "4.194.304") even with LC_ALL=C
+// set, so everything but the digits is dropped; unparsable values become 0
+fn parse_sysctl_number(value: &str) -> usize {
+    value
+        .chars()
+        .filter(char::is_ascii_digit)
+        .collect::<String>()
+        .parse::<usize>()
+        .unwrap_or(0)
+}
+
+// "Efficiency Cores (Level 1)" -> "efficiency_cores_level_1"
+fn metric_label(name: &str) -> String {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_ascii_lowercase)
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+// Quotes a field holding the separator, a quote or a line break
+fn csv_escape(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn prometheus_escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// A broken WMI repository can make wmic hang forever
+#[cfg(windows)]
+const WMIC_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Runs wmic under `WMIC_TIMEOUT`; on failure or timeout it logs the error and
+// returns None so detection carries on with zeros instead of blocking
+#[cfg(windows)]
+fn run_wmic(args: &[&str], raw_log: &mut RawLog) -> Option<String> {
+    let source = format!("wmic {}", args.join(" "));
+
+    let mut command = Command::new("wmic");
+    command.args(args);
+
+    match output_with_timeout(&mut command, WMIC_TIMEOUT) {
+        Ok(stdout) => {
+            raw_log.record(&source, &stdout);
+            Some(stdout)
+        }
+        Err(err) => {
+            raw_log.record(&source, &format!("<error: {}>", err));
+            None
+        }
+    }
+}
+
+#[cfg(windows)]
+fn output_with_timeout(command: &mut Command, timeout: Duration) -> io::Result<String> {
+    check_subprocess(&command.get_program().to_string_lossy())?;
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    // Drain stdout on a thread so a chatty child can't stall on a full pipe
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let reader = thread::spawn(move || {
+        let mut buffer = Vec::new();
+        stdout.read_to_end(&mut buffer).map(|_| buffer)
+    });
+
+    let deadline = Instant::now() + timeout;
+    while child.try_wait()?.is_none() {
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("timed out after {}s", timeout.as_secs()),
+            ));
+        }
+
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    let buffer = reader
+        .join()
+        .map_err(|_| io::Error::other("stdout reader panicked"))??;
+
+    Ok(String::from_utf8_lossy(&buffer).into_owned())
+}
+
+// HKEY_LOCAL_MACHINE is the sign-extended 0x80000002
+#[cfg(windows)]
+const HKEY_LOCAL_MACHINE: isize = 0x8000_0002_u32 as i32 as isize;
+#[cfg(windows)]
+const RRF_RT_REG_SZ: u32 = 0x2;
+
+#[cfg(windows)]
+#[link(name = "advapi32")]
+extern "system" {
+    fn RegGetValueW(
+        key: isize,
+        sub_key: *const u16,
+        value: *const u16,
+        flags: u32,
+        kind: *mut u32,
+        data: *mut std::ffi::c_void,
+        length: *mut u32,
+    ) -> i32;
+}
+
+// The processor brand string, read from the registry without spawning
+// a process
+#[cfg(windows)]
+pub(crate) fn registry_cpu_name() -> io::Result<String> {
+    let wide = |text: &str| text.encode_utf16().chain(Some(0)).collect::<Vec<u16>>();
+    let (key, value) = CPU_NAME_REGISTRY;
+    let sub_key = wide(key.strip_prefix(r"HKLM\").unwrap_or(key));
+    let value = wide(value);
+
+    let mut buffer = [0u16; 256];
+    let mut length = std::mem::size_of_val(&buffer) as u32;
+    // SAFETY: both names are NUL-terminated and the buffer holds `length`
+    // bytes
+    let status = unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            sub_key.as_ptr(),
+            value.as_ptr(),
+            RRF_RT_REG_SZ,
+            std::ptr::null_mut(),
+            buffer.as_mut_ptr().cast(),
+            &mut length,
+        )
+    };
+    if status != 0 {
+        return Err(io::Error::from_raw_os_error(status));
+    }
+
+    let chars = (length as usize / 2).min(buffer.len());
+    let name = String::from_utf16_lossy(&buffer[..chars]);
+    let name = name.trim_matches('\0').trim();
+    if name.is_empty() {
+        return Err(io::Error::other("empty brand string"));
+    }
+
+    Ok(name.to_string())
+}
+
+// OSVERSIONINFOW; GetVersionEx lies to unmanifested programs, RtlGetVersion
+// doesn't
+#[cfg(windows)]
+#[repr(C)]
+struct OsVersionInfo {
+    size: u32,
+    major: u32,
+    minor: u32,
+    build: u32,
+    platform_id: u32,
+    service_pack: [u16; 128],
+}
+
+#[cfg(windows)]
+#[link(name = "ntdll")]
+extern "system" {
+    fn RtlGetVersion(info: *mut OsVersionInfo) -> i32;
+}
+
+// getpagesize is on every Unix, unlike the value of _SC_PAGESIZE
+#[cfg(unix)]
+extern "C" {
+    fn getpagesize() -> i32;
+}
+
+// Mirror of the Win32 SYSTEM_INFO
+#[cfg(windows)]
+#[repr(C)]
+struct SystemInfo {
+    processor_architecture: u16,
+    reserved: u16,
+    page_size: u32,
+    minimum_application_address: *mut u8,
+    maximum_application_address: *mut u8,
+    active_processor_mask: usize,
+    number_of_processors: u32,
+    processor_type: u32,
+    allocation_granularity: u32,
+    processor_level: u16,
+    processor_revision: u16,
+}
+
+#[cfg(windows)]
+#[link(name = "kernel32")]
+extern "system" {
+    fn GetSystemInfo(info: *mut SystemInfo);
+}
+
+fn system_page_size() -> usize {
+    // SAFETY: getpagesize takes nothing and can't fail
+    #[cfg(unix)]
+    return unsafe { getpagesize() }.max(0) as usize;
+
+    #[cfg(windows)]
+    return windows_page_size();
+
+    #[cfg(not(any(unix, windows)))]
+    0
+}
+
+#[cfg(windows)]
+fn windows_page_size() -> usize {
+    // SAFETY: SYSTEM_INFO is plain data, GetSystemInfo fills in all of it
+    let mut info: SystemInfo = unsafe { std::mem::zeroed() };
+    unsafe { GetSystemInfo(&mut info) };
+
+    info.page_size as usize
+}
+
+// "10.0.22631" for Windows 11 23H2
+#[cfg(windows)]
+fn windows_version(raw_log: &mut RawLog) -> Option<String> {
+    let mut info = OsVersionInfo {
+        size: std::mem::size_of::<OsVersionInfo>() as u32,
+        major: 0,
+        minor: 0,
+        build: 0,
+        platform_id: 0,
+        service_pack: [0; 128],
+    };
+
+    // SAFETY: `info` is an OSVERSIONINFOW with its size filled in
+    let status = unsafe { RtlGetVersion(&mut info) };
+    if status != 0 {
+        raw_log.record("RtlGetVersion", &format!("<error: NTSTATUS {:#x}>", status));
+        return None;
+    }
+
+    let version = format!("{}.{}.{}", info.major, info.minor, info.build);
+    raw_log.record("RtlGetVersion", &version);
+    Some(version)
+}
+
+// Mirrors of the Win32 structures behind GetLogicalProcessorInformation
+#[cfg(windows)]
+const RELATION_CACHE: u32 = 2;
+#[cfg(windows)]
+const CACHE_UNIFIED: u32 = 0;
+#[cfg(windows)]
+const CACHE_INSTRUCTION: u32 = 1;
+#[cfg(windows)]
+const CACHE_DATA: u32 = 2;
+#[cfg(windows)]
+const ERROR_INSUFFICIENT_BUFFER: i32 = 122;
+#[cfg(all(windows, feature = "sysinfo"))]
+const RELATION_PROCESSOR_CORE: u32 = 0;
+
+#[cfg(windows)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CacheDescriptor {
+    level: u8,
+    associativity: u8,
+    line_size: u16,
+    size: u32,
+    cache_type: u32,
+}
+
+#[cfg(windows)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+union ProcessorInformation {
+    cache: CacheDescriptor,
+    reserved: [u64; 2],
+}
+
+#[cfg(windows)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct LogicalProcessorInformation {
+    processor_mask: usize,
+    relationship: u32,
+    information: ProcessorInformation,
+}
+
+#[cfg(windows)]
+struct LogicalProcessorCache {
+    processor_mask: usize,
+    descriptor: CacheDescriptor,
+}
+
+#[cfg(windows)]
+#[link(name = "kernel32")]
+extern "system" {
+    fn GetLogicalProcessorInformation(
+        buffer: *mut LogicalProcessorInformation,
+        returned_length: *mut u32,
+    ) -> i32;
+}
+
+#[cfg(windows)]
+fn logical_processor_information() -> io::Result<Vec<LogicalProcessorInformation>> {
+    let entry_size = std::mem::size_of::<LogicalProcessorInformation>();
+
+    // The first call only reports how large the buffer has to be
+    let mut length = 0u32;
+    // SAFETY: a null buffer with a zero length is the documented size query
+    if unsafe { GetLogicalProcessorInformation(std::ptr::null_mut(), &mut length) } == 0 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(ERROR_INSUFFICIENT_BUFFER) {
+            return Err(err);
+        }
+    }
+
+    let mut entries = Vec::with_capacity(length as usize / entry_size + 1);
+    length = (entries.capacity() * entry_size) as u32;
+    // SAFETY: the buffer holds `length` bytes of properly aligned entries
+    if unsafe { GetLogicalProcessorInformation(entries.as_mut_ptr(), &mut length) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: the call initialised `length` bytes worth of entries
+    unsafe { entries.set_len(length as usize / entry_size) };
+
+    Ok(entries)
+}
+
+#[cfg(windows)]
+fn logical_processor_caches() -> io::Result<Vec<LogicalProcessorCache>> {
+    Ok(logical_processor_information()?
+        .into_iter()
+        .filter(|entry| entry.relationship == RELATION_CACHE)
+        .map(|entry| LogicalProcessorCache {
+            processor_mask: entry.processor_mask,
+            // SAFETY: `cache` is the active variant for RelationCache entries
+            descriptor: unsafe { entry.information.cache },
+        })
+        .collect())
+}
+
+// Physical and logical cores: one RelationProcessorCore entry per physical
+// core, its SMT threads in the mask
+#[cfg(all(windows, feature = "sysinfo"))]
+pub(crate) fn processor_core_counts() -> io::Result<(usize, usize)> {
+    let cores: Vec<_> = logical_processor_information()?
+        .into_iter()
+        .filter(|entry| entry.relationship == RELATION_PROCESSOR_CORE)
+        .collect();
+    let threads = cores
+        .iter()
+        .map(|core| core.processor_mask.count_ones() as usize)
+        .sum();
+
+    Ok((cores.len(), threads))
+}
+
+fn read_file(path: &str, raw_log: &mut RawLog) -> io::Result<String> {
+    let mut content = String::new();
+
+    if let Err(err) = File::open(path).and_then(|mut file| file.read_to_string(&mut content)) {
+        raw_log.permission_denied |= err.kind() == io::ErrorKind::PermissionDenied;
+        raw_log.record(path, &format!("<error: {}>", err));
+        return Err(err);
+    }
+
+    raw_log.record(path, &content);
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn only_denied_reads_limit_an_unprivileged_detection() {
+        if cfg!(any(unix, windows)) {
+            assert_ne!(privilege_level(), PrivilegeLevel::Unknown);
+        }
+
+        // A missing file is no hint that more privileges would help
+        let mut raw_log = RawLog::default();
+        assert!(read_file("/matmul-utils/no/such/file", &mut raw_log).is_err());
+        assert!(!raw_log.permission_denied);
+
+        let processor = detect_sysfs_fixture(
+            "privileges",
+            &[&[("level", "2"), ("type", "Unified"), ("size", "1M")]],
+            &[],
+        );
+        assert!(!processor.limited_by_privileges);
+    }
+
+    #[test]
+    fn reads_the_page_size_of_the_live_system_only() {
+        let mut processor = ProcessorInfo::new();
+        processor.detect_architecture();
+        if cfg!(any(unix, windows)) {
+            assert!(processor.page_size.is_power_of_two());
+            assert!(processor.page_size >= 4096);
+        }
+
+        let mut captured = ProcessorInfo::new();
+        captured.sysfs_root = Some("/matmul-utils/captured".to_string());
+        assert_eq!(captured.detect_page_size(), 0);
+    }
+
+    #[test]
+    fn reports_l1_indexing_of_the_running_isa_only() {
+        assert_eq!(l1_data_indexing("x86"), Some("VIPT"));
+        assert_eq!(l1_data_indexing("PowerPC"), None);
+
+        let mut processor = ProcessorInfo::new();
+        processor.detect_architecture();
+        processor.performance_levels.insert(LevelKey::Default, {
+            let mut level = ProcessorLevel::new(LevelKey::Default);
+            level.l1_cache.data_size = 32 * 1024;
+            level
+        });
+        processor.assign_l1_indexing();
+        let expected = l1_data_indexing(&processor.architecture).map(str::to_string);
+        assert_eq!(
+            processor.performance_levels[&LevelKey::Default].l1_indexing,
+            expected
+        );
+
+        let captured = detect_sysfs_fixture(
+            "l1-indexing",
+            &[&[("level", "1"), ("type", "Data"), ("size", "48K")]],
+            &[],
+        );
+        let level = &captured.performance_levels[&LevelKey::Default];
+        assert!(level.l1_cache.data_size > 0);
+        assert_eq!(level.l1_indexing, None);
+    }
+
+    #[test]
+    fn reports_nontemporal_stores_for_the_target() {
+        let mut processor = ProcessorInfo::new();
+        processor.detect_architecture();
+
+        // 32-bit x86 depends on SSE2 at runtime
+        if !cfg!(target_arch = "x86") {
+            let expected = cfg!(any(target_arch = "x86_64", target_arch = "aarch64"));
+            assert_eq!(processor.nontemporal_stores, expected);
+        }
+        assert_eq!(
+            processor.display().contains("Non-temporal Stores: "),
+            processor.nontemporal_stores
+        );
+    }
+
+    #[test]
+    fn repeated_detection_is_deterministic() {
+        let mut processor = ProcessorInfo::new();
+        processor.detect_architecture();
+
+        let first = processor.collect_cache_info().unwrap().display();
+        let first_levels = processor.performance_levels.clone();
+        // What was read, not what it said: /proc/self/status and meminfo
+        // change from one read to the next
+        let sources = |raw_log: &RawLog| -> Vec<String> {
+            raw_log
+                .entries
+                .iter()
+                .map(|(source, _)| source.clone())
+                .collect()
+        };
+        let first_sources = sources(&processor.raw_log);
+
+        // Leftover state from a previous run must not leak into the next one
+        processor
+            .performance_levels
+            .insert(LevelKey::Group(9), ProcessorLevel::new(LevelKey::Group(9)));
+
+        let second = processor.collect_cache_info().unwrap().display();
+
+        assert_eq!(first, second);
+        assert_eq!(processor.performance_levels, first_levels);
+        assert_eq!(sources(&processor.raw_log), first_sources);
+
+        processor.detect_architecture();
+        processor.collect_cache_info().unwrap();
+        assert_eq!(sources(&processor.raw_log), first_sources);
+    }
+
+    // Builds a throwaway sysfs tree, one list of (file, content) pairs per
+    // cache index plus `extra` files relative to the root, and runs the Linux
+    // parser over it
+    fn detect_sysfs_fixture(
+        name: &str,
+        indices: &[&[(&str, &str)]],
+        extra: &[(&str, &str)],
+    ) -> ProcessorInfo {
+        let root = env::temp_dir().join(format!("matmul-utils-{}-{}", name, std::process::id()));
+
+        for (i, files) in indices.iter().enumerate() {
+            let dir = root.join(format!("devices/system/cpu/cpu0/cache/index{}", i));
+            std::fs::create_dir_all(&dir).unwrap();
+
+            for (file, content) in files.iter() {
+                std::fs::write(dir.join(file), format!("{}\n", content)).unwrap();
+            }
+        }
+
+        for (file, content) in extra.iter() {
+            let path = root.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, format!("{}\n", content)).unwrap();
+        }
+
+        let mut processor = ProcessorInfo::new();
+        processor.sysfs_root = Some(root.to_string_lossy().into_owned());
+        processor.collect_cache_info().unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        processor
+    }
+
+    fn collect_sysfs_fixture(
+        name: &str,
+        indices: &[&[(&str, &str)]],
+        extra: &[(&str, &str)],
+    ) -> ProcessorLevel {
+        detect_sysfs_fixture(name, indices, extra)
+            .performance_levels
+            .remove(&LevelKey::Default)
+            .unwrap()
+    }
+
+    #[test]
+    fn reads_cache_info_from_captured_sysfs_tree() {
+        let level = collect_sysfs_fixture(
+            "sysfs",
+            &[
+                &[("level", "1"), ("type", "Data"), ("size", "32K")],
+                &[("level", "1"), ("type", "Instruction"), ("size", "32K")],
+                &[("level", "2"), ("type", "Unified"), ("size", "1M")],
+            ],
+            &[],
+        );
+
+        assert_eq!(level.l1_cache.data_size, 32 * 1024);
+        assert_eq!(level.l1_cache.instruction_size, 32 * 1024);
+        assert_eq!(level.l2_cache, 1024 * 1024);
+        assert_eq!(level.l3_cache, 0);
+    }
+
+    #[test]
+    fn summarizes_the_primary_level() {
+        let mut processor = detect_sysfs_fixture(
+            "summary",
+            &[
+                &[
+                    ("level", "1"),
+                    ("type", "Data"),
+                    ("size", "48K"),
+                    ("coherency_line_size", "64"),
+                ],
+                &[("level", "2"), ("type", "Unified"), ("size", "2M")],
+                &[("level", "3"), ("type", "Unified"), ("size", "30M")],
+            ],
+            &[],
+        );
+        processor.allowed_cpus = Some(vec![0, 1, 2, 3]);
+
+        assert_eq!(
+            processor.summary(),
+            Summary {
+                l1_data: 48 * 1024,
+                l2: 2 * 1024 * 1024,
+                l3: 30 * 1024 * 1024,
+                line_size: 64,
+                logical_cpus: 4,
+            }
+        );
+        assert_eq!(ProcessorInfo::new().summary().l2, 0);
+    }
+
+    #[test]
+    fn aligns_labels_and_sizes_in_columns() {
+        let report = "Architecture: x86\nOS: Linux\n\nDefault\n-------\nL1 Data Cache: 48.00 KB\nL2 Cache: 2.00 MB (per-core)\nL3 Cache: Not detected\nWarning: L2 (2.00 MB) is larger than L3";
+        assert_eq!(
+            align_fields(report),
+            [
+                "Architecture: x86",
+                "OS:           Linux",
+                "",
+                "Default",
+                "-------",
+                "L1 Data Cache:     48.00 KB",
+                "L2 Cache:           2.00 MB (per-core)",
+                "L3 Cache:      Not detected",
+                "Warning: L2 (2.00 MB) is larger than L3",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn tells_asymmetric_cores_apart() {
+        let with_levels = |levels: &[(LevelKey, usize, usize)]| {
+            let mut processor = ProcessorInfo::new();
+            for &(key, l1_data, l2) in levels {
+                let mut level = ProcessorLevel::new(key);
+                level.l1_cache.data_size = l1_data;
+                level.l2_cache = l2;
+                processor.performance_levels.insert(key, level);
+            }
+            processor
+        };
+
+        let hybrid = with_levels(&[
+            (LevelKey::Performance, 128 << 10, 12 << 20),
+            (LevelKey::Efficiency(1), 64 << 10, 4 << 20),
+        ]);
+        assert!(hybrid.is_heterogeneous());
+
+        let homogeneous = with_levels(&[(LevelKey::Default, 32 << 10, 1 << 20)]);
+        assert!(!homogeneous.is_heterogeneous());
+        // Two tiers of identical cores, or one not detected, aren't told apart
+        let identical = with_levels(&[
+            (LevelKey::Performance, 32 << 10, 1 << 20),
+            (LevelKey::Efficiency(1), 32 << 10, 1 << 20),
+            (LevelKey::Efficiency(2), 0, 0),
+        ]);
+        assert!(!identical.is_heterogeneous());
+        assert!(!ProcessorInfo::new().is_heterogeneous());
+    }
+
+    #[test]
+    fn picks_the_smallest_and_largest_l2_across_levels() {
+        let mut processor = ProcessorInfo::new();
+        for (key, l2) in [
+            (LevelKey::Performance, 16 << 20),
+            (LevelKey::Efficiency(1), 4 << 20),
+            (LevelKey::Efficiency(2), 2 << 20),
+            (LevelKey::Efficiency(3), 0),
+        ] {
+            let mut level = ProcessorLevel::new(key);
+            level.l2_cache = l2;
+            processor.performance_levels.insert(key, level);
+        }
+
+        let worst = processor.worst_case_level().unwrap();
+        assert_eq!(worst.level_name, LevelKey::Efficiency(2).to_string());
+        let best = processor.best_case_level().unwrap();
+        assert_eq!(best.level_name, LevelKey::Performance.to_string());
+
+        assert!(ProcessorInfo::new().worst_case_level().is_none());
+    }
+
+    #[test]
+    fn matches_cache_types_case_insensitively() {
+        let level = collect_sysfs_fixture(
+            "sysfs-type-case",
+            &[
+                &[("level", "1"), ("type", "data"), ("size", "48K")],
+                &[("level", "1"), ("type", "INSTRUCTION"), ("size", "32K")],
+                &[("level", "2"), ("type", "UNIFIED"), ("size", "1M")],
+            ],
+            &[],
+        );
+        assert_eq!(level.l1_cache.data_size, 48 * 1024);
+        assert_eq!(level.l1_cache.instruction_size, 32 * 1024);
+        assert_eq!(level.l2_cache, 1024 * 1024);
+
+        let level = collect_sysfs_fixture(
+            "sysfs-type-upper",
+            &[&[("level", "1"), ("type", "DATA"), ("size", "32K")]],
+            &[],
+        );
+        assert_eq!(level.l1_cache.data_size, 32 * 1024);
+
+        // An unknown L1 type is logged and dropped, it can't be guessed
+        let level = collect_sysfs_fixture(
+            "sysfs-type-unknown",
+            &[
+                &[("level", "1"), ("type", "Trace"), ("size", "12K")],
+                &[("level", "2"), ("type", "Trace"), ("size", "1M")],
+            ],
+            &[],
+        );
+        assert_eq!(level.l1_cache.data_size, 0);
+        assert_eq!(level.l1_cache.unified_size, 0);
+        assert_eq!(level.l1_cache.instruction_size, 0);
+        assert_eq!(level.l2_cache, 1024 * 1024);
+        assert_eq!(normalize_cache_type(" Dcache\n"), Some("Data"));
+        assert_eq!(normalize_cache_type("Trace"), None);
+    }
+
+    #[test]
+    fn computes_a_missing_size_from_the_geometry() {
+        let level = collect_sysfs_fixture(
+            "sysfs-no-size",
+            &[
+                &[
+                    ("level", "1"),
+                    ("type", "Data"),
+                    ("number_of_sets", "64"),
+                    ("ways_of_associativity", "12"),
+                    ("coherency_line_size", "64"),
+                ],
+                &[
+                    ("level", "2"),
+                    ("type", "Unified"),
+                    ("number_of_sets", "2048"),
+                    ("ways_of_associativity", "16"),
+                    ("coherency_line_size", "64"),
+                ],
+                // Nothing to compute it from, still skipped
+                &[("level", "3"), ("type", "Unified")],
+            ],
+            &[],
+        );
+
+        assert_eq!(level.l1_cache.data_size, 48 * 1024);
+        assert_eq!(level.l2_cache, 2 * 1024 * 1024);
+        assert_eq!(level.l2_line_size, 64);
+        assert_eq!(level.l3_cache, 0);
+    }
+
+    #[test]
+    fn keeps_indices_with_only_level_and_size_readable() {
+        let level = collect_sysfs_fixture(
+            "sysfs-partial",
+            &[
+                &[("level", "1"), ("type", "Data"), ("size", "32K")],
+                &[("level", "1"), ("size", "32K")],
+                &[("level", "2"), ("size", "1M")],
+                &[("level", "3"), ("type", "Unified")],
+            ],
+            &[],
+        );
+
+        assert_eq!(level.l1_cache.data_size, 32 * 1024);
+        assert_eq!(level.l1_cache.instruction_size, 0);
+        assert_eq!(level.l2_cache, 1024 * 1024);
+        assert_eq!(level.l3_cache, 0);
+    }
+
+    #[test]
+    fn counts_populated_memory_channels() {
+        let device = |size: &str, locator: &str, bank: &str| {
+            format!(
+                "Handle 0x0040, DMI type 17, 84 bytes\nMemory Device\n\tTotal Width: 72 bits\n\tSize: {}\n\tForm Factor: DIMM\n\tLocator: {}\n\tBank Locator: {}\n\tType: DDR4\n",
+                size, locator, bank
+            )
+        };
+        let dump = |devices: &[String]| format!("# dmidecode 3.3\n\n{}", devices.join("\n"));
+
+        // Two sockets with channels A and B each, one slot left empty
+        let server = dump(&[
+            device("32 GB", "CPU0_DIMM_A1", "P0 CHANNEL A"),
+            device("32 GB", "CPU0_DIMM_B1", "P0 CHANNEL B"),
+            device("32 GB", "CPU1_DIMM_A1", "P1 CHANNEL A"),
+            device("32 GB", "CPU1_DIMM_B1", "P1 CHANNEL B"),
+            device("No Module Installed", "CPU1_DIMM_C1", "P1 CHANNEL C"),
+        ]);
+        assert_eq!(parse_dmidecode_memory_channels(&server), Some(4));
+
+        // Laptops tend to name the channel in the locator only, and boards
+        // with two DIMMs per channel only in the slot letter
+        let laptop = dump(&[
+            device("8 GB", "ChannelA-DIMM0", "BANK 0"),
+            device("8 GB", "ChannelB-DIMM0", "BANK 2"),
+        ]);
+        assert_eq!(parse_dmidecode_memory_channels(&laptop), Some(2));
+        let desktop = dump(&[
+            device("16 GB", "DIMM_A1", "BANK 0"),
+            device("16 GB", "DIMM_A2", "BANK 1"),
+            device("16 GB", "DIMM_B1", "BANK 2"),
+        ]);
+        assert_eq!(parse_dmidecode_memory_channels(&desktop), Some(2));
+
+        let unnamed = dump(&[device("16 GB", "DIMM 0", "BANK 0")]);
+        assert_eq!(parse_dmidecode_memory_channels(&unnamed), None);
+        // What an unprivileged run prints
+        assert_eq!(parse_dmidecode_memory_channels("# dmidecode 3.3\n"), None);
+    }
+
+    #[test]
+    fn parses_lscpu_cache_lines() {
+        let mut level = ProcessorLevel::new(LevelKey::Default);
+        parse_lscpu(
+            "Model name:  Intel(R) Xeon(R)\nL1d cache:   384 KiB (8 instances)\nL1i cache:   256 KiB (8 instances)\nL2 cache:    16 MiB (8 instances)\nL3 cache:    32 MiB (1 instance)\n",
+            &mut level,
+        );
+        assert_eq!(level.l1_cache.data_size, 48 * 1024);
+        assert_eq!(level.l1_cache.instruction_size, 32 * 1024);
+        assert_eq!(level.l2_cache, 2 * 1024 * 1024);
+        assert_eq!(level.l3_cache, 32 * 1024 * 1024);
+
+        // Older util-linux
+        let mut level = ProcessorLevel::new(LevelKey::Default);
+        parse_lscpu(
+            "L1d cache:             32K\nL2 cache:              1024K\n",
+            &mut level,
+        );
+        assert_eq!(level.l1_cache.data_size, 32 * 1024);
+        assert_eq!(level.l2_cache, 1024 * 1024);
+    }
+
+    #[test]
+    fn reads_unitless_tiny_sizes_as_kb() {
+        let level = collect_sysfs_fixture(
+            "bare-kb",
+            &[
+                &[("level", "1"), ("type", "Data"), ("size", "32K")],
+                &[
+                    ("level", "2"),
+                    ("type", "Unified"),
+                    ("size", "256"),
+                    ("coherency_line_size", "64"),
+                    ("number_of_sets", "512"),
+                    ("ways_of_associativity", "8"),
+                ],
+                // No geometry to cross-check against
+                &[("level", "3"), ("type", "Unified"), ("size", "2048")],
+            ],
+            &[],
+        );
+
+        assert_eq!(level.l2_cache, 256 * 1024);
+        assert_eq!(level.l3_cache, 2048 * 1024);
+
+        // A geometry that matches the bare number keeps it in bytes
+        let level = collect_sysfs_fixture(
+            "bare-bytes",
+            &[&[
+                ("level", "2"),
+                ("type", "Unified"),
+                ("size", "2048"),
+                ("coherency_line_size", "64"),
+                ("number_of_sets", "8"),
+                ("ways_of_associativity", "4"),
+            ]],
+            &[],
+        );
+
+        assert_eq!(level.l2_cache, 2048);
+    }
+
+    #[test]
+    fn reads_power_cache_indices_with_128_byte_lines() {
+        // Layout of a POWER9 core
+        let level = collect_sysfs_fixture(
+            "power9",
+            &[
+                &[
+                    ("level", "1"),
+                    ("type", "Data"),
+                    ("size", "32K"),
+                    ("coherency_line_size", "128"),
+                ],
+                &[
+                    ("level", "1"),
+                    ("type", "Instruction"),
+                    ("size", "32K"),
+                    ("coherency_line_size", "128"),
+                ],
+                &[
+                    ("level", "2"),
+                    ("type", "Unified"),
+                    ("size", "512K"),
+                    ("coherency_line_size", "128"),
+                ],
+                &[
+                    ("level", "3"),
+                    ("type", "Unified"),
+                    ("size", "10240K"),
+                    ("coherency_line_size", "128"),
+                ],
+            ],
+            &[],
+        );
+
+        assert_eq!(level.l1_cache.data_size, 32 * 1024);
+        assert_eq!(level.l2_cache, 512 * 1024);
+        assert_eq!(level.l3_cache, 10 * 1024 * 1024);
+        assert_eq!(level.l1_cache.line_size, 128);
+        assert_eq!(level.l2_line_size, 128);
+        assert_eq!(level.l3_line_size, 128);
+        assert_eq!(level.line_size_warning(), None);
+    }
+
+    #[test]
+    fn reads_cache_protection_from_edac() {
+        let indices: &[&[(&str, &str)]] = &[
+            &[("level", "1"), ("type", "Data"), ("size", "64K")],
+            &[("level", "2"), ("type", "Unified"), ("size", "1M")],
+        ];
+
+        let protected = collect_sysfs_fixture(
+            "edac",
+            indices,
+            &[
+                ("devices/system/edac/cpu/cpu0/L1/ce_count", "0"),
+                ("devices/system/edac/cpu/cpu0/L2/ce_count", "3"),
+            ],
+        );
+        assert_eq!(protected.ecc_protected, Some(true));
+        assert!(protected
+            .format()
+            .contains(&"Cache Protection: ECC/parity".to_string()));
+
+        let unknown = collect_sysfs_fixture("no-edac", indices, &[]);
+        assert_eq!(unknown.ecc_protected, None);
+        assert!(!unknown
+            .format()
+            .iter()
+            .any(|line| line.starts_with("Cache Protection")));
+    }
+
+    #[test]
+    fn reads_available_l3_ways_from_resctrl() {
+        // 12-way L3 with the default group limited to the low 4 ways
+        let level = collect_sysfs_fixture(
+            "resctrl",
+            &[
+                &[("level", "1"), ("type", "Data"), ("size", "48K")],
+                &[("level", "2"), ("type", "Unified"), ("size", "2M")],
+                &[
+                    ("level", "3"),
+                    ("type", "Unified"),
+                    ("size", "12M"),
+                    ("id", "1"),
+                ],
+            ],
+            &[
+                ("fs/resctrl/info/L3/cbm_mask", "fff"),
+                (
+                    "fs/resctrl/schemata",
+                    "    L3:0=fff;1=00f\n    MB:0=100;1=100",
+                ),
+            ],
+        );
+
+        assert_eq!(level.available_l3_ways, Some(4));
+        assert_eq!(level.available_l3_bytes, Some(4 * 1024 * 1024));
+        assert!(level
+            .format()
+            .contains(&"L3 available to this cgroup: 4.00 MB".to_string()));
+    }
+
+    #[test]
+    fn reads_cgroup_cpu_quota() {
+        let l1 = &[("level", "1"), ("type", "Data"), ("size", "32K")][..];
+
+        let v2 = detect_sysfs_fixture(
+            "cgroup-v2",
+            &[l1],
+            &[("fs/cgroup/cpu.max", "150000 100000")],
+        );
+        assert_eq!(v2.effective_cpus, Some(1.5));
+
+        let unlimited =
+            detect_sysfs_fixture("cgroup-max", &[l1], &[("fs/cgroup/cpu.max", "max 100000")]);
+        assert_eq!(unlimited.effective_cpus, None);
+
+        let v1 = detect_sysfs_fixture(
+            "cgroup-v1",
+            &[l1],
+            &[
+                ("fs/cgroup/cpu/cpu.cfs_quota_us", "400000"),
+                ("fs/cgroup/cpu/cpu.cfs_period_us", "100000"),
+            ],
+        );
+        assert_eq!(v1.effective_cpus, Some(4.0));
+    }
+
+    #[test]
+    fn reads_hugepage_pools() {
+        let processor = detect_sysfs_fixture(
+            "hugepages",
+            &[&[("level", "1"), ("type", "Data"), ("size", "32K")]],
+            &[
+                ("kernel/mm/hugepages/hugepages-1048576kB/nr_hugepages", "2"),
+                (
+                    "kernel/mm/hugepages/hugepages-1048576kB/free_hugepages",
+                    "1",
+                ),
+                ("kernel/mm/hugepages/hugepages-2048kB/nr_hugepages", "512"),
+                ("kernel/mm/hugepages/hugepages-2048kB/free_hugepages", "512"),
+            ],
+        );
+
+        assert_eq!(
+            processor.hugepages,
+            vec![
+                HugepageInfo {
+                    size: 2 * 1024 * 1024,
+                    total: 512,
+                    free: 512,
+                    default: false,
+                },
+                HugepageInfo {
+                    size: 1024 * 1024 * 1024,
+                    total: 2,
+                    free: 1,
+                    default: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_per_cpu_topology() {
+        let root = env::temp_dir().join(format!("matmul-utils-topology-{}", std::process::id()));
+        for (cpu, core, l2) in [(0, "0", "0-1"), (1, "0", "0-1"), (2, "1", "2-3")] {
+            let dir = root.join(format!("devices/system/cpu/cpu{}", cpu));
+            for (file, content) in [
+                ("topology/physical_package_id", "0"),
+                ("topology/core_id", core),
+                ("cache/index0/level", "1"),
+                ("cache/index0/shared_cpu_list", &cpu.to_string()),
+                ("cache/index1/level", "2"),
+                ("cache/index1/shared_cpu_list", l2),
+                ("cache/index2/level", "3"),
+                ("cache/index2/shared_cpu_list", "0-3"),
+            ] {
+                let path = dir.join(file);
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(path, format!("{}\n", content)).unwrap();
+            }
+            std::fs::create_dir_all(dir.join("node0")).unwrap();
+        }
+
+        let mut processor = ProcessorInfo::new();
+        processor.sysfs_root = Some(root.to_string_lossy().into_owned());
+        let topology = processor.cpu_topology();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(topology.len(), 3);
+        assert_eq!(
+            topology[2],
+            CpuTopology {
+                cpu: 2,
+                package: Some(0),
+                core: Some(1),
+                numa_node: Some(0),
+                l2_shared_cpus: vec![2, 3],
+                l3_shared_cpus: vec![0, 1, 2, 3],
+            }
+        );
+        assert_eq!(topology[1].l2_shared_cpus, vec![0, 1]);
+    }
+
+    #[test]
+    fn assumes_defaults_without_any_detection() {
+        let mut processor = ProcessorInfo::new();
+        processor.assume_defaults();
+
+        assert_eq!(processor.l1_data_cache(), 32 * 1024);
+        assert!(processor.block_sizes_for_dtype(8).is_some());
+        assert_eq!(
+            processor.assumed_defaults,
+            vec!["Default: L1 Data, L2, L3, line size".to_string()]
+        );
+    }
+
+    #[test]
+    fn orders_levels_fastest_first() {
+        let mut processor = ProcessorInfo::new();
+        for key in [
+            LevelKey::Group(0),
+            LevelKey::Efficiency(2),
+            LevelKey::Default,
+            LevelKey::Efficiency(1),
+        ] {
+            processor
+                .performance_levels
+                .insert(key, ProcessorLevel::new(key));
+        }
+
+        let names: Vec<_> = processor
+            .levels_by_tier()
+            .iter()
+            .map(|level| level.level_name.clone())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                LevelKey::Default,
+                LevelKey::Efficiency(1),
+                LevelKey::Efficiency(2),
+                LevelKey::Group(0)
+            ]
+            .map(|key| key.to_string())
+        );
+    }
+
+    #[test]
+    fn sorts_levels_by_name_or_size() {
+        let mut processor = ProcessorInfo::new();
+        for (key, l2) in [
+            (LevelKey::Performance, 4 * 1024 * 1024),
+            (LevelKey::Efficiency(1), 16 * 1024 * 1024),
+            (LevelKey::Efficiency(2), 4 * 1024 * 1024),
+        ] {
+            let mut level = ProcessorLevel::new(key);
+            level.l2_cache = l2;
+            processor.performance_levels.insert(key, level);
+        }
+
+        let names = |order| -> Vec<String> {
+            processor
+                .levels_sorted(order)
+                .iter()
+                .map(|level| level.level_name.clone())
+                .collect()
+        };
+        assert_eq!(
+            names(LevelOrder::Size),
+            [
+                "Efficiency Cores (Level 1)",
+                "Performance Cores",
+                "Efficiency Cores (Level 2)"
+            ]
+        );
+        assert_eq!(
+            names(LevelOrder::Name),
+            [
+                "Efficiency Cores (Level 1)",
+                "Efficiency Cores (Level 2)",
+                "Performance Cores"
+            ]
+        );
+        assert_eq!(names(LevelOrder::Tier)[0], "Performance Cores");
+        assert_eq!(LevelOrder::from_name("size"), Some(LevelOrder::Size));
+        assert_eq!(LevelOrder::from_name("l2"), None);
+    }
+
+    #[test]
+    fn primary_accessors_read_the_main_cores() {
+        let processor = detect_sysfs_fixture(
+            "primary",
+            &[
+                &[("level", "1"), ("type", "Data"), ("size", "48K")],
+                &[("level", "2"), ("type", "Unified"), ("size", "2M")],
+            ],
+            &[],
+        );
+
+        assert_eq!(processor.l1_data_cache(), 48 * 1024);
+        assert_eq!(processor.l2_cache(), 2 * 1024 * 1024);
+        assert_eq!(processor.l3_cache(), 0);
+        assert_eq!(ProcessorInfo::new().l2_cache(), 0);
+
+        assert_eq!(processor.levels().count(), 1);
+        assert_eq!(
+            processor.level("Default").map(|level| level.l2_cache),
+            Some(2 * 1024 * 1024)
+        );
+        assert!(processor.level("Performance Cores").is_none());
+    }
+
+    #[test]
+    fn reads_back_its_own_json() {
+        let indices: &[&[(&str, &str)]] = &[
+            &[("level", "1"), ("type", "Data"), ("size", "48K")],
+            &[("level", "2"), ("type", "Unified"), ("size", "2M")],
+            &[("level", "3"), ("type", "Unified"), ("size", "105M")],
+        ];
+        let mut processor = detect_sysfs_fixture("from-json", indices, &[]);
+        processor.x86_signature = Some(X86Signature {
+            family: 6,
+            model: 143,
+            stepping: 8,
+        });
+        processor.effective_cpus = Some(2.5);
+
+        let json = processor.to_json();
+        let restored = ProcessorInfo::from_json(&Json::parse(&json.render()).unwrap()).unwrap();
+        assert_eq!(restored.to_json(), json);
+        assert_eq!(restored.fingerprint(), processor.fingerprint());
+        // A saved report renders like the live one, given the same options
+        processor.stats = true;
+        assert_eq!(
+            render(&restored, &processor.display_options()),
+            processor.display()
+        );
+
+        let mut other_version = json.clone();
+        if let Json::Object(fields) = &mut other_version {
+            fields[0].1 = Json::UInt(JSON_SCHEMA_VERSION + 1);
+        }
+        assert!(ProcessorInfo::from_json(&other_version).is_none());
+
+        // A version 1 report saved before the later fields existed
+        let mut older = json.clone();
+        if let Json::Object(fields) = &mut older {
+            fields.retain(|(key, _)| {
+                !matches!(
+                    key.as_str(),
+                    "os_name"
+                        | "kernel_version"
+                        | "nontemporal_stores"
+                        | "limited_by_privileges"
+                        | "allowed_cpus"
+                )
+            });
+        }
+        let older = ProcessorInfo::from_json(&older).unwrap();
+        assert!(older.os_name.is_empty() && older.kernel_version.is_empty());
+    }
+
+    #[test]
+    fn json_levels_come_in_tier_order_every_time() {
+        let mut processor = ProcessorInfo::new();
+        for key in [
+            LevelKey::Group(0),
+            LevelKey::Efficiency(2),
+            LevelKey::Performance,
+            LevelKey::Efficiency(1),
+        ] {
+            processor
+                .performance_levels
+                .insert(key, ProcessorLevel::new(key));
+        }
+
+        let json = processor.to_json();
+        let names: Vec<_> = json
+            .get("performance_levels")
+            .and_then(Json::as_array)
+            .unwrap()
+            .iter()
+            .map(|level| level.get("name").and_then(Json::as_str).unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "Performance Cores",
+                "Efficiency Cores (Level 1)",
+                "Efficiency Cores (Level 2)",
+                "Core Group 0"
+            ]
+        );
+
+        // Same machine, same bytes
+        if let (Ok(first), Ok(second)) = (detect(), detect()) {
+            assert_eq!(first.to_json_string(), second.to_json_string());
+        }
+    }
+
+    #[test]
+    fn fingerprint_depends_only_on_the_hardware() {
+        let indices: &[&[(&str, &str)]] = &[
+            &[("level", "1"), ("type", "Data"), ("size", "48K")],
+            &[("level", "2"), ("type", "Unified"), ("size", "2M")],
+        ];
+
+        let first = detect_sysfs_fixture("fingerprint-a", indices, &[]);
+        let mut second = detect_sysfs_fixture("fingerprint-b", indices, &[]);
+        assert_eq!(first.fingerprint(), second.fingerprint());
+        assert_eq!(first.fingerprint().len(), 16);
+
+        second.effective_cpus = Some(2.0);
+        assert_eq!(first.fingerprint(), second.fingerprint());
+
+        second.model_name = "Other CPU".to_string();
+        assert_ne!(first.fingerprint(), second.fingerprint());
+    }
+
+    #[test]
+    fn renders_primary_level_as_rust_consts() {
+        let processor = detect_sysfs_fixture(
+            "codegen",
+            &[
+                &[("level", "1"), ("type", "Data"), ("size", "32K")],
+                &[("level", "2"), ("type", "Unified"), ("size", "1M")],
+            ],
+            &[],
+        );
+        let consts = processor.to_rust_consts();
+
+        assert!(consts.contains("pub const L1D_CACHE: usize = 32768;"));
+        assert!(consts.contains("pub const L2_CACHE: usize = 1048576;"));
+        assert!(consts.contains("pub const L3_CACHE: usize = 0;"));
+        assert!(consts.contains("pub const CACHE_LINE_SIZE: usize = 64;"));
+
+        assert_eq!(
+            processor.to_shell_exports(),
+            "export MATMUL_L1D=32768\nexport MATMUL_L1I=0\nexport MATMUL_L2=1048576\nexport MATMUL_L3=0\nexport MATMUL_LINE_SIZE=64"
+        );
+    }
+
+    #[test]
+    fn debug_json_keeps_level_keys_and_raw_queries() {
+        let processor = detect_sysfs_fixture(
+            "debug-json",
+            &[&[("level", "2"), ("type", "Unified"), ("size", "1M")]],
+            &[],
+        );
+        let json = processor.to_debug_json();
+
+        assert_eq!(
+            json.get("level_keys").and_then(Json::as_array),
+            Some(&vec![Json::Str("Default".to_string())][..])
+        );
+        let raw_log = json.get("raw_log").and_then(Json::as_array).unwrap();
+        assert!(raw_log.iter().any(|entry| {
+            entry
+                .as_array()
+                .and_then(|pair| pair[0].as_str())
+                .is_some_and(|source| source.ends_with("index0/size"))
+        }));
+        // Everything the public layout has is there too
+        assert_eq!(
+            json.get("performance_levels"),
+            processor.to_json().get("performance_levels")
+        );
+    }
+
+    #[test]
+    fn renders_levels_as_csv_toml_and_markdown() {
+        let mut processor = detect_sysfs_fixture(
+            "table-formats",
+            &[
+                &[
+                    ("level", "1"),
+                    ("type", "Data"),
+                    ("size", "32K"),
+                    ("coherency_line_size", "64"),
+                ],
+                &[("level", "2"), ("type", "Unified"), ("size", "1M")],
+            ],
+            &[],
+        );
+        processor.model_name = "Test, \"Quoted\" CPU".to_string();
+        if let Some(level) = processor.performance_levels.get_mut(&LevelKey::Default) {
+            level.level_name = "Cores, all".to_string();
+        }
+
+        let csv = processor.to_csv();
+        assert_eq!(
+            csv.lines().nth(1),
+            Some("\"Cores, all\",0,32768,0,1048576,0,64")
+        );
+
+        let toml = processor.to_toml();
+        assert!(toml.contains("model_name = \"Test, \\\"Quoted\\\" CPU\""));
+        assert!(toml.contains("[[levels]]\nname = \"Cores, all\"\nl1i_bytes = 0"));
+        assert!(toml.contains("l2_bytes = 1048576"));
+
+        let markdown = processor.to_markdown();
+        assert!(markdown
+            .contains("| Cores, all | Not detected | 32.00 KB | 1.00 MB | Not detected | 64 B |"));
+    }
+
+    #[test]
+    fn estimates_all_core_turbo_from_per_core_ceilings() {
+        let l2 = [("level", "2"), ("type", "Unified"), ("size", "1M")];
+        let ceilings = |khz: [&'static str; 3]| {
+            vec![
+                ("devices/system/cpu/cpu0/cpufreq/cpuinfo_max_freq", khz[0]),
+                ("devices/system/cpu/cpu1/cpufreq/cpuinfo_max_freq", khz[1]),
+                ("devices/system/cpu/cpu2/cpufreq/cpuinfo_max_freq", khz[2]),
+            ]
+        };
+
+        // Two favored cores above the rest
+        let favored = ceilings(["5300000", "4800000", "5300000"]);
+        let processor = detect_sysfs_fixture("turbo-favored", &[&l2], &favored);
+        assert_eq!(processor.max_turbo_hz, Some(5_300_000_000));
+        assert_eq!(processor.all_core_turbo_hz, Some(4_800_000_000));
+
+        let mut turbo_off = ceilings(["3000000", "3000000", "3000000"]);
+        turbo_off.push(("devices/system/cpu/cpufreq/boost", "0"));
+        let processor = detect_sysfs_fixture("turbo-off", &[&l2], &turbo_off);
+        assert_eq!(processor.all_core_turbo_hz, Some(3_000_000_000));
+
+        let uniform = ceilings(["4500000", "4500000", "4500000"]);
+        let processor = detect_sysfs_fixture("turbo-uniform", &[&l2], &uniform);
+        assert_eq!(processor.max_turbo_hz, Some(4_500_000_000));
+        assert_eq!(processor.all_core_turbo_hz, None);
+
+        let processor = detect_sysfs_fixture("turbo-none", &[&l2], &[]);
+        assert_eq!(processor.max_turbo_hz, None);
+    }
+
+    #[test]
+    fn tree_groups_cpus_by_shared_l2() {
+        let mut extra = Vec::new();
+        for cpu in 0..4 {
+            for (index, level, shared) in
+                [(0, "2", ["0-1", "0-1", "2-3", "2-3"][cpu]), (1, "3", "0-3")]
+            {
+                let dir = format!("devices/system/cpu/cpu{}/cache/index{}", cpu, index);
+                extra.push((format!("{}/level", dir), level));
+                extra.push((format!("{}/shared_cpu_list", dir), shared));
+                extra.push((
+                    format!("{}/size", dir),
+                    if level == "2" { "1M" } else { "8M" },
+                ));
+            }
+        }
+        let extra: Vec<(&str, &str)> = extra
+            .iter()
+            .map(|(path, content)| (path.as_str(), *content))
+            .collect();
+
+        let root = env::temp_dir().join(format!("matmul-utils-tree-{}", std::process::id()));
+        for (file, content) in &extra {
+            let path = root.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, format!("{}\n", content)).unwrap();
+        }
+        let mut processor = ProcessorInfo::new();
+        processor.sysfs_root = Some(root.to_string_lossy().into_owned());
+        processor.collect_cache_info().unwrap();
+        let tree = processor.display_tree();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert!(tree.contains("└── L3 8.00 MB (CPUs 0-3)"));
+        assert!(tree.contains("    ├── L2 1.00 MB (CPUs 0-1)"));
+        assert!(tree.contains("    └── L2 1.00 MB (CPUs 2-3)"));
+    }
+
+    #[test]
+    fn reads_current_frequencies_from_cpufreq() {
+        let root = env::temp_dir().join(format!("matmul-utils-cpufreq-{}", std::process::id()));
+        for (cpu, khz) in [(0, "2400000"), (1, "800000"), (10, "3600000")] {
+            let dir = root.join(format!("devices/system/cpu/cpu{}/cpufreq", cpu));
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("scaling_cur_freq"), khz).unwrap();
+        }
+        // Offline or cpufreq-less CPUs are skipped
+        std::fs::create_dir_all(root.join("devices/system/cpu/cpu2")).unwrap();
+        std::fs::create_dir_all(root.join("devices/system/cpu/cpufreq")).unwrap();
+
+        let mut processor = ProcessorInfo::new();
+        processor.sysfs_root = Some(root.to_string_lossy().into_owned());
+        let frequencies = processor.current_frequencies_mhz();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(frequencies, vec![(0, 2400.0), (1, 800.0), (10, 3600.0)]);
+    }
+
+    #[test]
+    fn max_line_size_spans_the_hierarchy() {
+        let processor = detect_sysfs_fixture(
+            "line-size",
+            &[
+                &[
+                    ("level", "1"),
+                    ("type", "Data"),
+                    ("size", "64K"),
+                    ("coherency_line_size", "64"),
+                ],
+                &[
+                    ("level", "2"),
+                    ("type", "Unified"),
+                    ("size", "1M"),
+                    ("coherency_line_size", "128"),
+                ],
+            ],
+            &[],
+        );
+
+        assert_eq!(processor.max_line_size(), 128);
+        assert_eq!(ProcessorInfo::new().max_line_size(), 64);
+    }
+
+    #[test]
+    fn labels_l2_as_per_core_when_only_smt_siblings_share_it() {
+        let l1 = &[("level", "1"), ("type", "Data"), ("size", "48K")][..];
+        let topology = |cpu: usize, core: usize| {
+            [
+                (
+                    format!("devices/system/cpu/cpu{}/topology/physical_package_id", cpu),
+                    "0".to_string(),
+                ),
+                (
+                    format!("devices/system/cpu/cpu{}/topology/core_id", cpu),
+                    core.to_string(),
+                ),
+            ]
+        };
+        let files: Vec<(String, String)> = [topology(0, 0), topology(1, 0), topology(2, 1)]
+            .into_iter()
+            .flatten()
+            .collect();
+        let extra: Vec<(&str, &str)> = files
+            .iter()
+            .map(|(path, content)| (path.as_str(), content.as_str()))
+            .collect();
+
+        let private = collect_sysfs_fixture(
+            "l2-private",
+            &[
+                l1,
+                &[
+                    ("level", "2"),
+                    ("type", "Unified"),
+                    ("size", "2M"),
+                    ("shared_cpu_list", "0-1"),
+                ],
+            ],
+            &extra,
+        );
+        assert_eq!(private.l2_shared(), Some(false));
+        assert!(private
+            .format()
+            .contains(&"L2 Cache: 2.00 MB (per-core)".to_string()));
+
+        let shared = collect_sysfs_fixture(
+            "l2-shared",
+            &[
+                l1,
+                &[
+                    ("level", "2"),
+                    ("type", "Unified"),
+                    ("size", "4M"),
+                    ("shared_cpu_list", "0-2"),
+                ],
+            ],
+            &extra,
+        );
+        assert_eq!(shared.l2_shared(), Some(true));
+        assert!(shared
+            .format()
+            .contains(&"L2 Cache: 4.00 MB (shared by 2 cores)".to_string()));
+    }
+
+    #[test]
+    fn reads_only_the_caches_of_allowed_cpus() {
+        let root = env::temp_dir().join(format!("matmul-utils-affinity-{}", std::process::id()));
+        let sysfs = root.join("sys");
+        let cpu_dir = sysfs.join("devices/system/cpu");
+        for (cpu, size, shared) in [(0, "1024K", "0-1"), (2, "2048K", "2-3")] {
+            let dir = cpu_dir.join(format!("cpu{}/cache/index0", cpu));
+            std::fs::create_dir_all(&dir).unwrap();
+            for (file, content) in [
+                ("level", "2"),
+                ("type", "Unified"),
+                ("size", size),
+                ("shared_cpu_list", shared),
+            ] {
+                std::fs::write(dir.join(file), content).unwrap();
+            }
+        }
+        std::fs::write(cpu_dir.join("online"), "0-3\n").unwrap();
+        std::fs::create_dir_all(root.join("proc/self")).unwrap();
+        std::fs::write(
+            root.join("proc/self/status"),
+            "Name:\tmatmul\nCpus_allowed:\t4\nCpus_allowed_list:\t2\n",
+        )
+        .unwrap();
+
+        let mut processor = ProcessorInfo::new();
+        processor.sysfs_root = Some(sysfs.to_string_lossy().into_owned());
+        processor.proc_root = Some(root.join("proc").to_string_lossy().into_owned());
+        processor.collect_linux_cache_info().unwrap();
+        let display = processor.display();
+
+        std::fs::write(root.join("proc/self/status"), "Cpus_allowed_list:\t0-3\n").unwrap();
+        let mut unpinned = ProcessorInfo::new();
+        unpinned.sysfs_root = processor.sysfs_root.clone();
+        unpinned.proc_root = processor.proc_root.clone();
+        unpinned.collect_linux_cache_info().unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(processor.allowed_cpus, Some(vec![2]));
+        assert_eq!(processor.l2_cache(), 2 * 1024 * 1024);
+        assert_eq!(processor.primary_level().unwrap().l2_shared_cpus, vec![2]);
+        assert!(
+            display.contains("Note: affinity restricted to CPUs 2, caches shown are theirs alone")
+        );
+
+        assert_eq!(unpinned.allowed_cpus, None);
+        assert_eq!(unpinned.l2_cache(), 1024 * 1024);
+        assert_eq!(unpinned.primary_level().unwrap().l2_shared_cpus, vec![0, 1]);
+    }
+
+    #[test]
+    fn detects_a_qemu_machine_from_dmi() {
+        let root = env::temp_dir().join(format!("matmul-utils-dmi-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("class/dmi/id")).unwrap();
+        std::fs::write(root.join("class/dmi/id/sys_vendor"), "QEMU\n").unwrap();
+
+        let mut processor = ProcessorInfo::new();
+        processor.sysfs_root = Some(root.to_string_lossy().into_owned());
+        processor.detect_emulation();
+        let display = processor.display();
+
+        std::fs::write(root.join("class/dmi/id/sys_vendor"), "Dell Inc.\n").unwrap();
+        let mut bare_metal = ProcessorInfo::new();
+        bare_metal.sysfs_root = processor.sysfs_root.clone();
+        bare_metal.detect_emulation();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(processor.emulated.as_deref(), Some("QEMU"));
+        assert!(display.contains("Note: running under QEMU, cache sizes may be synthetic"));
+        assert_eq!(bare_metal.emulated, None);
+    }
+
+    #[test]
+    fn tells_whether_the_gpu_shares_the_llc() {
+        let root = env::temp_dir().join(format!("matmul-utils-igpu-{}", std::process::id()));
+        let device = root.join("bus/pci/devices/0000:00:02.0");
+        std::fs::create_dir_all(&device).unwrap();
+        std::fs::write(device.join("vendor"), "0x8086\n").unwrap();
+        std::fs::write(device.join("class"), "0x030000\n").unwrap();
+
+        let mut processor = ProcessorInfo::new();
+        processor.sysfs_root = Some(root.to_string_lossy().into_owned());
+        processor.llc_shared_with_gpu = processor.detect_llc_shared_with_gpu();
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(processor.llc_shared_with_gpu, Some(true));
+        assert!(processor
+            .raw_log
+            .entries
+            .iter()
+            .any(|(source, _)| source.ends_with("0000:00:02.0/class")));
+        assert!(processor
+            .display()
+            .contains("LLC Shared with GPU: yes, the GPU may take part of it"));
+
+        // No iGPU there now
+        assert_eq!(processor.detect_llc_shared_with_gpu(), None);
+        processor.model_name = "AMD Ryzen 7 5800H with Radeon Graphics".to_string();
+        assert_eq!(processor.detect_llc_shared_with_gpu(), Some(false));
+
+        // A captured tree says nothing about the host being a Mac
+        processor.model_name = "Apple M2".to_string();
+        processor.architecture = "Apple Silicon".to_string();
+        assert_eq!(processor.detect_llc_shared_with_gpu(), None);
+        processor.sysfs_root = None;
+        assert_eq!(processor.detect_llc_shared_with_gpu(), Some(true));
+    }
+
+    #[test]
+    fn shows_wmic_values_before_and_after_scaling() {
+        let mut processor = ProcessorInfo::new();
+        processor.raw_log.record(
+            "wmic cpu get L2CacheSize,L3CacheSize /value",
+            "\r\nL2CacheSize=2048\r\nl3cachesize=8.192\r\nName=Some CPU\r\n",
+        );
+        processor
+            .raw_log
+            .record("wmic cpu get name /value", "<error: timed out after 5s>");
+
+        let report = processor.unit_report();
+        assert_eq!(report[2..2 + unit_assumptions().len()], *unit_assumptions());
+        let wmic: Vec<_> = report
+            .iter()
+            .filter(|line| line.starts_with("wmic "))
+            .collect();
+        assert_eq!(
+            wmic,
+            [
+                "wmic L2CacheSize=2048 -> 2048 KB x 1024 = 2.00 MB",
+                "wmic l3cachesize=8.192 -> 8192 KB x 1024 = 8.00 MB"
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_memory_encryption_flags() {
+        let cpuinfo = "processor\t: 0\nflags\t\t: fpu sme sev sev_es ssbd\n\nprocessor\t: 1\nflags\t\t: fpu tme\n";
+        assert_eq!(
+            parse_cpuinfo_memory_encryption(cpuinfo).as_deref(),
+            Some("SME, SEV, SEV-ES")
+        );
+
+        let cpuinfo = "processor\t: 0\nflags\t\t: fpu vme de pse\n";
+        assert_eq!(parse_cpuinfo_memory_encryption(cpuinfo), None);
+    }
+
+    #[test]
+    fn lists_amx_from_cpuinfo_flags() {
+        let mut processor = ProcessorInfo::new();
+        processor.apply_cpuinfo("flags\t\t: fpu avx512f amx_bf16 amx_tile amx_int8 amx_fp16\n");
+        assert_eq!(
+            processor.matrix_extensions,
+            ["AMX-TILE", "AMX-BF16", "AMX-INT8", "AMX-FP16"]
+        );
+        assert!(processor
+            .display()
+            .contains("Matrix Extensions: AMX-TILE, AMX-BF16, AMX-INT8, AMX-FP16"));
+
+        processor.apply_cpuinfo("flags\t\t: fpu avx2\n");
+        assert!(processor.matrix_extensions.is_empty());
+    }
+
+    #[test]
+    fn parses_x86_signature_from_cpuinfo() {
+        let cpuinfo = "processor\t: 0\nvendor_id\t: GenuineIntel\ncpu family\t: 6\nmodel\t\t: 85\nmodel name\t: Intel(R) Xeon(R) Gold 6148 CPU @ 2.40GHz\nstepping\t: 4\n\nprocessor\t: 1\ncpu family\t: 6\nmodel\t\t: 85\nstepping\t: 7\n";
+        assert_eq!(
+            parse_cpuinfo_signature(cpuinfo),
+            Some(X86Signature {
+                family: 6,
+                model: 85,
+                stepping: 4,
+            })
+        );
+
+        // aarch64 reports "CPU part" and friends instead
+        let cpuinfo =
+            "processor\t: 0\nBogoMIPS\t: 50.00\nCPU implementer\t: 0x41\nCPU part\t: 0xd0c\n";
+        assert_eq!(parse_cpuinfo_signature(cpuinfo), None);
+    }
+
+    #[test]
+    fn reads_cpuinfo_from_proc_root() {
+        let root = env::temp_dir().join(format!("matmul-utils-proc-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(
+            root.join("cpuinfo"),
+            "processor\t: 0\ncpu family\t: 25\nmodel\t\t: 33\nmodel name\t: AMD Ryzen 9 5950X 16-Core Processor\nstepping\t: 0\n",
+        )
+        .unwrap();
+
+        let mut processor = ProcessorInfo::new();
+        processor.proc_root = Some(root.to_string_lossy().into_owned());
+        processor.detect_model_name();
+        let plan = processor.detection_plan();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(processor.model_name, "AMD Ryzen 9 5950X 16-Core Processor");
+        assert_eq!(
+            processor.x86_signature,
+            Some(X86Signature {
+                family: 25,
+                model: 33,
+                stepping: 0,
+            })
+        );
+        if env::consts::OS == "linux" {
+            assert!(plan.contains(&format!("read {}/cpuinfo", root.display())));
+        }
+    }
+
+    #[test]
+    fn reads_the_kernel_version_from_proc_root() {
+        let root = env::temp_dir().join(format!("matmul-utils-osrelease-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("sys/kernel")).unwrap();
+        std::fs::write(root.join("sys/kernel/ostype"), "Linux\n").unwrap();
+        std::fs::write(root.join("sys/kernel/osrelease"), "6.8.0-45-generic\n").unwrap();
+
+        let mut processor = ProcessorInfo::new();
+        processor.proc_root = Some(root.to_string_lossy().into_owned());
+        processor.detect_os_version();
+
+        // Without the files nothing is made up from this machine's uname
+        let mut missing = ProcessorInfo::new();
+        missing.proc_root = Some(root.join("missing").to_string_lossy().into_owned());
+        missing.detect_os_version();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(processor.os_name, "Linux");
+        assert_eq!(processor.kernel_version, "6.8.0-45-generic");
+        assert!(processor
+            .display()
+            .lines()
+            .any(|line| line.starts_with("OS:") && line.ends_with(" Linux 6.8.0-45-generic")));
+        assert!(missing.os_name.is_empty());
+    }
+
+    #[test]
+    fn reads_the_sve_default_vector_length_from_proc_root() {
+        let root = env::temp_dir().join(format!("matmul-utils-sve-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("sys/abi")).unwrap();
+        std::fs::write(root.join("sys/abi/sve_default_vector_length"), "64\n").unwrap();
+
+        let mut processor = ProcessorInfo::new();
+        processor.proc_root = Some(root.to_string_lossy().into_owned());
+        let bits = processor.detect_sve_vector_bits();
+
+        let mut without_sve = ProcessorInfo::new();
+        without_sve.proc_root = Some(root.join("missing").to_string_lossy().into_owned());
+        let missing = without_sve.detect_sve_vector_bits();
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(bits, Some(512));
+        assert_eq!(missing, None);
+
+        processor.sve_vector_bits = bits;
+        assert!(processor
+            .display()
+            .contains("SVE Vector Length: 512 bits (8 f64 lanes)"));
+    }
+
+    #[test]
+    fn parses_cpuinfo_cache_size() {
+        let cpuinfo = "processor\t: 0\nmodel name\t: Intel(R) Core(TM) i7-9700K CPU @ 3.60GHz\ncache size\t: 12288 KB\n";
+        assert_eq!(parse_cpuinfo_cache_size(cpuinfo), Some(12 * 1024 * 1024));
+        assert_eq!(parse_cpuinfo_cache_size("processor\t: 0\n"), None);
+    }
+
+    #[test]
+    fn parses_sysctl_numbers_with_digit_grouping() {
+        assert_eq!(parse_sysctl_number("4194304"), 4194304);
+        assert_eq!(parse_sysctl_number("4,194,304"), 4194304);
+        assert_eq!(parse_sysctl_number("4.194.304\n"), 4194304);
+        assert_eq!(parse_sysctl_number(""), 0);
+    }
+
+    #[test]
+    fn formats_raw_sysctl_values_like_the_tool() {
+        assert_eq!(
+            format_sysctl_value("A", b"Apple M1\0").as_deref(),
+            Some("Apple M1")
+        );
+        assert_eq!(
+            format_sysctl_value("I", &(-1i32).to_ne_bytes()).as_deref(),
+            Some("-1")
+        );
+        assert_eq!(
+            format_sysctl_value("Q", &16384u64.to_ne_bytes()).as_deref(),
+            Some("16384")
+        );
+
+        let cacheconfig: Vec<u8> = [8u64, 1, 2].iter().flat_map(|n| n.to_ne_bytes()).collect();
+        assert_eq!(
+            format_sysctl_value("QU", &cacheconfig).as_deref(),
+            Some("8 1 2")
+        );
+
+        assert_eq!(format_sysctl_value("I", &[0, 0, 0]), None);
+        assert_eq!(format_sysctl_value("S,clockinfo", &[0; 16]), None);
+    }
+
+    #[test]
+    fn survives_a_missing_tool() {
+        let err = run_tool("matmul-utils-no-such-tool", &[]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert_eq!(
+            err.to_string(),
+            "matmul-utils-no-such-tool not found on PATH"
+        );
+
+        // Every sysctl failing the way a missing binary does
+        let mut processor = ProcessorInfo::new();
+        let result = processor.collect_perflevel_cache_info(|key, raw_log| {
+            let err = run_tool("matmul-utils-no-such-tool", &[key]);
+            raw_log.record(key, "<error>");
+            err
+        });
+        let recovered = result.or_else(|err| processor.recover_from_missing_tool(err));
+        assert!(recovered.is_ok());
+
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        assert!(processor.recover_from_missing_tool(denied).is_err());
+    }
+
+    #[test]
+    fn falls_back_to_chip_wide_keys_without_perflevel_keys() {
+        let sysctls: HashMap<&str, &str> = [
+            ("hw.nperflevels", "2"),
+            ("hw.cachelinesize", "128"),
+            ("hw.l1icachesize", "131072"),
+            ("hw.l1dcachesize", "65536"),
+            ("hw.l2cachesize", "4194304"),
+        ]
+        .into_iter()
+        .collect();
+
+        let mut processor = ProcessorInfo::new();
+        processor
+            .collect_perflevel_cache_info(|key, raw_log| match sysctls.get(key) {
+                Some(value) => {
+                    raw_log.record(key, value);
+                    Ok(value.to_string())
+                }
+                None => Err(io::Error::new(io::ErrorKind::NotFound, key.to_string())),
+            })
+            .unwrap();
+
+        let keys: Vec<LevelKey> = processor.performance_levels.keys().copied().collect();
+        assert_eq!(keys, [LevelKey::Default]);
+        assert_eq!(processor.l1_data_cache(), 64 * 1024);
+        assert_eq!(processor.l2_cache(), 4 * 1024 * 1024);
+        assert_eq!(processor.l3_cache(), 0);
+        assert_eq!(processor.primary_level().unwrap().l2_line_size, 128);
+    }
+
+    #[test]
+    fn reads_l2_from_whichever_key_has_it() {
+        let lookup = |sysctls: HashMap<&'static str, &'static str>| {
+            move |key: &str, raw_log: &mut RawLog| {
+                let value = sysctls.get(key).copied().unwrap_or_default();
+                raw_log.record(key, value);
+                Ok(value.to_string())
+            }
+        };
+
+        // Intel Mac path, only the perflevel key set
+        let flat_empty: HashMap<_, _> = [
+            ("hw.l1dcachesize", "32768"),
+            ("hw.l2cachesize", ""),
+            ("hw.perflevel0.l2cachesize", "262144"),
+        ]
+        .into_iter()
+        .collect();
+        let mut processor = ProcessorInfo::new();
+        processor
+            .collect_flat_sysctl_cache_info(lookup(flat_empty))
+            .unwrap();
+        assert_eq!(processor.l2_cache(), 256 * 1024);
+
+        // The flat key wins when both are set
+        let both: HashMap<_, _> = [
+            ("hw.l2cachesize", "524288"),
+            ("hw.perflevel0.l2cachesize", "262144"),
+        ]
+        .into_iter()
+        .collect();
+        let mut processor = ProcessorInfo::new();
+        processor
+            .collect_flat_sysctl_cache_info(lookup(both))
+            .unwrap();
+        assert_eq!(processor.l2_cache(), 512 * 1024);
+
+        // Perflevel path, only the flat key set for L2
+        let perflevel_empty: HashMap<_, _> = [
+            ("hw.nperflevels", "2"),
+            ("hw.perflevel0.l1dcachesize", "131072"),
+            ("hw.perflevel0.l2cachesize", ""),
+            ("hw.perflevel1.l1dcachesize", "65536"),
+            ("hw.perflevel1.l2cachesize", "4194304"),
+            ("hw.l2cachesize", "16777216"),
+        ]
+        .into_iter()
+        .collect();
+        let mut processor = ProcessorInfo::new();
+        processor
+            .collect_perflevel_cache_info(lookup(perflevel_empty))
+            .unwrap();
+        let tier = |key| processor.performance_levels[&key].l2_cache;
+        assert_eq!(tier(LevelKey::Performance), 16 * 1024 * 1024);
+        assert_eq!(tier(LevelKey::Efficiency(1)), 4 * 1024 * 1024);
+    }
+
+    #[test]
+    fn reads_every_apple_perflevel() {
+        // A hypothetical three tier chip, only the middle tier with its own L3
+        let sysctls: HashMap<&str, &str> = [
+            ("hw.nperflevels", "3"),
+            ("hw.cachelinesize", "128"),
+            ("hw.perflevel0.l1dcachesize", "131072"),
+            ("hw.perflevel0.l2cachesize", "16777216"),
+            ("hw.perflevel0.cpusperl2", "4"),
+            ("hw.perflevel1.l1dcachesize", "65536"),
+            ("hw.perflevel1.l2cachesize", "4194304"),
+            ("hw.perflevel1.l3cachesize", "8388608"),
+            ("hw.perflevel2.l1dcachesize", "32768"),
+            ("hw.perflevel2.l2cachesize", "2097152"),
+        ]
+        .into_iter()
+        .collect();
+
+        let mut processor = ProcessorInfo::new();
+        processor
+            .collect_perflevel_cache_info(|key, raw_log| {
+                let value = sysctls.get(key).copied().unwrap_or_default();
+                raw_log.record(key, value);
+                Ok(value.to_string())
+            })
+            .unwrap();
+
+        let keys: Vec<LevelKey> = processor.performance_levels.keys().copied().collect();
+        assert_eq!(
+            keys,
+            [
+                LevelKey::Performance,
+                LevelKey::Efficiency(1),
+                LevelKey::Efficiency(2)
+            ]
+        );
+
+        let tier = |key| &processor.performance_levels[&key];
+        assert_eq!(tier(LevelKey::Performance).l2_cache, 16 * 1024 * 1024);
+        assert_eq!(tier(LevelKey::Performance).l2_source, Some(Source::Sysctl));
+        assert_eq!(tier(LevelKey::Performance).l2_shared(), Some(true));
+        assert_eq!(tier(LevelKey::Efficiency(2)).l2_shared(), None);
+        assert_eq!(tier(LevelKey::Efficiency(1)).l3_cache, 8 * 1024 * 1024);
+        assert_eq!(tier(LevelKey::Efficiency(2)).l1_cache.data_size, 32 * 1024);
+        assert_eq!(tier(LevelKey::Efficiency(2)).l3_cache, 0);
+        assert_eq!(tier(LevelKey::Efficiency(2)).l2_line_size, 128);
+        assert_eq!(
+            tier(LevelKey::Efficiency(2)).level_name,
+            "Efficiency Cores (Level 2)"
+        );
+    }
+}
@@ -0,0 +1,213 @@
+//! Detection backends behind one interface
+//!
+//! Each provider builds a complete `ProcessorInfo` on its own, so `detect`
+//! can try them in order of accuracy and tests can substitute their own.
+
+use std::env;
+
+use crate::error::CacheError;
+use crate::processor::ProcessorInfo;
+
+/// A source of cache information for this (or a captured) machine
+pub trait CacheProvider {
+    fn collect(&self) -> Result<ProcessorInfo, CacheError>;
+
+    /// What `cross_check` reports the results as coming from
+    fn name(&self) -> &'static str {
+        "custom"
+    }
+}
+
+/// The per-OS queries: sysfs, sysctl, wmic, kstat and friends
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NativeProvider;
+
+impl CacheProvider for NativeProvider {
+    fn collect(&self) -> Result<ProcessorInfo, CacheError> {
+        let mut processor = ProcessorInfo::new();
+        processor
+            .detect_architecture()
+            .collect_native_cache_info()?;
+
+        Ok(processor)
+    }
+
+    fn name(&self) -> &'static str {
+        "native"
+    }
+}
+
+/// A sysfs tree captured from another machine, mounted at `root`
+#[derive(Debug, Default, Clone)]
+pub struct SysfsProvider {
+    pub root: String,
+}
+
+impl CacheProvider for SysfsProvider {
+    fn collect(&self) -> Result<ProcessorInfo, CacheError> {
+        let mut processor = ProcessorInfo::new();
+        processor.sysfs_root = Some(self.root.clone());
+        processor.detect_architecture().collect_linux_cache_info()?;
+
+        Ok(processor)
+    }
+
+    fn name(&self) -> &'static str {
+        "sysfs"
+    }
+}
+
+/// hwloc's topology through its `lstopo` tool
+#[cfg(feature = "hwloc")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HwlocProvider;
+
+#[cfg(feature = "hwloc")]
+impl CacheProvider for HwlocProvider {
+    fn collect(&self) -> Result<ProcessorInfo, CacheError> {
+        let mut processor = ProcessorInfo::new();
+        processor.detect_architecture().collect_hwloc_cache_info()?;
+
+        Ok(processor)
+    }
+
+    fn name(&self) -> &'static str {
+        "hwloc"
+    }
+}
+
+/// `lscpu`, which reads sysfs itself but through its own parsing
+#[cfg(target_os = "linux")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LscpuProvider;
+
+#[cfg(target_os = "linux")]
+impl CacheProvider for LscpuProvider {
+    fn collect(&self) -> Result<ProcessorInfo, CacheError> {
+        let mut processor = ProcessorInfo::new();
+        processor.detect_architecture().collect_lscpu_cache_info()?;
+
+        Ok(processor)
+    }
+
+    fn name(&self) -> &'static str {
+        "lscpu"
+    }
+}
+
+/// The x86 CPUID cache parameters and TLB leaves; finds no levels on other
+/// CPUs
+#[cfg(all(feature = "cpuid", any(target_arch = "x86", target_arch = "x86_64")))]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CpuidProvider;
+
+#[cfg(all(feature = "cpuid", any(target_arch = "x86", target_arch = "x86_64")))]
+impl CacheProvider for CpuidProvider {
+    fn collect(&self) -> Result<ProcessorInfo, CacheError> {
+        let mut processor = ProcessorInfo::new();
+        processor.detect_architecture().collect_cpuid_cache_info();
+
+        Ok(processor)
+    }
+
+    fn name(&self) -> &'static str {
+        "cpuid"
+    }
+}
+
+/// The providers of this build, most accurate first
+pub fn providers() -> Vec<Box<dyn CacheProvider>> {
+    vec![
+        #[cfg(feature = "hwloc")]
+        Box::new(HwlocProvider),
+        Box::new(NativeProvider),
+        // Only describes the core detection ran on, which hybrid CPUs differ in
+        #[cfg(all(feature = "cpuid", any(target_arch = "x86", target_arch = "x86_64")))]
+        Box::new(CpuidProvider),
+    ]
+}
+
+/// Sources other than the native queries, for `cross_check`ing them
+pub fn alternate_providers() -> Vec<Box<dyn CacheProvider>> {
+    vec![
+        #[cfg(feature = "hwloc")]
+        Box::new(HwlocProvider),
+        #[cfg(all(feature = "cpuid", any(target_arch = "x86", target_arch = "x86_64")))]
+        Box::new(CpuidProvider),
+        #[cfg(target_os = "linux")]
+        Box::new(LscpuProvider),
+    ]
+}
+
+/// Result of the first provider that finds a primary level
+///
+/// When none does, the last provider that didn't fail still wins, so a
+/// partial detection is reported rather than an error.
+pub fn detect_with(providers: &[Box<dyn CacheProvider>]) -> Result<ProcessorInfo, CacheError> {
+    let mut fallback = Err(CacheError::UnsupportedOs(env::consts::OS.to_string()));
+
+    for provider in providers {
+        match provider.collect() {
+            Ok(processor) if processor.primary_level().is_some() => return Ok(processor),
+            Ok(processor) => fallback = Ok(processor),
+            Err(err) if fallback.is_err() => fallback = Err(err),
+            Err(_) => {}
+        }
+    }
+
+    fallback
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::{LevelKey, ProcessorLevel};
+
+    // Hands back a canned result: its L2 size as the only level, no levels
+    // for 0 and an error for None
+    struct MockProvider(Option<usize>);
+
+    impl CacheProvider for MockProvider {
+        fn collect(&self) -> Result<ProcessorInfo, CacheError> {
+            let l2_cache = self
+                .0
+                .ok_or_else(|| CacheError::UnsupportedOs("mock".to_string()))?;
+
+            let mut processor = ProcessorInfo::new();
+            if l2_cache > 0 {
+                let mut level = ProcessorLevel::new(LevelKey::Default);
+                level.l2_cache = l2_cache;
+                processor
+                    .performance_levels
+                    .insert(LevelKey::Default, level);
+            }
+
+            Ok(processor)
+        }
+    }
+
+    #[test]
+    fn picks_the_first_provider_with_caches() {
+        let providers: Vec<Box<dyn CacheProvider>> = vec![
+            Box::new(MockProvider(None)),
+            Box::new(MockProvider(Some(0))),
+            Box::new(MockProvider(Some(1024))),
+            Box::new(MockProvider(Some(2048))),
+        ];
+        assert_eq!(detect_with(&providers).unwrap().l2_cache(), 1024);
+
+        // A partial result beats an error
+        let providers: Vec<Box<dyn CacheProvider>> = vec![
+            Box::new(MockProvider(Some(0))),
+            Box::new(MockProvider(None)),
+        ];
+        assert!(detect_with(&providers).unwrap().primary_level().is_none());
+
+        let providers: Vec<Box<dyn CacheProvider>> = vec![Box::new(MockProvider(None))];
+        assert!(matches!(
+            detect_with(&providers),
+            Err(CacheError::UnsupportedOs(os)) if os == "mock"
+        ));
+        assert!(detect_with(&[]).is_err());
+    }
+}
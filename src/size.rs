@@ -0,0 +1,160 @@
+use alloc::string::{String, ToString};
+use core::fmt;
+use core::str::FromStr;
+
+const KB: usize = 1024;
+const MB: usize = 1024 * KB;
+const GB: usize = 1024 * MB;
+
+/// A cache size in bytes, parsed from strings like "32K", "48 KB" or "4 MiB"
+/// and displayed as "32.00 KB"
+///
+/// Units are always binary (1K = 1024 bytes), which is how every OS we read
+/// from reports cache sizes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CacheSize(pub usize);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseCacheSizeError {
+    input: String,
+}
+
+impl fmt::Display for ParseCacheSizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid cache size: {:?}", self.input)
+    }
+}
+
+impl FromStr for CacheSize {
+    type Err = ParseCacheSizeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseCacheSizeError {
+            input: s.to_string(),
+        };
+
+        let s = s.trim();
+        let split = s
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+
+        let multiplier = match unit.trim().to_ascii_uppercase().as_str() {
+            "" | "B" => 1,
+            "K" | "KB" | "KIB" => KB,
+            "M" | "MB" | "MIB" => MB,
+            "G" | "GB" | "GIB" => GB,
+            _ => return Err(error()),
+        };
+
+        // Keep integers exact, only fall back to floats for "1.5M" style input
+        if let Ok(value) = number.parse::<usize>() {
+            return value
+                .checked_mul(multiplier)
+                .map(CacheSize)
+                .ok_or_else(error);
+        }
+
+        match number.parse::<f64>() {
+            Ok(value) if value.is_finite() && value >= 0.0 => {
+                Ok(CacheSize((value * multiplier as f64) as usize))
+            }
+            _ => Err(error()),
+        }
+    }
+}
+
+// Values that would round up to "1024.00" of a unit are shown in the next
+// unit instead, so 1048575 bytes prints as "1.00 MB" rather than "1024.00 KB"
+impl fmt::Display for CacheSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let size = self.0;
+
+        if size < KB {
+            return write!(f, "{} B", size);
+        }
+
+        for (unit, name) in [(KB, "KB"), (MB, "MB")] {
+            let value = size as f64 / unit as f64;
+            if value < 1023.995 {
+                return write!(f, "{:.2} {}", value, name);
+            }
+        }
+
+        write!(f, "{:.2} GB", size as f64 / GB as f64)
+    }
+}
+
+/// Parses a size such as "32K" into bytes, 0 when it can't be parsed
+///
+/// Use `try_parse_size_with_unit` where a real "0" has to be told apart from
+/// garbage.
+pub fn parse_size_with_unit(size_str: &str) -> usize {
+    try_parse_size_with_unit(size_str).unwrap_or(0)
+}
+
+/// Parses a size such as "32K" into bytes
+pub fn try_parse_size_with_unit(size_str: &str) -> Result<usize, ParseCacheSizeError> {
+    size_str.parse::<CacheSize>().map(|size| size.0)
+}
+
+/// Human-readable size as shown by `CacheSize`, "Not detected" for 0
+pub fn format_size(size: usize) -> String {
+    if size == 0 {
+        return "Not detected".to_string();
+    }
+
+    CacheSize(size).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_zero_apart_from_garbage() {
+        assert_eq!(try_parse_size_with_unit("0"), Ok(0));
+        assert!(try_parse_size_with_unit("").is_err());
+        assert!(try_parse_size_with_unit("abc").is_err());
+        assert_eq!(parse_size_with_unit("abc"), 0);
+    }
+
+    #[test]
+    fn parses_proc_cpuinfo_cache_size() {
+        // "cache size : 8192 KB" in /proc/cpuinfo is always in KB
+        assert_eq!(parse_size_with_unit("8192 KB"), 8388608);
+        assert_eq!(parse_size_with_unit(" 8192 KB\n"), 8388608);
+    }
+
+    #[test]
+    fn parses_sizes_with_units() {
+        assert_eq!("32K".parse(), Ok(CacheSize(32 * KB)));
+        assert_eq!("48 KB".parse(), Ok(CacheSize(48 * KB)));
+        assert_eq!("4 MiB".parse(), Ok(CacheSize(4 * MB)));
+        assert_eq!("1.5M".parse(), Ok(CacheSize(3 * MB / 2)));
+        assert_eq!("2G".parse(), Ok(CacheSize(2 * GB)));
+        assert_eq!("512".parse(), Ok(CacheSize(512)));
+        assert!("".parse::<CacheSize>().is_err());
+        assert!("abc".parse::<CacheSize>().is_err());
+        assert!("32X".parse::<CacheSize>().is_err());
+    }
+
+    #[test]
+    fn displays_human_readable_sizes() {
+        assert_eq!(CacheSize(512).to_string(), "512 B");
+        assert_eq!(CacheSize(32 * KB).to_string(), "32.00 KB");
+        assert_eq!(CacheSize(4 * MB).to_string(), "4.00 MB");
+    }
+
+    #[test]
+    fn formats_sizes_at_unit_boundaries() {
+        assert_eq!(format_size(0), "Not detected");
+        assert_eq!(format_size(1), "1 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1024), "1.00 KB");
+        assert_eq!(format_size(1048575), "1.00 MB");
+        assert_eq!(format_size(1048576), "1.00 MB");
+        assert_eq!(format_size(1073741823), "1.00 GB");
+        assert_eq!(format_size(6 * GB + GB / 2), "6.50 GB");
+    }
+}
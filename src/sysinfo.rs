@@ -0,0 +1,201 @@
+//! Model name, core counts and total memory straight from each OS's API
+//!
+//! Only built with the `sysinfo` feature. It answers what the `sysinfo`
+//! crate would without the dependency, which keeps the crate free of any:
+//! procfs on Linux, sysctlbyname on macOS and Win32 calls on Windows, none of
+//! them running a tool. Detection prefers these answers and leaves the rest
+//! to the per-OS readers.
+
+/// What `system_summary` could tell, None for anything it couldn't
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SystemSummary {
+    pub model_name: Option<String>,
+    pub physical_cores: Option<usize>,
+    /// SMT threads included
+    pub logical_cores: Option<usize>,
+    /// Installed RAM in bytes, as the OS sees it
+    pub total_memory: Option<u64>,
+}
+
+/// The running machine's model name, cores and memory
+pub fn system_summary() -> SystemSummary {
+    #[cfg(target_os = "linux")]
+    return linux_summary();
+    #[cfg(target_os = "macos")]
+    return macos_summary();
+    #[cfg(windows)]
+    return windows_summary();
+
+    // Elsewhere only std knows anything, and its count is the CPUs this
+    // process may use
+    #[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+    SystemSummary {
+        logical_cores: std::thread::available_parallelism().ok().map(usize::from),
+        ..SystemSummary::default()
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn linux_summary() -> SystemSummary {
+    use std::fs;
+
+    use crate::processor::parse_cpuinfo_model_name;
+
+    let cpuinfo = fs::read_to_string("/proc/cpuinfo").unwrap_or_default();
+    let (physical_cores, logical_cores) = parse_cpuinfo_cores(&cpuinfo);
+
+    SystemSummary {
+        model_name: parse_cpuinfo_model_name(&cpuinfo).map(str::to_string),
+        physical_cores,
+        logical_cores: logical_cores
+            .or_else(|| std::thread::available_parallelism().ok().map(usize::from)),
+        total_memory: fs::read_to_string("/proc/meminfo")
+            .ok()
+            .and_then(|meminfo| parse_meminfo_total(&meminfo)),
+    }
+}
+
+// Distinct (physical id, core id) pairs and "processor" entries. Arm
+// kernels give no core ids, so the physical count is None there
+#[cfg(any(target_os = "linux", test))]
+fn parse_cpuinfo_cores(contents: &str) -> (Option<usize>, Option<usize>) {
+    use std::collections::BTreeSet;
+
+    let mut logical = 0;
+    let mut package = None;
+    let mut cores = BTreeSet::new();
+
+    for line in contents.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let number = value.trim().parse::<usize>().ok();
+
+        match key.trim() {
+            "processor" => {
+                logical += 1;
+                package = None;
+            }
+            "physical id" => package = number,
+            "core id" => cores.extend(number.map(|core| (package, core))),
+            _ => {}
+        }
+    }
+
+    (
+        (!cores.is_empty()).then_some(cores.len()),
+        (logical > 0).then_some(logical),
+    )
+}
+
+// "MemTotal:       32768000 kB"
+#[cfg(any(target_os = "linux", test))]
+fn parse_meminfo_total(contents: &str) -> Option<u64> {
+    let kb = contents
+        .lines()
+        .find_map(|line| line.strip_prefix("MemTotal:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+
+    Some(kb * 1024)
+}
+
+#[cfg(target_os = "macos")]
+fn macos_summary() -> SystemSummary {
+    use crate::processor::sysctl_by_name;
+
+    let number = |name| sysctl_by_name(name).ok()?.trim().parse().ok();
+
+    SystemSummary {
+        model_name: sysctl_by_name("machdep.cpu.brand_string")
+            .ok()
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty()),
+        physical_cores: number("hw.physicalcpu").map(|cores: u64| cores as usize),
+        logical_cores: number("hw.logicalcpu").map(|cores: u64| cores as usize),
+        total_memory: number("hw.memsize"),
+    }
+}
+
+#[cfg(windows)]
+fn windows_summary() -> SystemSummary {
+    use crate::processor::{processor_core_counts, registry_cpu_name};
+
+    let cores = processor_core_counts().ok();
+
+    SystemSummary {
+        model_name: registry_cpu_name().ok(),
+        physical_cores: cores.map(|(physical, _)| physical),
+        logical_cores: cores
+            .map(|(_, logical)| logical)
+            .or_else(|| std::thread::available_parallelism().ok().map(usize::from)),
+        total_memory: total_physical_memory(),
+    }
+}
+
+#[cfg(windows)]
+#[repr(C)]
+struct MemoryStatusEx {
+    length: u32,
+    memory_load: u32,
+    total_phys: u64,
+    avail_phys: u64,
+    total_page_file: u64,
+    avail_page_file: u64,
+    total_virtual: u64,
+    avail_virtual: u64,
+    avail_extended_virtual: u64,
+}
+
+#[cfg(windows)]
+#[link(name = "kernel32")]
+extern "system" {
+    fn GlobalMemoryStatusEx(buffer: *mut MemoryStatusEx) -> i32;
+}
+
+#[cfg(windows)]
+fn total_physical_memory() -> Option<u64> {
+    let mut status = MemoryStatusEx {
+        length: std::mem::size_of::<MemoryStatusEx>() as u32,
+        memory_load: 0,
+        total_phys: 0,
+        avail_phys: 0,
+        total_page_file: 0,
+        avail_page_file: 0,
+        total_virtual: 0,
+        avail_virtual: 0,
+        avail_extended_virtual: 0,
+    };
+
+    // SAFETY: `status` is a MEMORYSTATUSEX with its length filled in
+    (unsafe { GlobalMemoryStatusEx(&mut status) } != 0).then_some(status.total_phys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_cores_and_memory_from_procfs() {
+        // Two cores with two threads each, the second listed out of order
+        let cpuinfo = "processor\t: 0\nphysical id\t: 0\ncore id\t\t: 0\n\n\
+                       processor\t: 1\nphysical id\t: 0\ncore id\t\t: 1\n\n\
+                       processor\t: 2\nphysical id\t: 0\ncore id\t\t: 0\n\n\
+                       processor\t: 3\nphysical id\t: 0\ncore id\t\t: 1\n";
+        assert_eq!(parse_cpuinfo_cores(cpuinfo), (Some(2), Some(4)));
+
+        // Arm lists processors without core ids
+        let cpuinfo = "processor\t: 0\nBogoMIPS\t: 48.00\n\nprocessor\t: 1\nBogoMIPS\t: 48.00\n";
+        assert_eq!(parse_cpuinfo_cores(cpuinfo), (None, Some(2)));
+
+        let meminfo = "MemTotal:       32768000 kB\nMemFree:        1024 kB\n";
+        assert_eq!(parse_meminfo_total(meminfo), Some(32768000 * 1024));
+        assert_eq!(parse_meminfo_total("MemFree: 1 kB\n"), None);
+
+        let summary = system_summary();
+        assert!(summary.logical_cores.unwrap_or(1) >= summary.physical_cores.unwrap_or(1));
+    }
+}
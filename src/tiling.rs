@@ -0,0 +1,512 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::cache::ProcessorLevel;
+use crate::size::CacheSize;
+
+/// Register tile of the micro-kernel the block sizes are derived for
+pub const MR: usize = 8;
+pub const NR: usize = 8;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BlockSizes {
+    pub mc: usize,
+    pub kc: usize,
+    pub nc: usize,
+}
+
+impl BlockSizes {
+    /// Blocks never need to be larger than the matrices they tile
+    pub fn fit_to(self, shape: &MatrixShape) -> Self {
+        Self {
+            mc: self.mc.min(shape.m),
+            kc: self.kc.min(shape.k),
+            nc: self.nc.min(shape.n),
+        }
+    }
+}
+
+/// Which cache levels the blocks are sized for, 1 through 3 by default
+///
+/// A `max` of 2 leaves L3 out, so the B panel is sized for L2 as on CPUs
+/// without an L3. A `min` of 2 leaves L1 out: KC is no longer bound by the
+/// B sliver in L1 and the A block in L2 becomes square.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockingLevels {
+    pub min: usize,
+    pub max: usize,
+}
+
+impl Default for BlockingLevels {
+    fn default() -> Self {
+        Self { min: 1, max: 3 }
+    }
+}
+
+impl BlockingLevels {
+    /// None unless 1 <= min <= 2 <= max <= 3, the only ranges the blocking
+    /// can follow: KC and MC always need L2
+    pub fn new(min: usize, max: usize) -> Option<Self> {
+        ((1..=2).contains(&min) && (2..=3).contains(&max)).then_some(Self { min, max })
+    }
+}
+
+/// C (m x n) = A (m x k) * B (k x n)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MatrixShape {
+    pub m: usize,
+    pub n: usize,
+    pub k: usize,
+}
+
+/// How many elements of one type a cache holds
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ElementCapacity {
+    pub elements: usize,
+    /// Side of the largest square tile of them that fits
+    pub square_tile: usize,
+}
+
+impl ElementCapacity {
+    pub fn of(cache_bytes: usize, bytes_per_elem: usize) -> Self {
+        let elements = cache_bytes.checked_div(bytes_per_elem).unwrap_or(0);
+
+        Self {
+            elements,
+            square_tile: elements.isqrt(),
+        }
+    }
+}
+
+/// Bytes of each operand a tile keeps resident
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryFootprint {
+    /// The m x k panel of A
+    pub a: usize,
+    /// The k x n panel of B
+    pub b: usize,
+    /// The m x n tile of C
+    pub c: usize,
+    pub total: usize,
+}
+
+impl ProcessorLevel {
+    /// Bytes an m x n x k tile touches, to hold against this level's caches
+    ///
+    /// Sizes past `usize::MAX` saturate, they outgrow any cache either way.
+    pub fn memory_footprint_for(
+        &self,
+        m: usize,
+        n: usize,
+        k: usize,
+        bytes_per_elem: usize,
+    ) -> MemoryFootprint {
+        let bytes =
+            |rows: usize, cols: usize| rows.saturating_mul(cols).saturating_mul(bytes_per_elem);
+        let (a, b, c) = (bytes(m, k), bytes(k, n), bytes(m, n));
+
+        MemoryFootprint {
+            a,
+            b,
+            c,
+            total: a.saturating_add(b).saturating_add(c),
+        }
+    }
+
+    /// Halving steps a cache-oblivious matmul of `shape` takes before its
+    /// block of C fits in L2, when it can stop recursing
+    ///
+    /// Each step halves the largest of m, n and k, as the recursion splits
+    /// them; 0 when C already fits, or when no L2 was detected.
+    pub fn recursion_depth_for(&self, shape: &MatrixShape, bytes_per_elem: usize) -> usize {
+        self.recursion_for(shape, bytes_per_elem).0
+    }
+
+    // The depth and the block it reaches
+    fn recursion_for(&self, shape: &MatrixShape, bytes_per_elem: usize) -> (usize, MatrixShape) {
+        let mut block = *shape;
+        let mut depth = 0;
+        if self.l2_cache == 0 {
+            return (depth, block);
+        }
+
+        let c_bytes = |block: &MatrixShape| {
+            block
+                .m
+                .saturating_mul(block.n)
+                .saturating_mul(bytes_per_elem)
+        };
+        while c_bytes(&block) > self.l2_cache && (block.m > 1 || block.n > 1) {
+            let largest = block.m.max(block.n).max(block.k);
+            if block.m == largest {
+                block.m = block.m.div_ceil(2);
+            } else if block.n == largest {
+                block.n = block.n.div_ceil(2);
+            } else {
+                block.k = block.k.div_ceil(2);
+            }
+            depth += 1;
+        }
+
+        (depth, block)
+    }
+
+    /// Each detected cache in elements, as
+    /// "L2: 1.00 MB (= 131072 f64 elements = 362x362 tile)"
+    pub fn format_elements(&self, dtype: &str, bytes_per_elem: usize) -> Vec<String> {
+        [
+            ("L1 Data", self.l1_cache.effective_data_size()),
+            ("L2", self.l2_cache),
+            ("L3", self.l3_cache),
+        ]
+        .into_iter()
+        .filter(|&(_, size)| size > 0)
+        .map(|(name, size)| {
+            let capacity = ElementCapacity::of(size, bytes_per_elem);
+            format!(
+                "{}: {} (= {} {} elements = {}x{} tile)",
+                name,
+                CacheSize(size),
+                capacity.elements,
+                dtype,
+                capacity.square_tile,
+                capacity.square_tile
+            )
+        })
+        .collect()
+    }
+
+    /// BLIS-style blocking: a KC x NR sliver of B stays in L1, an MC x KC block
+    /// of A in L2 and a KC x NC panel of B in L3, each using half of its cache
+    ///
+    /// All zero for zero-sized elements, which nothing can be blocked for.
+    pub fn recommended_blocks(&self, bytes_per_elem: usize) -> BlockSizes {
+        self.recommended_blocks_for_levels(bytes_per_elem, BlockingLevels::default())
+    }
+
+    /// Like `recommended_blocks`, sizing blocks only for the caches in
+    /// `levels`
+    pub fn recommended_blocks_for_levels(
+        &self,
+        bytes_per_elem: usize,
+        levels: BlockingLevels,
+    ) -> BlockSizes {
+        if bytes_per_elem == 0 {
+            return BlockSizes::default();
+        }
+
+        let kc = if levels.min <= 1 {
+            let l1_data = self.l1_cache.effective_data_size();
+            round_to_multiple(l1_data / 2 / NR.saturating_mul(bytes_per_elem), 8)
+        } else {
+            round_to_multiple((self.l2_cache / 2 / bytes_per_elem).isqrt(), 8)
+        };
+        let mc = round_to_multiple(self.l2_cache / 2 / kc.saturating_mul(bytes_per_elem), MR);
+
+        // Without an L3 the B panel has to share L2 with the A block
+        let outer_cache = if self.l3_cache > 0 && levels.max >= 3 {
+            self.l3_cache
+        } else {
+            self.l2_cache
+        };
+        let nc = round_to_multiple(outer_cache / 2 / kc.saturating_mul(bytes_per_elem), NR);
+
+        BlockSizes { mc, kc, nc }
+    }
+
+    /// Like `recommended_blocks`, but None when L1 data or L2 is missing so a
+    /// failed detection can't silently turn into nonsense blocks
+    pub fn block_sizes(&self, bytes_per_elem: usize) -> Option<BlockSizes> {
+        self.block_sizes_for_levels(bytes_per_elem, BlockingLevels::default())
+    }
+
+    /// Like `block_sizes`, only needing L1 data when `levels` includes it
+    pub fn block_sizes_for_levels(
+        &self,
+        bytes_per_elem: usize,
+        levels: BlockingLevels,
+    ) -> Option<BlockSizes> {
+        let l1_data = self.l1_cache.effective_data_size();
+
+        if (l1_data == 0 && levels.min <= 1) || self.l2_cache == 0 || bytes_per_elem == 0 {
+            return None;
+        }
+
+        Some(self.recommended_blocks_for_levels(bytes_per_elem, levels))
+    }
+
+    pub fn format_tiling(
+        &self,
+        shape: &MatrixShape,
+        dtype: &str,
+        bytes_per_elem: usize,
+    ) -> Vec<String> {
+        self.format_tiling_for_levels(shape, dtype, bytes_per_elem, BlockingLevels::default())
+    }
+
+    /// `format_tiling` with blocks sized for the caches in `levels`
+    pub fn format_tiling_for_levels(
+        &self,
+        shape: &MatrixShape,
+        dtype: &str,
+        bytes_per_elem: usize,
+        levels: BlockingLevels,
+    ) -> Vec<String> {
+        let mut result = Vec::new();
+
+        let l1_data = self.l1_cache.effective_data_size();
+
+        let title = format!(
+            "{} Tiling ({}, {}x{}x{})",
+            self.level_name, dtype, shape.m, shape.n, shape.k
+        );
+        result.push(format!("\n{}", title));
+        result.push("-".repeat(title.len()));
+
+        let Some(blocks) = self.block_sizes_for_levels(bytes_per_elem, levels) else {
+            result.push("Insufficient cache data for tiling".to_string());
+            return result;
+        };
+        let blocks = blocks.fit_to(shape);
+
+        result.push(format!(
+            "Blocks: MC={}, KC={}, NC={}",
+            blocks.mc, blocks.kc, blocks.nc
+        ));
+        result.push(format!(
+            "KC×NR sliver of B uses {} of L1 Data",
+            format_usage(blocks.kc * NR * bytes_per_elem, l1_data)
+        ));
+        result.push(format!(
+            "KC×MC panel of A uses {} of L2",
+            format_usage(blocks.kc * blocks.mc * bytes_per_elem, self.l2_cache)
+        ));
+
+        if self.l3_cache > 0 && levels.max >= 3 {
+            result.push(format!(
+                "KC×NC panel of B uses {} of L3",
+                format_usage(blocks.kc * blocks.nc * bytes_per_elem, self.l3_cache)
+            ));
+        }
+
+        result
+    }
+
+    /// The `recursion_depth_for` line of the tiling report, None without an
+    /// L2 to fit
+    pub fn format_recursion_depth(
+        &self,
+        shape: &MatrixShape,
+        bytes_per_elem: usize,
+    ) -> Option<String> {
+        if self.l2_cache == 0 {
+            return None;
+        }
+
+        let (depth, block) = self.recursion_for(shape, bytes_per_elem);
+        Some(format!(
+            "Recursion depth: {} halvings take C from {}x{} to {}x{}, which fits L2",
+            depth, shape.m, shape.n, block.m, block.n
+        ))
+    }
+
+    /// f32 and f64 blocks side by side with how much of each cache they
+    /// fill, to weigh precision against how much of the matrix stays cached
+    pub fn format_dtype_comparison(&self, shape: &MatrixShape) -> Vec<String> {
+        let mut result = Vec::new();
+
+        let l1_data = self.l1_cache.effective_data_size();
+
+        let title = format!(
+            "{} f32 vs f64 ({}x{}x{})",
+            self.level_name, shape.m, shape.n, shape.k
+        );
+        result.push(format!("\n{}", title));
+        result.push("-".repeat(title.len()));
+        result.push(format!(
+            "{:<6}{:>6}{:>6}{:>7}{:>6}{:>6}{:>6}",
+            "dtype", "MC", "KC", "NC", "L1", "L2", "L3"
+        ));
+
+        for dtype in ["f32", "f64"] {
+            let bytes_per_elem = dtype_size(dtype).unwrap_or(8);
+            let Some(blocks) = self.block_sizes(bytes_per_elem) else {
+                result.push(format!("{:<6}Insufficient cache data for tiling", dtype));
+                continue;
+            };
+            let blocks = blocks.fit_to(shape);
+
+            let l3_usage = if self.l3_cache > 0 {
+                format_usage(blocks.kc * blocks.nc * bytes_per_elem, self.l3_cache)
+            } else {
+                "-".to_string()
+            };
+
+            result.push(format!(
+                "{:<6}{:>6}{:>6}{:>7}{:>6}{:>6}{:>6}",
+                dtype,
+                blocks.mc,
+                blocks.kc,
+                blocks.nc,
+                format_usage(blocks.kc * NR * bytes_per_elem, l1_data),
+                format_usage(blocks.kc * blocks.mc * bytes_per_elem, self.l2_cache),
+                l3_usage
+            ));
+        }
+
+        result
+    }
+}
+
+/// Round down to a multiple of `multiple`, but never below one multiple
+fn round_to_multiple(value: usize, multiple: usize) -> usize {
+    (value / multiple * multiple).max(multiple)
+}
+
+fn format_usage(bytes: usize, cache_size: usize) -> String {
+    if cache_size == 0 {
+        return "an undetected cache".to_string();
+    }
+
+    format!("{:.0}%", bytes as f64 / cache_size as f64 * 100.0)
+}
+
+pub fn dtype_size(dtype: &str) -> Option<usize> {
+    match dtype {
+        "f32" => Some(4),
+        "f64" => Some(8),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::LevelKey;
+
+    #[test]
+    fn blocks_follow_the_chosen_levels() {
+        let mut level = ProcessorLevel::new(LevelKey::Default);
+        level.l1_cache.data_size = 32 * 1024;
+        level.l2_cache = 1024 * 1024;
+        level.l3_cache = 32 * 1024 * 1024;
+
+        let all = level.recommended_blocks(8);
+        assert_eq!(
+            all,
+            BlockSizes {
+                mc: 256,
+                kc: 256,
+                nc: 8192
+            }
+        );
+
+        // L2 only: the B panel shrinks to L2
+        let l2_only = BlockingLevels::new(1, 2).unwrap();
+        assert_eq!(
+            level.recommended_blocks_for_levels(8, l2_only),
+            BlockSizes {
+                mc: 256,
+                kc: 256,
+                nc: 256
+            }
+        );
+
+        // Without L1, a square A block fills half of L2
+        level.l1_cache.data_size = 0;
+        let no_l1 = BlockingLevels::new(2, 3).unwrap();
+        assert!(level.block_sizes(8).is_none());
+        assert_eq!(
+            level.block_sizes_for_levels(8, no_l1),
+            Some(BlockSizes {
+                mc: 256,
+                kc: 256,
+                nc: 8192
+            })
+        );
+
+        assert_eq!(level.recommended_blocks(0), BlockSizes::default());
+        // Elements too big to fit anywhere get the smallest blocks, not an
+        // overflow
+        assert_eq!(
+            level.recommended_blocks(usize::MAX),
+            BlockSizes {
+                mc: MR,
+                kc: 8,
+                nc: NR
+            }
+        );
+
+        assert_eq!(BlockingLevels::new(3, 3), None);
+        assert_eq!(BlockingLevels::new(1, 1), None);
+        assert_eq!(BlockingLevels::new(1, 3), Some(BlockingLevels::default()));
+    }
+
+    #[test]
+    fn counts_the_elements_each_cache_holds() {
+        let mut level = ProcessorLevel::new(LevelKey::Default);
+        level.l1_cache.data_size = 48 * 1024;
+        level.l2_cache = 1024 * 1024;
+
+        assert_eq!(
+            level.format_elements("f64", 8),
+            [
+                "L1 Data: 48.00 KB (= 6144 f64 elements = 78x78 tile)",
+                "L2: 1.00 MB (= 131072 f64 elements = 362x362 tile)"
+            ]
+        );
+        assert_eq!(
+            ElementCapacity::of(1024 * 1024, 4),
+            ElementCapacity {
+                elements: 262144,
+                square_tile: 512
+            }
+        );
+        assert_eq!(ElementCapacity::of(1024, 0), ElementCapacity::default());
+    }
+
+    #[test]
+    fn footprint_counts_each_operand() {
+        let level = ProcessorLevel::new(LevelKey::Default);
+
+        assert_eq!(
+            level.memory_footprint_for(96, 64, 256, 8),
+            MemoryFootprint {
+                a: 96 * 256 * 8,
+                b: 256 * 64 * 8,
+                c: 96 * 64 * 8,
+                total: 368 * 1024,
+            }
+        );
+        assert_eq!(level.memory_footprint_for(0, 8, 8, 4).total, 256);
+        assert_eq!(
+            level.memory_footprint_for(usize::MAX, 2, 2, 8).total,
+            usize::MAX
+        );
+    }
+
+    #[test]
+    fn halves_until_c_fits_in_l2() {
+        let shape = |m, n, k| MatrixShape { m, n, k };
+        let mut level = ProcessorLevel::new(LevelKey::Default);
+        assert_eq!(level.recursion_depth_for(&shape(4096, 4096, 4096), 8), 0);
+        assert_eq!(level.format_recursion_depth(&shape(64, 64, 64), 8), None);
+
+        level.l2_cache = 256 * 1024;
+        // m, n and k take turns: 4096^3 -> ... -> 256^3 in 12 steps, then
+        // m once more for a 128x256 C of 256 KB
+        assert_eq!(level.recursion_depth_for(&shape(4096, 4096, 4096), 8), 13);
+        assert_eq!(level.recursion_depth_for(&shape(128, 128, 4096), 8), 0);
+        // A tall C only splits m: 1 << 20 rows of 1 -> 1 << 15 rows
+        assert_eq!(level.recursion_depth_for(&shape(1 << 20, 1, 1), 8), 5);
+        assert_eq!(
+            level
+                .format_recursion_depth(&shape(1000, 500, 8), 8)
+                .as_deref(),
+            Some("Recursion depth: 4 halvings take C from 1000x500 to 125x250, which fits L2")
+        );
+        // No overflow on absurd shapes
+        assert!(level.recursion_depth_for(&shape(usize::MAX, usize::MAX, 1), 8) > 0);
+    }
+}
@@ -0,0 +1,100 @@
+//! Detection under a time budget
+//!
+//! A hung sysctl, a slow lscpu or a sysfs scan on a machine with hundreds of
+//! CPUs can hold up a program that only wanted cache sizes at startup.
+//! Detection then runs on a worker thread that reports its progress at the
+//! same steps `install_debug_hook` snapshots.
+
+use std::cell::RefCell;
+use std::io;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::CacheError;
+use crate::processor::{detect, ProcessorInfo};
+
+enum Progress {
+    Checkpoint(ProcessorInfo),
+    Done(Result<ProcessorInfo, CacheError>),
+}
+
+thread_local! {
+    // Only set on the worker threads of `detect_with_timeout`
+    static PROGRESS: RefCell<Option<Sender<Progress>>> = const { RefCell::new(None) };
+}
+
+/// `detect` giving up after `timeout`, with `CacheError::Timeout` holding
+/// whatever was collected by then
+///
+/// Detection can't be interrupted, so on a timeout the worker thread carries
+/// on in the background and its result is dropped.
+pub fn detect_with_timeout(timeout: Duration) -> Result<ProcessorInfo, CacheError> {
+    run_with_timeout(timeout, detect)
+}
+
+fn run_with_timeout(
+    timeout: Duration,
+    job: impl FnOnce() -> Result<ProcessorInfo, CacheError> + Send + 'static,
+) -> Result<ProcessorInfo, CacheError> {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::Builder::new()
+        .name("matmul-utils-detect".to_string())
+        .spawn(move || {
+            PROGRESS.with(|progress| *progress.borrow_mut() = Some(sender.clone()));
+            // Nobody is listening any more after a timeout
+            let _ = sender.send(Progress::Done(job()));
+        })?;
+
+    let deadline = Instant::now() + timeout;
+    let mut partial = ProcessorInfo::new();
+    loop {
+        match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(Progress::Checkpoint(processor)) => partial = processor,
+            Ok(Progress::Done(result)) => return result,
+            Err(RecvTimeoutError::Timeout) => {
+                return Err(CacheError::Timeout(timeout, Box::new(partial)))
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(io::Error::other("detection thread panicked").into())
+            }
+        }
+    }
+}
+
+pub(crate) fn checkpoint(processor: &ProcessorInfo) {
+    PROGRESS.with(|progress| {
+        if let Some(sender) = progress.borrow().as_ref() {
+            let _ = sender.send(Progress::Checkpoint(processor.clone()));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn returns_the_partial_result_on_timeout() {
+        let result = run_with_timeout(Duration::from_millis(50), || {
+            let mut processor = ProcessorInfo::new();
+            processor.model_name = "Slow CPU".to_string();
+            checkpoint(&processor);
+
+            thread::sleep(Duration::from_secs(2));
+            Ok(processor)
+        });
+
+        match result {
+            Err(CacheError::Timeout(timeout, partial)) => {
+                assert_eq!(timeout, Duration::from_millis(50));
+                assert_eq!(partial.model_name, "Slow CPU");
+            }
+            other => panic!("expected a timeout, got {:?}", other),
+        }
+
+        let finished = run_with_timeout(Duration::from_secs(60), || Ok(ProcessorInfo::new()));
+        assert!(finished.is_ok());
+    }
+}
@@ -0,0 +1,22 @@
+#![cfg(feature = "std")]
+
+#[test]
+fn detects_the_host() {
+    let info = matmul_utils::detect().expect("detection should succeed on the host");
+
+    assert!(!info.architecture.is_empty());
+    assert!(info.primary_level().is_some());
+}
+
+#[test]
+fn shares_one_detection_between_threads() {
+    let handles: Vec<_> = (0..4)
+        .map(|_| std::thread::spawn(matmul_utils::ProcessorInfo::shared))
+        .collect();
+    let shared = matmul_utils::ProcessorInfo::shared();
+
+    for handle in handles {
+        assert!(std::sync::Arc::ptr_eq(&handle.join().unwrap(), &shared));
+    }
+    assert!(!shared.architecture.is_empty());
+}
@@ -0,0 +1,183 @@
+//! Detection replayed from sysfs trees, procfs files and tool output
+//! captured on real machines, under `tests/fixtures`
+#![cfg(feature = "std")]
+
+use std::fs;
+use std::path::PathBuf;
+
+use matmul_utils::{
+    CacheProvider, Capture, FieldCheck, LevelKey, ProcessorInfo, ProcessorLevel, Source,
+    SysfsProvider,
+};
+
+fn fixture(path: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(path)
+}
+
+fn read(path: &str) -> String {
+    fs::read_to_string(fixture(path)).unwrap()
+}
+
+fn replay(capture: Capture<'_>) -> ProcessorInfo {
+    let mut processor = ProcessorInfo::new();
+    processor.collect_from_capture(capture).unwrap();
+    processor
+}
+
+fn level(processor: &ProcessorInfo, key: LevelKey) -> &ProcessorLevel {
+    &processor.performance_levels[&key]
+}
+
+#[test]
+fn skylake_sysfs_tree() {
+    let processor = SysfsProvider {
+        root: fixture("sysfs/skylake-4c8t").to_string_lossy().into_owned(),
+    }
+    .collect()
+    .unwrap();
+
+    let level = level(&processor, LevelKey::Default);
+    assert_eq!(level.l1_cache.data_size, 32 * 1024);
+    assert_eq!(level.l1_cache.instruction_size, 32 * 1024);
+    assert_eq!(level.l1_cache.line_size, 64);
+    assert_eq!(level.l2_cache, 256 * 1024);
+    assert_eq!(level.l2_shared(), Some(false));
+    assert_eq!(level.l2_shared_cpus, [0, 4]);
+    assert_eq!(level.l3_cache, 8 * 1024 * 1024);
+    assert_eq!(level.l3_shared_cores, 4);
+    assert_eq!(level.l3_per_core(), Some(2 * 1024 * 1024));
+    assert_eq!(level.l3_source, Some(Source::Sysfs));
+}
+
+#[test]
+fn zen3_procfs() {
+    let mut processor = ProcessorInfo::new();
+    processor.proc_root = Some(fixture("proc/zen3").to_string_lossy().into_owned());
+    processor.detect_architecture();
+
+    assert_eq!(processor.model_name, "AMD Ryzen 7 5800X 8-Core Processor");
+    let signature = processor.x86_signature.unwrap();
+    assert_eq!(
+        (signature.family, signature.model, signature.stepping),
+        (25, 33, 0)
+    );
+    assert_eq!(processor.os_name, "Linux");
+    assert_eq!(processor.kernel_version, "6.5.0-41-generic");
+}
+
+#[test]
+fn zen3_cpuinfo_text() {
+    let processor = replay(Capture::Cpuinfo(&read("proc/zen3/cpuinfo")));
+    assert_eq!(processor.architecture, "x86");
+    assert_eq!(processor.model_name, "AMD Ryzen 7 5800X 8-Core Processor");
+    assert_eq!(processor.x86_signature.unwrap().family, 25);
+
+    // AMD's "cache size" is the L2 of a core
+    let level = level(&processor, LevelKey::Default);
+    assert_eq!(level.l2_cache, 512 * 1024);
+    assert_eq!(level.l3_cache, 0);
+    assert_eq!(level.l2_source, Some(Source::Fallback));
+}
+
+#[test]
+fn apple_m1_sysctl() {
+    let processor = replay(Capture::Sysctl(&read("sysctl/apple-m1.txt")));
+    assert_eq!(processor.model_name, "Apple M1");
+
+    let performance = level(&processor, LevelKey::Performance);
+    assert_eq!(performance.l1_cache.instruction_size, 192 * 1024);
+    assert_eq!(performance.l1_cache.data_size, 128 * 1024);
+    assert_eq!(performance.l2_cache, 12 * 1024 * 1024);
+    assert_eq!(performance.l2_shared_cores, 4);
+    assert_eq!(performance.l2_line_size, 128);
+    // The efficiency cores are numbered first
+    assert_eq!(performance.l2_clusters, vec![vec![4, 5, 6, 7]]);
+    assert!(performance
+        .format()
+        .contains(&"L2 Clusters: CPUs 4-7".to_string()));
+
+    let efficiency = level(&processor, LevelKey::Efficiency(1));
+    assert_eq!(efficiency.l1_cache.data_size, 64 * 1024);
+    assert_eq!(efficiency.l2_cache, 4 * 1024 * 1024);
+    assert_eq!(efficiency.l3_cache, 0);
+    assert_eq!(efficiency.l2_clusters, vec![vec![0, 1, 2, 3]]);
+}
+
+#[test]
+fn intel_mac_sysctl() {
+    let processor = replay(Capture::Sysctl(&read("sysctl/intel-mac-i7-9750h.txt")));
+    assert_eq!(
+        processor.model_name,
+        "Intel(R) Core(TM) i7-9750H CPU @ 2.60GHz"
+    );
+
+    let level = level(&processor, LevelKey::Default);
+    assert_eq!(level.l1_cache.data_size, 32 * 1024);
+    assert_eq!(level.l1_cache.unified_size, 0);
+    assert_eq!(level.l2_cache, 256 * 1024);
+    assert_eq!(level.l3_cache, 12 * 1024 * 1024);
+    assert_eq!(level.l3_line_size, 64);
+    assert_eq!(level.l2_source, Some(Source::Sysctl));
+
+    let checks = processor.self_test().unwrap();
+    assert_eq!(checks.len(), 3);
+    assert!(checks.iter().all(FieldCheck::passed));
+}
+
+#[test]
+fn ryzen_wmic() {
+    let processor = replay(Capture::Wmic(&read("wmic/ryzen-5950x.txt")));
+    assert_eq!(processor.model_name, "AMD Ryzen 9 5950X 16-Core Processor");
+
+    // wmic sums the caches of every core
+    let level = level(&processor, LevelKey::Default);
+    assert_eq!(level.l1_cache.unified_size, 1024 * 1024);
+    assert_eq!(level.l2_cache, 8 * 1024 * 1024);
+    assert_eq!(level.l3_cache, 64 * 1024 * 1024);
+    assert_eq!(level.l2_source, Some(Source::Wmic));
+
+    // Which the self-test, knowing per-core sizes, flags
+    let checks = processor.self_test().unwrap();
+    assert!(!checks.iter().any(FieldCheck::passed));
+}
+
+#[test]
+fn localized_wmic() {
+    // German Windows: lowercase or uppercase keys and grouped digits
+    let processor = replay(Capture::Wmic(&read("wmic/i9-10900k-de-de.txt")));
+    assert_eq!(
+        processor.model_name,
+        "Intel(R) Core(TM) i9-10900K CPU @ 3.70GHz"
+    );
+
+    let level = level(&processor, LevelKey::Default);
+    assert_eq!(level.l1_cache.unified_size, 1024 * 1024);
+    assert_eq!(level.l2_cache, 8 * 1024 * 1024);
+    assert_eq!(level.l3_cache, 32 * 1024 * 1024);
+}
+
+#[test]
+fn sparc_kstat() {
+    let processor = replay(Capture::Kstat(&read("kstat/sparc-t4.txt")));
+
+    let level = level(&processor, LevelKey::Default);
+    assert_eq!(level.l1_cache.data_size, 16 * 1024);
+    assert_eq!(level.l1_cache.instruction_size, 16 * 1024);
+    assert_eq!(level.l2_cache, 128 * 1024);
+    assert_eq!(level.l3_cache, 4 * 1024 * 1024);
+}
+
+#[test]
+fn xeon_lscpu() {
+    let processor = replay(Capture::Lscpu(&read("lscpu/xeon-8375c.txt")));
+
+    // Totals over 8 instances, per core
+    let level = level(&processor, LevelKey::Default);
+    assert_eq!(level.l1_cache.data_size, 48 * 1024);
+    assert_eq!(level.l1_cache.instruction_size, 32 * 1024);
+    assert_eq!(level.l2_cache, 1280 * 1024);
+    assert_eq!(level.l3_cache, 54 * 1024 * 1024);
+    assert_eq!(level.l2_source, Some(Source::Fallback));
+}